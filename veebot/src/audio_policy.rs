@@ -0,0 +1,84 @@
+//! Per-guild audio settings: restrictions on what tracks may be ordered
+//! and how they are played
+
+use crate::{audio_queue::AudioTrackMeta, util::format_duration};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, time};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct AudioPolicy {
+    /// Tracks longer than this are rejected
+    pub(crate) max_duration: Option<time::Duration>,
+    pub(crate) allow_livestreams: bool,
+    /// Livestreams are stopped after playing for this long
+    pub(crate) max_livestream_duration: Option<time::Duration>,
+    /// YouTube channel ids or channel/uploader titles (lowercase)
+    pub(crate) blocked_channels: BTreeSet<String>,
+    /// Keywords that may not appear in the track title (lowercase)
+    pub(crate) blocked_keywords: BTreeSet<String>,
+    /// Apply EBU R128 loudness normalization to every track
+    pub(crate) normalize_loudness: bool,
+    /// How long the end of the track overlaps with the start of the next one
+    pub(crate) crossfade: Option<time::Duration>,
+}
+
+impl Default for AudioPolicy {
+    fn default() -> Self {
+        Self {
+            max_duration: None,
+            allow_livestreams: true,
+            max_livestream_duration: None,
+            blocked_channels: BTreeSet::new(),
+            blocked_keywords: BTreeSet::new(),
+            normalize_loudness: false,
+            crossfade: None,
+        }
+    }
+}
+
+impl AudioPolicy {
+    /// Returns an error describing why the track may not be played.
+    pub(crate) fn check(&self, meta: &AudioTrackMeta) -> crate::Result<()> {
+        if meta.is_livestream() && !self.allow_livestreams {
+            return Err(crate::err!(LivestreamsNotAllowed));
+        }
+
+        if let (Some(max), Some(duration)) = (self.max_duration, meta.duration()) {
+            if duration > max {
+                return Err(crate::err!(TrackTooLong {
+                    duration: format_duration(&duration),
+                    max: format_duration(&max),
+                }));
+            }
+        }
+
+        let (channel_title, channel_id) = match meta {
+            AudioTrackMeta::Yt(video) => (Some(video.channel_title()), Some(video.channel_id())),
+            AudioTrackMeta::Ytdl(track) => (track.uploader(), None),
+            AudioTrackMeta::Local(_) => (None, None),
+        };
+        let channel_title = channel_title.map(str::to_lowercase);
+
+        if let Some(channel) = self.blocked_channels.iter().find(|it| {
+            Some(it.as_str()) == channel_title.as_deref() || Some(it.as_str()) == channel_id
+        }) {
+            return Err(crate::err!(TrackChannelBlocked {
+                channel: channel.clone()
+            }));
+        }
+
+        let title = meta.title().to_lowercase();
+        if let Some(keyword) = self
+            .blocked_keywords
+            .iter()
+            .find(|it| title.contains(it.as_str()))
+        {
+            return Err(crate::err!(TrackKeywordBlocked {
+                keyword: keyword.clone()
+            }));
+        }
+
+        Ok(())
+    }
+}
@@ -1,15 +1,23 @@
 //! Audio tracks queue implementation
 
 use crate::{
-    derpibooru::{rpc::Image, DerpibooruService},
+    audio_policy::AudioPolicy,
+    booru::{Booru, BooruMedia, BooruQuery},
+    chapters::{self, Chapter},
+    derpibooru::DerpibooruService,
+    local_audio::LocalAudioFile,
+    recording::{self, RecordedFile, RecordingBuffer, RecordingMode, VoiceRecorder},
+    store::JsonStore,
     util::format_duration,
     util::CacheExt,
     yt::YtVideo,
+    ytdl::{self, YtdlTrack},
 };
 use futures::{
     channel::{mpsc, oneshot},
     FutureExt, StreamExt,
 };
+use rand::seq::SliceRandom;
 use serenity::{
     async_trait,
     builder::{CreateEmbed, CreateMessage},
@@ -18,7 +26,9 @@ use serenity::{
     model::prelude::CurrentUser,
     model::{
         channel::{ChannelType, Message},
-        id::{ChannelId, GuildId},
+        guild::Guild,
+        id::{ChannelId, GuildId, UserId},
+        voice::VoiceState,
     },
     prelude::{Mutex, RwLock},
     utils::MessageBuilder,
@@ -26,9 +36,19 @@ use serenity::{
     CacheAndHttp,
 };
 use std::{
-    collections::hash_map::Entry, collections::HashMap, collections::VecDeque, sync::Arc, time,
+    collections::hash_map::Entry,
+    collections::HashMap,
+    collections::VecDeque,
+    ffi::{OsStr, OsString},
+    io,
+    ops::RangeInclusive,
+    path::PathBuf,
+    process,
+    sync::{atomic, Arc},
+    time,
 };
 use tracing::{debug, info, warn};
+use url::Url;
 
 /// Inherently atomic
 pub(crate) struct AudioService {
@@ -37,6 +57,8 @@ pub(crate) struct AudioService {
     cache_and_http: Arc<CacheAndHttp>,
     queues: RwLock<HashMap<GuildId, mpsc::UnboundedSender<AudioQueueCmd>>>,
     bot_user: Arc<CurrentUser>,
+    recordings_dir: PathBuf,
+    policies: Arc<JsonStore<HashMap<GuildId, AudioPolicy>>>,
 }
 
 impl AudioService {
@@ -45,6 +67,8 @@ impl AudioService {
         cache_and_http: Arc<CacheAndHttp>,
         derpibooru: Arc<DerpibooruService>,
         bot_user: Arc<CurrentUser>,
+        recordings_dir: PathBuf,
+        policies: JsonStore<HashMap<GuildId, AudioPolicy>>,
     ) -> Self {
         AudioService {
             voice_mgr,
@@ -52,9 +76,40 @@ impl AudioService {
             queues: Default::default(),
             derpibooru,
             bot_user,
+            recordings_dir,
+            policies: Arc::new(policies),
         }
     }
 
+    pub(crate) async fn policy(&self, guild_id: GuildId) -> AudioPolicy {
+        self.policies
+            .read(|it| it.get(&guild_id).cloned().unwrap_or_default())
+            .await
+    }
+
+    /// Applies the given mutation to the guild's policy and returns the updated policy.
+    pub(crate) async fn update_policy(
+        &self,
+        guild_id: GuildId,
+        f: impl FnOnce(&mut AudioPolicy),
+    ) -> crate::Result<AudioPolicy> {
+        self.policies
+            .update(|it| {
+                let policy = it.entry(guild_id).or_default();
+                f(policy);
+                policy.clone()
+            })
+            .await
+    }
+
+    /// Returns the queue for the given guild only if it was already created.
+    pub(crate) async fn queue(
+        &self,
+        guild_id: GuildId,
+    ) -> Option<mpsc::UnboundedSender<AudioQueueCmd>> {
+        self.queues.read().await.get(&guild_id).cloned()
+    }
+
     pub(crate) async fn get_or_create_queue(
         &self,
         guild_id: GuildId,
@@ -71,6 +126,8 @@ impl AudioService {
                     &self.cache_and_http,
                     Arc::clone(&self.derpibooru),
                     Arc::clone(&self.bot_user),
+                    self.recordings_dir.clone(),
+                    Arc::clone(&self.policies),
                 ))
                 .clone(),
         }
@@ -81,13 +138,288 @@ pub(crate) struct ActiveAudioTrack {
     pub(crate) order: AudioTrackOrder,
     pub(crate) source: Arc<Mutex<Audio>>,
     finish_recv: oneshot::Receiver<()>,
+    /// Resolves with the crossfade duration once the track starts fading out.
+    /// `None` if crossfade is disabled or the crossfade has already started.
+    crossfade_recv: Option<oneshot::Receiver<time::Duration>>,
+    /// Position of the livestream at the previous check, used to detect that it has ended
+    last_livestream_position: Option<time::Duration>,
+    /// Position of the track the audio source was started from (non-zero after seeking)
+    start_offset: time::Duration,
 }
 
+impl ActiveAudioTrack {
+    /// Returns the position of the track counting from its very beginning.
+    async fn position(&self) -> time::Duration {
+        self.start_offset + self.source.lock().await.position
+    }
+}
+
+/// EBU R128 loudness normalization filter
+const LOUDNORM_FFMPEG_FILTER: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+
+/// Same ffmpeg output arguments as serenity uses for its own sources
+const SERENITY_FFMPEG_ARGS: &[&str] = &[
+    "-f",
+    "s16le",
+    "-ac",
+    "2",
+    "-ar",
+    "48000",
+    "-acodec",
+    "pcm_s16le",
+    "-",
+];
+
+pub(crate) fn ffmpeg_args(
+    input: &OsStr,
+    normalize_loudness: bool,
+    start: time::Duration,
+) -> Vec<OsString> {
+    let mut args: Vec<OsString> = Vec::new();
+    if start != time::Duration::default() {
+        // `-ss` before the input makes ffmpeg seek the input instead of decoding
+        // and discarding all the audio up to `start`, which takes ages for the
+        // long remote tracks. This is why we don't use `voice::ffmpeg_arged()`,
+        // it puts all the arguments after the input.
+        args.push("-ss".into());
+        args.push(format!("{:.3}", start.as_secs_f64()).into());
+    }
+    args.push("-i".into());
+    args.push(input.to_owned());
+    if normalize_loudness {
+        args.push("-af".into());
+        args.push(LOUDNORM_FFMPEG_FILTER.into());
+    }
+    args.extend(SERENITY_FFMPEG_ARGS.iter().map(OsString::from));
+    args
+}
+
+/// Stdout of the ffmpeg process, the process is killed once the audio is dropped
+struct FfmpegOutput(process::Child);
+
+impl io::Read for FfmpegOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let stdout = self.0.stdout.as_mut();
+        io::Read::read(stdout.expect("BUG: ffmpeg stdout is always piped"), buf)
+    }
+}
+
+impl Drop for FfmpegOutput {
+    fn drop(&mut self) {
+        if let Err(err) = self.0.kill().and_then(|_| self.0.wait()) {
+            warn!(?err, "Failed to kill ffmpeg");
+        }
+    }
+}
+
+fn ffmpeg_source(
+    input: &OsStr,
+    normalize_loudness: bool,
+    start: time::Duration,
+) -> crate::Result<Box<dyn AudioSource>> {
+    let child = process::Command::new("ffmpeg")
+        .args(ffmpeg_args(input, normalize_loudness, start))
+        .stdin(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .map_err(|err| crate::err!(AudioStart(err.into())))?;
+
+    // The output is always stereo, see `SERENITY_FFMPEG_ARGS`
+    Ok(voice::pcm(true, FfmpegOutput(child)))
+}
+
+/// How often the active livestream is checked for being ended or exceeding the play time cap
+const LIVESTREAM_CHECK_INTERVAL: time::Duration = time::Duration::from_secs(30);
+
 pub(crate) struct AudioTrackOrder {
-    pub(crate) meta: YtVideo,
-    pub(crate) ordered_by: Message,
+    pub(crate) meta: AudioTrackMeta,
+    pub(crate) ordered_by: TrackOrderer,
+}
+
+/// Metadata of the audio track, the set of supported sources may grow over time.
+#[derive(Clone)]
+pub(crate) enum AudioTrackMeta {
+    Yt(YtVideo),
+    /// Track from any other site supported by `youtube-dl`
+    Ytdl(YtdlTrack),
+    Local(LocalAudioFile),
 }
 
+impl AudioTrackMeta {
+    pub(crate) fn title(&self) -> &str {
+        match self {
+            AudioTrackMeta::Yt(it) => it.title(),
+            AudioTrackMeta::Ytdl(it) => it.title(),
+            AudioTrackMeta::Local(it) => it.title(),
+        }
+    }
+
+    /// Returns the url of the webpage of the track if there is one.
+    pub(crate) fn url(&self) -> Option<Url> {
+        match self {
+            AudioTrackMeta::Yt(it) => Some(it.url()),
+            AudioTrackMeta::Ytdl(it) => Some(it.url().clone()),
+            AudioTrackMeta::Local(_) => None,
+        }
+    }
+
+    pub(crate) fn thumbnail_url(&self) -> Option<&Url> {
+        match self {
+            AudioTrackMeta::Yt(it) => Some(it.thumbnail_url()),
+            AudioTrackMeta::Ytdl(it) => it.thumbnail_url(),
+            AudioTrackMeta::Local(_) => None,
+        }
+    }
+
+    pub(crate) fn is_livestream(&self) -> bool {
+        match self {
+            AudioTrackMeta::Yt(it) => it.is_livestream(),
+            AudioTrackMeta::Ytdl(it) => it.is_livestream(),
+            AudioTrackMeta::Local(_) => false,
+        }
+    }
+
+    /// Returns `None` if the duration is unknown (e.g. for livestreams).
+    pub(crate) fn duration(&self) -> Option<time::Duration> {
+        match self {
+            AudioTrackMeta::Yt(it) => it.duration(),
+            AudioTrackMeta::Ytdl(it) => it.duration(),
+            AudioTrackMeta::Local(it) => Some(it.duration()),
+        }
+    }
+
+    pub(crate) fn format_duration(&self) -> String {
+        match self {
+            AudioTrackMeta::Yt(it) => it.format_duration().to_string(),
+            AudioTrackMeta::Ytdl(it) => it.format_duration().to_string(),
+            AudioTrackMeta::Local(it) => format_duration(&it.duration()),
+        }
+    }
+
+    /// Returns the chapters of the track (empty if there are none).
+    /// Livestreams are never split into chapters, since we can't seek them.
+    pub(crate) fn chapters(&self) -> Vec<Chapter> {
+        if self.is_livestream() {
+            return Vec::new();
+        }
+        match self {
+            AudioTrackMeta::Yt(it) => it.chapters(),
+            AudioTrackMeta::Ytdl(it) => it.chapters(),
+            AudioTrackMeta::Local(_) => Vec::new(),
+        }
+    }
+
+    /// Returns `true` if both metadata objects point to the same track.
+    pub(crate) fn is_same_track(&self, other: &AudioTrackMeta) -> bool {
+        match (self, other) {
+            (AudioTrackMeta::Yt(a), AudioTrackMeta::Yt(b)) => a.id() == b.id(),
+            (AudioTrackMeta::Ytdl(a), AudioTrackMeta::Ytdl(b)) => a.id() == b.id(),
+            (AudioTrackMeta::Local(a), AudioTrackMeta::Local(b)) => a.path() == b.path(),
+            _ => false,
+        }
+    }
+
+    /// Creates the source that streams the track starting from the given position.
+    async fn create_audio_source(
+        &self,
+        normalize_loudness: bool,
+        start: time::Duration,
+    ) -> crate::Result<Box<dyn AudioSource>> {
+        if !normalize_loudness && start == time::Duration::default() {
+            let source = match self {
+                AudioTrackMeta::Yt(it) => voice::ytdl(it.url().as_str()).await,
+                AudioTrackMeta::Ytdl(it) => voice::ytdl(it.url().as_str()).await,
+                AudioTrackMeta::Local(it) => voice::ffmpeg(it.path()).await,
+            };
+            return source.map_err(|err| crate::err!(AudioStart(err)));
+        }
+
+        match self {
+            AudioTrackMeta::Local(it) => {
+                ffmpeg_source(it.path().as_ref(), normalize_loudness, start)
+            }
+            // `voice::ytdl()` doesn't let us pass any ffmpeg arguments,
+            // so we resolve the direct stream url and give it to ffmpeg ourselves
+            AudioTrackMeta::Yt(_) | AudioTrackMeta::Ytdl(_) => {
+                let url = self.url().expect("BUG: remote tracks always have a url");
+                let stream_url = ytdl::direct_audio_url(&url).await?;
+                ffmpeg_source(stream_url.as_str().as_ref(), normalize_loudness, start)
+            }
+        }
+    }
+}
+
+/// Describes who has put the track into the queue.
+pub(crate) enum TrackOrderer {
+    /// The track was ordered by the author of the given message
+    User(Message),
+    /// The track was put into the queue by the scheduled radio
+    Radio {
+        text_channel: ChannelId,
+        voice_channel: ChannelId,
+    },
+}
+
+impl TrackOrderer {
+    /// Text channel where the track status messages should be sent to
+    pub(crate) fn text_channel(&self) -> ChannelId {
+        match self {
+            TrackOrderer::User(it) => it.channel_id,
+            TrackOrderer::Radio { text_channel, .. } => *text_channel,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            TrackOrderer::User(it) => &it.author.name,
+            TrackOrderer::Radio { .. } => "radio",
+        }
+    }
+
+    pub(crate) fn user_id(&self) -> Option<UserId> {
+        match self {
+            TrackOrderer::User(it) => Some(it.author.id),
+            TrackOrderer::Radio { .. } => None,
+        }
+    }
+
+    pub(crate) fn is_radio(&self) -> bool {
+        matches!(self, TrackOrderer::Radio { .. })
+    }
+}
+
+/// State of the radio that is currently on air
+struct RadioSession {
+    text_channel: ChannelId,
+    voice_channel: ChannelId,
+    tracks: Vec<AudioTrackMeta>,
+    failed: Arc<atomic::AtomicBool>,
+}
+
+struct ActiveRecording {
+    buffer: Arc<std::sync::Mutex<RecordingBuffer>>,
+    started_by: Message,
+    voice_channel: ChannelId,
+}
+
+/// Describes which tracks should be removed from the queue
+pub(crate) enum TrackRemoval {
+    /// Tracks with the indices in the given range (the same indices as in `skip` command)
+    Range(RangeInclusive<usize>),
+    /// Tracks in the queue ordered by the given user
+    OrderedBy(UserId),
+    /// Tracks that are already playing or are in the queue at the lower index
+    Duplicates,
+}
+
+/// Max number of tracks to list in a single message (discord limits the length of the embed).
+const MAX_LISTED_TRACKS: usize = 20;
+
+/// Discord rejects uploads that are bigger than this (for non-boosted servers).
+const MAX_UPLOAD_SIZE: usize = 8 * 1024 * 1024;
+const MAX_FILES_PER_MESSAGE: usize = 10;
+
 struct AudioTrackQueue {
     orders: VecDeque<AudioTrackOrder>,
     active_track: Option<ActiveAudioTrack>,
@@ -97,29 +429,99 @@ struct AudioTrackQueue {
     http: Arc<Http>,
     derpibooru: Arc<DerpibooruService>,
     bot_user: Arc<CurrentUser>,
+    recording: Option<ActiveRecording>,
+    recordings_dir: PathBuf,
+    radio: Option<RadioSession>,
+    /// Voice channel the bot is connected to during the current session
+    voice_channel: Option<ChannelId>,
+    /// `true` if the active track was paused because the bot was disconnected
+    /// from the voice channel (e.g. kicked by a moderator)
+    paused_by_disconnect: bool,
+    policies: Arc<JsonStore<HashMap<GuildId, AudioPolicy>>>,
 }
 
 pub(crate) enum AudioQueueCmd {
     PlayTrack(AudioTrackOrder),
-    SkipTrack { index: usize, source: Message },
-    ShowNowPlaying { source: Message },
-    ShowQueue { source: Message },
-    Pause { source: Message },
-    Resume { source: Message },
-    Clear { source: Message },
+    SkipTrack {
+        index: usize,
+        source: Message,
+    },
+    ShowNowPlaying {
+        source: Message,
+    },
+    ShowQueue {
+        source: Message,
+    },
+    Pause {
+        source: Message,
+    },
+    Resume {
+        source: Message,
+    },
+    Clear {
+        source: Message,
+    },
+    Join {
+        source: Message,
+    },
+    VoiceStateUpdate(VoiceState),
+    StartRecording {
+        mode: RecordingMode,
+        source: Message,
+    },
+    StopRecording {
+        source: Message,
+    },
+    RemoveTracks {
+        removal: TrackRemoval,
+        source: Message,
+    },
+    StartRadio {
+        text_channel: ChannelId,
+        voice_channel: ChannelId,
+        tracks: Vec<AudioTrackMeta>,
+        /// Set by the queue if it turns the radio off because none of its tracks could be played
+        failed: Arc<atomic::AtomicBool>,
+    },
+    StopRadio {
+        text_channel: ChannelId,
+    },
+    /// Replies with the metadata of the track that is playing right now
+    GetActiveTrack(oneshot::Sender<Option<AudioTrackMeta>>),
+    JumpToChapter {
+        target: ChapterTarget,
+        source: Message,
+    },
+}
+
+pub(crate) enum ChapterTarget {
+    /// The chapter after the one that is playing right now
+    Next,
+    /// Chapter number as it is shown to the users (starting from 1)
+    Number(usize),
 }
 
 impl AudioQueueCmd {
-    fn source_msg(&self) -> &Message {
-        match self {
-            AudioQueueCmd::PlayTrack(it) => &it.ordered_by,
-            AudioQueueCmd::SkipTrack { source, .. } => source,
-            AudioQueueCmd::ShowNowPlaying { source, .. } => source,
-            AudioQueueCmd::ShowQueue { source, .. } => source,
-            AudioQueueCmd::Pause { source, .. } => source,
-            AudioQueueCmd::Resume { source, .. } => source,
-            AudioQueueCmd::Clear { source, .. } => source,
-        }
+    /// Text channel where the errors of the command processing should be sent to.
+    /// Returns `None` if the command didn't come from any text channel.
+    fn source_channel(&self) -> Option<ChannelId> {
+        Some(match self {
+            AudioQueueCmd::PlayTrack(it) => it.ordered_by.text_channel(),
+            AudioQueueCmd::SkipTrack { source, .. } => source.channel_id,
+            AudioQueueCmd::ShowNowPlaying { source, .. } => source.channel_id,
+            AudioQueueCmd::ShowQueue { source, .. } => source.channel_id,
+            AudioQueueCmd::Pause { source, .. } => source.channel_id,
+            AudioQueueCmd::Resume { source, .. } => source.channel_id,
+            AudioQueueCmd::Clear { source, .. } => source.channel_id,
+            AudioQueueCmd::Join { source, .. } => source.channel_id,
+            AudioQueueCmd::VoiceStateUpdate(_) | AudioQueueCmd::GetActiveTrack(_) => return None,
+            AudioQueueCmd::StartRecording { source, .. } => source.channel_id,
+            AudioQueueCmd::StopRecording { source, .. } => source.channel_id,
+            AudioQueueCmd::RemoveTracks { source, .. } => source.channel_id,
+            AudioQueueCmd::JumpToChapter { source, .. } => source.channel_id,
+            AudioQueueCmd::StartRadio { text_channel, .. } => *text_channel,
+            AudioQueueCmd::StopRadio { text_channel } => *text_channel,
+        })
     }
 }
 
@@ -141,21 +543,38 @@ impl AudioTrackQueue {
         Ok(())
     }
 
-    async fn out_channel(&self) -> ChannelId {
-        let guild = self.cache.guild_or_err(self.guild_id).await.unwrap();
-        match guild.system_channel_id {
+    async fn out_channel(&self) -> crate::Result<ChannelId> {
+        let guild = self.cache.guild_or_err(self.guild_id).await?;
+        if let Some(it) = guild.system_channel_id {
+            return Ok(it);
+        }
+        guild
+            .channels
+            .iter()
+            .find(|(&id, it)| {
+                it.kind == ChannelType::Text
+                    && guild
+                        .user_permissions_in(id, self.bot_user.id)
+                        .send_messages()
+            })
+            .map(|(&id, _)| id)
+            .ok_or_else(|| crate::err!(NoOutChannel(self.guild_id)))
+    }
+
+    /// Sends the error to the given channel or to the out channel if there is none.
+    async fn report_error(&self, channel: Option<ChannelId>, err: crate::Error) {
+        let channel = match channel {
             Some(it) => it,
-            None => *guild
-                .channels
-                .iter()
-                .find(|(&id, it)| {
-                    it.kind == ChannelType::Text
-                        && guild
-                            .user_permissions_in(id, self.bot_user.id)
-                            .send_messages()
-                })
-                .map(|(id, _)| id)
-                .unwrap_or_else(|| todo!()),
+            None => match self.out_channel().await {
+                Ok(it) => it,
+                Err(out_err) => {
+                    warn!(?err, ?out_err, "Nowhere to report the error to");
+                    return;
+                }
+            },
+        };
+        if let Err(send_err) = self.send_message(channel, |it| err.create_msg(it)).await {
+            warn!(?err, ?send_err, "Failed to report the error");
         }
     }
 
@@ -165,6 +584,8 @@ impl AudioTrackQueue {
         cah: &CacheAndHttp,
         derpibooru: Arc<DerpibooruService>,
         bot_user: Arc<CurrentUser>,
+        recordings_dir: PathBuf,
+        policies: Arc<JsonStore<HashMap<GuildId, AudioPolicy>>>,
     ) -> mpsc::UnboundedSender<AudioQueueCmd> {
         let (cmd_send, cmd_recv) = mpsc::unbounded();
         let cache = Arc::clone(&cah.cache);
@@ -179,6 +600,12 @@ impl AudioTrackQueue {
                 guild_id,
                 cache,
                 http,
+                recording: None,
+                recordings_dir,
+                radio: None,
+                voice_channel: None,
+                paused_by_disconnect: false,
+                policies,
             }
             .run_event_loop(cmd_recv)
             .await;
@@ -187,10 +614,15 @@ impl AudioTrackQueue {
     }
 
     async fn run_event_loop(&mut self, mut cmd_recv: mpsc::UnboundedReceiver<AudioQueueCmd>) {
+        let mut next_livestream_check = tokio::time::Instant::now() + LIVESTREAM_CHECK_INTERVAL;
         loop {
             let cmd = match &mut self.active_track {
                 None => cmd_recv.next().await,
-                Some(ActiveAudioTrack { finish_recv, .. }) => futures::select! {
+                Some(ActiveAudioTrack {
+                    finish_recv,
+                    crossfade_recv,
+                    ..
+                }) => futures::select! {
                     it = cmd_recv.select_next_some() => Some(it),
                     it = finish_recv.fuse() => {
                         if let Ok(()) = it {
@@ -201,6 +633,17 @@ impl AudioTrackQueue {
                         }
                         continue;
                     },
+                    crossfade = Self::crossfade_started(crossfade_recv).fuse() => {
+                        self.start_crossfade(crossfade).await;
+                        continue;
+                    },
+                    _ = tokio::time::delay_until(next_livestream_check).fuse() => {
+                        next_livestream_check = tokio::time::Instant::now() + LIVESTREAM_CHECK_INTERVAL;
+                        if let Err(err) = self.check_active_livestream().await {
+                            self.report_error(None, err).await;
+                        }
+                        continue;
+                    }
                 },
             };
 
@@ -214,11 +657,9 @@ impl AudioTrackQueue {
                 }
             };
 
-            let source_msg_channel_id = cmd.source_msg().channel_id;
+            let source_channel_id = cmd.source_channel();
             if let Err(err) = self.process_command(cmd).await {
-                let _ = self
-                    .send_message(source_msg_channel_id, |it| err.create_msg(it))
-                    .await;
+                self.report_error(source_channel_id, err).await;
             }
         }
     }
@@ -240,11 +681,22 @@ impl AudioTrackQueue {
     }
 
     fn push_track_link(msg: &mut MessageBuilder, order: &AudioTrackOrder) {
-        msg.push("[")
-            .push_mono_safe(order.meta.title())
-            .push("](")
-            .push_safe(order.meta.url())
-            .push(")");
+        match order.meta.url() {
+            Some(url) => msg
+                .push("[")
+                .push_mono_safe(order.meta.title())
+                .push("](")
+                .push_safe(url)
+                .push(")"),
+            None => msg.push_mono_safe(order.meta.title()),
+        };
+    }
+
+    fn orderer_face(&self, orderer: &TrackOrderer) -> String {
+        match orderer {
+            TrackOrderer::User(it) => it.author.face(),
+            TrackOrderer::Radio { .. } => self.bot_user.face(),
+        }
     }
 
     fn build_track_status_msg(order: &AudioTrackOrder) -> MessageBuilder {
@@ -255,13 +707,13 @@ impl AudioTrackQueue {
     }
 
     async fn show_track_finished(&self, order: &AudioTrackOrder) -> crate::Result<()> {
-        self.send_embed(order.ordered_by.channel_id, |it| {
+        self.send_embed(order.ordered_by.text_channel(), |it| {
             let mut msg = Self::build_track_status_msg(order);
             msg.push("has ").push_bold("finished");
 
-            if !order.meta.is_livestream() {
+            if let Some(duration) = order.meta.duration() {
                 msg.push(" (played for: ")
-                    .push_mono(format_duration(&order.meta.duration()))
+                    .push_mono(format_duration(&duration))
                     .push(")");
             }
 
@@ -294,11 +746,11 @@ impl AudioTrackQueue {
 
     fn try_add_random_queue_humnail(
         embed: &mut CreateEmbed,
-        image: crate::Result<Option<Image>>,
+        image: crate::Result<Option<BooruMedia>>,
     ) -> &mut CreateEmbed {
         match image {
             Ok(Some(image)) => {
-                embed.thumbnail(image.representations.thumb);
+                embed.thumbnail(image.thumbnail_url.unwrap_or(image.media_url));
             }
             err => warn!(
                 ?err,
@@ -308,27 +760,40 @@ impl AudioTrackQueue {
         embed
     }
 
-    async fn fetch_random_queue_thumbnail(&self) -> crate::Result<Option<Image>> {
-        let tags = ["solo", "face"];
-        self.derpibooru
-            .fetch_random_media(tags.iter().map(|it| it.parse().unwrap()))
-            .await
+    async fn fetch_random_queue_thumbnail(&self) -> crate::Result<Option<BooruMedia>> {
+        // The queue may be shown in any channel, so the thumbnail must be safe
+        let tags = vec!["solo".parse().unwrap(), "face".parse().unwrap()];
+        let query = BooruQuery::new(tags, false)?;
+        Ok(self
+            .derpibooru
+            .fetch_media(&query)
+            .await?
+            .into_iter()
+            .next())
     }
 
     async fn process_command(&mut self, cmd: AudioQueueCmd) -> crate::Result<()> {
         match cmd {
             AudioQueueCmd::PlayTrack(order) => {
+                if let Some(index) = self.find_track_index(&order.meta) {
+                    return Err(crate::err!(TrackAlreadyQueued { index }));
+                }
+
                 if self.active_track.is_some() {
                     let footer = format!(
                         "ordered by {}, time until playing: {}",
-                        order.ordered_by.author.name,
-                        format_duration(&self.time_until_playing(self.orders.len()).await),
+                        order.ordered_by.name(),
+                        format_wait_time(self.time_until_playing(self.orders.len()).await),
                     );
-                    self.send_embed(order.ordered_by.channel_id, |it| {
+                    let face = self.orderer_face(&order.ordered_by);
+                    self.send_embed(order.ordered_by.text_channel(), |it| {
                         it.title(format_args!("Track pending `#{}`", self.orders.len() + 1))
-                            .description(Self::full_yt_video_link(&order.meta))
-                            .footer(|it| it.text(footer).icon_url(order.ordered_by.author.face()))
-                            .thumbnail(&order.meta.thumbnail_url())
+                            .description(Self::full_track_link(&order.meta))
+                            .footer(|it| it.text(footer).icon_url(face));
+                        if let Some(url) = order.meta.thumbnail_url() {
+                            it.thumbnail(url);
+                        }
+                        it
                     })
                     .await?;
                 }
@@ -356,6 +821,38 @@ impl AudioTrackQueue {
 
                 self.show_track_removed(&source, index, &removed).await?;
             }
+            AudioQueueCmd::GetActiveTrack(reply) => {
+                let meta = self.active_track.as_ref().map(|it| it.order.meta.clone());
+                // The requester may have given up waiting, that's fine
+                let _ = reply.send(meta);
+            }
+            AudioQueueCmd::JumpToChapter { target, source: _ } => {
+                let track = self.active_track_or_err()?;
+                let chapters = track.order.meta.chapters();
+                if chapters.is_empty() {
+                    return Err(crate::err!(NoChapters));
+                }
+
+                let number = match target {
+                    ChapterTarget::Next => {
+                        let position = track.position().await;
+                        chapters::chapter_index_at(&chapters, position).map_or(1, |it| it + 2)
+                    }
+                    ChapterTarget::Number(number) => number,
+                };
+
+                let chapter = number
+                    .checked_sub(1)
+                    .and_then(|index| chapters.get(index))
+                    .ok_or_else(|| {
+                        crate::err!(ChapterIndexOutOfBounds {
+                            index: number,
+                            available: chapters.len(),
+                        })
+                    })?;
+
+                self.seek_active_track(chapter.start).await?;
+            }
             AudioQueueCmd::ShowNowPlaying { source } => {
                 if let Some(track) = &self.active_track {
                     self.show_now_playing_track(track).await?;
@@ -389,9 +886,9 @@ impl AudioTrackQueue {
                 Self::push_track_link(&mut msg, &track.order);
                 msg.push_mono_safe(format_args!(
                     "({} / {}) ordered by {}",
-                    format_duration(&track.source.lock().await.position),
+                    format_duration(&track.position().await),
                     track.order.meta.format_duration(),
-                    track.order.ordered_by.author.name,
+                    track.order.ordered_by.name(),
                 ));
 
                 if !self.orders.is_empty() {
@@ -404,7 +901,7 @@ impl AudioTrackQueue {
                     msg.push_mono_line_safe(format_args!(
                         "({}) ordered by {}",
                         order.meta.format_duration(),
-                        order.ordered_by.author.name,
+                        order.ordered_by.name(),
                     ));
                 }
 
@@ -416,7 +913,7 @@ impl AudioTrackQueue {
                         .footer(|it| {
                             it.text(format_args!(
                                 "Total time left to play: {}",
-                                format_duration(&total_duration)
+                                format_wait_time(total_duration)
                             ))
                         });
                     Self::try_add_random_queue_humnail(it, image)
@@ -450,6 +947,10 @@ impl AudioTrackQueue {
                 }
             }
             AudioQueueCmd::Resume { source } => {
+                if self.voice_channel.is_none() && self.active_track.is_some() {
+                    self.join_user_voice_channel(source.author.id).await?;
+                    self.paused_by_disconnect = false;
+                }
                 let track = self.active_track_or_err()?;
                 let mut audio_source = track.source.lock().await;
                 if audio_source.playing {
@@ -493,13 +994,465 @@ impl AudioTrackQueue {
                     .await?;
                 }
             }
+            AudioQueueCmd::StartRecording { mode, source } => {
+                self.start_recording(mode, source).await?;
+            }
+            AudioQueueCmd::StopRecording { source } => {
+                self.stop_recording(source).await?;
+            }
+            AudioQueueCmd::Join { source } => {
+                let channel = self.join_user_voice_channel(source.author.id).await?;
+
+                if self.paused_by_disconnect {
+                    self.paused_by_disconnect = false;
+                    if let Some(track) = &self.active_track {
+                        track.source.lock().await.play();
+                    }
+                }
+
+                self.send_embed(source.channel_id, |it| {
+                    it.description(
+                        MessageBuilder::new()
+                            .push("Joined ")
+                            .channel(channel)
+                            .push(" as asked by ")
+                            .push_mono_safe(&source.author.name),
+                    )
+                })
+                .await?;
+            }
+            AudioQueueCmd::VoiceStateUpdate(state) => {
+                if state.user_id == self.bot_user.id {
+                    self.on_bot_voice_state_update(state.channel_id).await?;
+                } else {
+                    self.follow_requester(state).await?;
+                }
+            }
+            AudioQueueCmd::RemoveTracks { removal, source } => {
+                self.remove_tracks(removal, &source).await?;
+            }
+            AudioQueueCmd::StartRadio {
+                text_channel,
+                voice_channel,
+                tracks,
+                failed,
+            } => {
+                self.radio = Some(RadioSession {
+                    text_channel,
+                    voice_channel,
+                    tracks,
+                    failed,
+                });
+                self.send_embed(text_channel, |it| {
+                    it.title("📻 Radio is on air").description(
+                        MessageBuilder::new()
+                            .push("The scheduled radio is playing in ")
+                            .channel(voice_channel),
+                    )
+                })
+                .await?;
+
+                self.enqueue_radio_tracks();
+                if self.active_track.is_none() {
+                    self.play_next_track().await;
+                }
+            }
+            AudioQueueCmd::StopRadio { text_channel } => {
+                if self.radio.take().is_none() {
+                    return Ok(());
+                }
+                self.orders.retain(|it| !it.ordered_by.is_radio());
+
+                let is_radio_playing = self
+                    .active_track
+                    .as_ref()
+                    .map(|it| it.order.ordered_by.is_radio())
+                    .unwrap_or(false);
+
+                if is_radio_playing {
+                    self.play_next_track().await;
+                }
+
+                self.send_embed(text_channel, |it| {
+                    it.description("📻 The scheduled radio is off air. See you next time!")
+                })
+                .await?;
+            }
         }
 
         Ok(())
     }
 
-    async fn time_until_playing(&self, order_index: usize) -> time::Duration {
-        let queue_duration: time::Duration = self
+    async fn on_bot_voice_state_update(&mut self, channel: Option<ChannelId>) -> crate::Result<()> {
+        match (self.voice_channel, channel) {
+            // Someone has forcibly disconnected the bot
+            (Some(_), None) => {
+                self.voice_channel = None;
+                if let Some(handler) = self.voice_mgr.lock().await.get_mut(&self.guild_id) {
+                    handler.leave();
+                }
+
+                let track = match &self.active_track {
+                    Some(it) => it,
+                    None => return Ok(()),
+                };
+                track.source.lock().await.pause();
+                self.paused_by_disconnect = true;
+
+                let msg = Self::build_track_status_msg(&track.order)
+                    .push(" was ")
+                    .push_bold("paused")
+                    .push(" because I was disconnected from the voice channel. Use ")
+                    .push_mono("resume")
+                    .push(" or ")
+                    .push_mono("join")
+                    .push(" to bring me back")
+                    .build();
+
+                self.send_embed(track.order.ordered_by.text_channel(), |it| {
+                    it.description(msg)
+                })
+                .await
+            }
+            // Someone has moved the bot to another channel, so we just stay there
+            (Some(old), Some(new)) if old != new => {
+                self.voice_channel = Some(new);
+                self.send_embed(self.out_channel().await?, |it| {
+                    it.description(
+                        MessageBuilder::new()
+                            .push("I was moved to ")
+                            .channel(new)
+                            .push(", the music will continue there"),
+                    )
+                })
+                .await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves the bot to the channel the orderer of the active track has moved to,
+    /// but only if nobody else is listening in the current channel.
+    async fn follow_requester(&mut self, state: VoiceState) -> crate::Result<()> {
+        let session_channel = match self.voice_channel {
+            Some(it) => it,
+            None => return Ok(()),
+        };
+
+        let track = match &self.active_track {
+            Some(it) if it.order.ordered_by.user_id() == Some(state.user_id) => it,
+            _ => return Ok(()),
+        };
+
+        let new_channel = match state.channel_id {
+            Some(it) if it != session_channel => it,
+            _ => return Ok(()),
+        };
+
+        let text_channel = track.order.ordered_by.text_channel();
+        let guild = self.cache.guild_or_err(self.guild_id).await?;
+
+        let has_listeners = guild.voice_states.values().any(|it| {
+            it.channel_id == Some(session_channel)
+                && it.user_id != self.bot_user.id
+                && it.user_id != state.user_id
+        });
+
+        if has_listeners {
+            return Ok(());
+        }
+
+        self.join_voice_channel(&guild, new_channel).await?;
+
+        self.send_embed(text_channel, |it| {
+            it.description(
+                MessageBuilder::new()
+                    .push("Following ")
+                    .mention(&state.user_id)
+                    .push(" to ")
+                    .channel(new_channel),
+            )
+        })
+        .await
+    }
+
+    async fn join_user_voice_channel(&mut self, user: UserId) -> crate::Result<ChannelId> {
+        let guild = self.cache.guild_or_err(self.guild_id).await?;
+
+        let channel_id = guild
+            .voice_states
+            .get(&user)
+            .and_then(|it| it.channel_id)
+            .ok_or_else(|| crate::err!(UserNotInVoiceChanel))?;
+
+        self.join_voice_channel(&guild, channel_id).await?;
+
+        Ok(channel_id)
+    }
+
+    async fn join_voice_channel(
+        &mut self,
+        guild: &Guild,
+        channel_id: ChannelId,
+    ) -> crate::Result<()> {
+        // The channel may have been deleted since the radio was scheduled
+        let channel = guild
+            .channels
+            .get(&channel_id)
+            .ok_or_else(|| crate::err!(JoinVoiceChannel(None)))?;
+
+        self.voice_mgr
+            .lock()
+            .await
+            .join(guild.id, channel_id)
+            .ok_or_else(|| crate::err!(JoinVoiceChannel(Some(channel.name().to_owned()))))?;
+
+        self.voice_channel = Some(channel_id);
+
+        Ok(())
+    }
+
+    /// Returns the indices of the tracks to remove (the same indices as in `skip` command)
+    fn track_indices_to_remove(&self, removal: TrackRemoval) -> crate::Result<Vec<usize>> {
+        Ok(match removal {
+            TrackRemoval::Range(range) => {
+                let available = self.available_track_index_range();
+                for &index in &[*range.start(), *range.end()] {
+                    if !available.as_ref().map_or(false, |it| it.contains(&index)) {
+                        return Err(self.track_index_out_of_bounds_err(index));
+                    }
+                }
+                range.collect()
+            }
+            TrackRemoval::OrderedBy(user) => self
+                .orders
+                .iter()
+                .enumerate()
+                .filter(|(_, it)| it.ordered_by.user_id() == Some(user))
+                .map(|(i, _)| i + 1)
+                .collect(),
+            TrackRemoval::Duplicates => {
+                let mut seen: Vec<_> = self.active_track.iter().map(|it| &it.order.meta).collect();
+                let mut duplicates = Vec::new();
+                for (i, order) in self.orders.iter().enumerate() {
+                    if seen.iter().any(|it| it.is_same_track(&order.meta)) {
+                        duplicates.push(i + 1);
+                    } else {
+                        seen.push(&order.meta);
+                    }
+                }
+                duplicates
+            }
+        })
+    }
+
+    async fn remove_tracks(
+        &mut self,
+        removal: TrackRemoval,
+        source: &Message,
+    ) -> crate::Result<()> {
+        let indices = self.track_indices_to_remove(removal)?;
+
+        if indices.is_empty() {
+            return self
+                .send_embed(source.channel_id, |it| {
+                    it.description("There is nothing to remove from the queue")
+                })
+                .await;
+        }
+
+        let mut msg = MessageBuilder::new();
+        for &index in indices.iter().take(MAX_LISTED_TRACKS) {
+            let order = match index {
+                0 => &self.active_track_or_err()?.order,
+                _ => &self.orders[index - 1],
+            };
+            msg.push_bold(format_args!("{}. ", index));
+            Self::push_track_link(&mut msg, order);
+            msg.push_line("");
+        }
+        if indices.len() > MAX_LISTED_TRACKS {
+            msg.push_italic(format_args!(
+                "...and {} more",
+                indices.len() - MAX_LISTED_TRACKS
+            ));
+        }
+
+        let title = format!("Removed {} track(s) from the queue", indices.len());
+        let footer = format!("removed by {}", source.author.name);
+        self.send_embed(source.channel_id, |it| {
+            it.title(title)
+                .description(msg)
+                .footer(|it| it.text(footer).icon_url(source.author.face()))
+        })
+        .await?;
+
+        // Remove from the back, so that the indices of the remaining tracks stay valid
+        let mut skip_active = false;
+        for &index in indices.iter().rev() {
+            match index {
+                0 => skip_active = true,
+                _ => {
+                    self.orders.remove(index - 1);
+                }
+            }
+        }
+
+        if skip_active {
+            self.play_next_track().await;
+        }
+
+        Ok(())
+    }
+
+    async fn start_recording(&mut self, mode: RecordingMode, source: Message) -> crate::Result<()> {
+        if self.recording.is_some() {
+            return Err(crate::err!(RecordingAlreadyActive));
+        }
+
+        let buffer = RecordingBuffer::new(mode);
+
+        let voice_channel = {
+            let mut voice_mgr = self.voice_mgr.lock().await;
+            let handler = voice_mgr
+                .get_mut(&self.guild_id)
+                .filter(|it| it.channel_id.is_some())
+                .ok_or_else(|| crate::err!(BotNotInVoiceChannel))?;
+
+            handler.listen(Some(Box::new(VoiceRecorder::new(Arc::clone(&buffer)))));
+            handler.channel_id.unwrap()
+        };
+
+        self.send_embed(source.channel_id, |it| {
+            it.title("🔴 Recording is active").description(
+                MessageBuilder::new()
+                    .push("Everything said in ")
+                    .channel(voice_channel)
+                    .push(" is now being ")
+                    .push_bold("recorded")
+                    .push(match mode {
+                        RecordingMode::Mixed => " into a single track",
+                        RecordingMode::PerSpeaker => " into a separate track for each speaker",
+                    })
+                    .push(". Started by ")
+                    .push_mono_safe(&source.author.name),
+            )
+        })
+        .await?;
+
+        self.recording = Some(ActiveRecording {
+            buffer,
+            started_by: source,
+            voice_channel,
+        });
+
+        Ok(())
+    }
+
+    async fn stop_recording(&mut self, source: Message) -> crate::Result<()> {
+        let recording = self
+            .recording
+            .take()
+            .ok_or_else(|| crate::err!(NoActiveRecording))?;
+
+        if let Some(handler) = self.voice_mgr.lock().await.get_mut(&self.guild_id) {
+            handler.listen(None);
+        }
+
+        // The session was kept only for the sake of the recording
+        if self.active_track.is_none() {
+            self.end_session().await;
+        }
+
+        let guild = self.cache.guild_or_err(self.guild_id).await?;
+        let file_stem = format!(
+            "recording-{}",
+            recording.started_by.timestamp.format("%Y-%m-%d_%H-%M-%S")
+        );
+
+        let (wav_files, elapsed, truncated) = {
+            let buffer = recording.buffer.lock().unwrap();
+            let files = buffer.encode_wav_files(&file_stem, |user| {
+                guild
+                    .members
+                    .get(&user)
+                    .map(|it| it.user.name.clone())
+                    .unwrap_or_else(|| user.to_string())
+            });
+            (files, buffer.elapsed(), buffer.is_truncated())
+        };
+
+        let mut files = Vec::with_capacity(wav_files.len());
+        for file in wav_files {
+            files.push(recording::encode_opus(file).await?);
+        }
+
+        let mut msg = MessageBuilder::new();
+        msg.push("Recording of ")
+            .channel(recording.voice_channel)
+            .push(" was ")
+            .push_bold("stopped")
+            .push(" by ")
+            .push_mono_safe(&source.author.name)
+            .push(" (recorded for: ")
+            .push_mono(format_duration(&elapsed))
+            .push(")");
+
+        if truncated {
+            msg.push("\nOnly the first ")
+                .push_mono(format_duration(&recording::MAX_RECORDING_DURATION))
+                .push(" were recorded");
+        }
+
+        if files.is_empty() {
+            msg.push("\nNobody said anything, so there is nothing to save");
+            return self
+                .send_embed(source.channel_id, |it| it.description(msg))
+                .await;
+        }
+
+        let total_size: usize = files.iter().map(|it| it.bytes.len()).sum();
+
+        if total_size <= MAX_UPLOAD_SIZE && files.len() <= MAX_FILES_PER_MESSAGE {
+            let attachments = files
+                .iter()
+                .map(|it| (it.bytes.as_slice(), it.name.as_str()));
+
+            source
+                .channel_id
+                .send_files(&self.http, attachments, |it| {
+                    it.embed(|it| it.description(msg))
+                })
+                .await?;
+
+            return Ok(());
+        }
+
+        self.save_recording_locally(&files).await?;
+
+        msg.push("\nThe recording is too big to upload, so it was saved to ")
+            .push_mono_safe(self.recordings_dir.display());
+
+        self.send_embed(source.channel_id, |it| it.description(msg))
+            .await
+    }
+
+    async fn save_recording_locally(&self, files: &[RecordedFile]) -> crate::Result<()> {
+        tokio::fs::create_dir_all(&self.recordings_dir)
+            .await
+            .map_err(|err| crate::err!(SaveRecording(err)))?;
+
+        for file in files {
+            tokio::fs::write(self.recordings_dir.join(&file.name), &file.bytes)
+                .await
+                .map_err(|err| crate::err!(SaveRecording(err)))?;
+        }
+        Ok(())
+    }
+
+    /// Returns `None` if the duration of some track ahead is unknown (e.g. a livestream).
+    async fn time_until_playing(&self, order_index: usize) -> Option<time::Duration> {
+        let queue_duration: Option<time::Duration> = self
             .orders
             .iter()
             .map(|it| it.meta.duration())
@@ -507,16 +1460,79 @@ impl AudioTrackQueue {
             .sum();
 
         let track = self.active_track.as_ref().unwrap();
-        let current_position = track.source.lock().await.position;
+        let active_duration = track.order.meta.duration()?;
+        let current_position = track.position().await;
 
-        // This condition is required because livestreams have duration 0
-        // and this way we prevent the duration underflow in the further subtraction expression
-        if track.order.meta.is_livestream() {
-            return queue_duration;
-        }
+        // The actual audio may be a bit longer than the duration reported by the API
+        let active_left = active_duration
+            .checked_sub(current_position)
+            .unwrap_or_default();
+
+        Some(active_left + queue_duration?)
+    }
+
+    /// Stops the active livestream if it has ended or has exceeded the play time cap.
+    ///
+    /// The audio source of the ended livestream may not report the end of the
+    /// stream and just hang, so we consider the livestream ended if its position
+    /// didn't advance since the previous check.
+    async fn check_active_livestream(&mut self) -> crate::Result<()> {
+        let max_duration = self
+            .policies
+            .read(|it| {
+                it.get(&self.guild_id)
+                    .and_then(|it| it.max_livestream_duration)
+            })
+            .await;
+
+        let track = match &mut self.active_track {
+            Some(it) if it.order.meta.is_livestream() => it,
+            _ => return Ok(()),
+        };
+
+        let (position, playing) = {
+            let audio = track.source.lock().await;
+            (audio.position, audio.playing)
+        };
+
+        let stalled = playing && track.last_livestream_position == Some(position);
+        track.last_livestream_position = Some(position);
+
+        let reason = if stalled {
+            "has ended"
+        } else if max_duration.map_or(false, |max| position >= max) {
+            "has reached the livestream play time limit on this server"
+        } else {
+            return Ok(());
+        };
+
+        let msg = Self::build_track_status_msg(&track.order)
+            .push(" ")
+            .push_bold(reason)
+            .push(" (played for: ")
+            .push_mono(format_duration(&position))
+            .push(")")
+            .build();
+
+        let text_channel = track.order.ordered_by.text_channel();
+
+        // Announce the end of the stream before the next track is announced
+        let result = self
+            .send_embed(text_channel, |it| it.description(msg))
+            .await;
+
+        self.play_next_track().await;
+
+        result
+    }
 
-        let active_left = track.order.meta.duration() - current_position;
-        active_left + queue_duration
+    /// Returns the index of the given track if it is playing or is already in the queue.
+    fn find_track_index(&self, meta: &AudioTrackMeta) -> Option<usize> {
+        let active = self.active_track.as_ref().map(|it| &it.order);
+        active
+            .into_iter()
+            .chain(&self.orders)
+            .position(|it| it.meta.is_same_track(meta))
     }
 
     fn active_track_or_err(&self) -> crate::Result<&ActiveAudioTrack> {
@@ -525,16 +1541,115 @@ impl AudioTrackQueue {
             .ok_or_else(|| crate::err!(NoActiveTrack))
     }
 
+    /// Puts the radio tracks into the queue in random order (if the radio is on air).
+    fn enqueue_radio_tracks(&mut self) {
+        let radio = match &self.radio {
+            Some(it) => it,
+            None => return,
+        };
+
+        let mut tracks = radio.tracks.clone();
+        tracks.shuffle(&mut rand::thread_rng());
+
+        let (text_channel, voice_channel) = (radio.text_channel, radio.voice_channel);
+
+        self.orders
+            .extend(tracks.into_iter().map(|meta| AudioTrackOrder {
+                meta,
+                ordered_by: TrackOrderer::Radio {
+                    text_channel,
+                    voice_channel,
+                },
+            }));
+    }
+
+    /// Resolves once the active track starts fading out, never resolves if there is no crossfade.
+    async fn crossfade_started(
+        recv: &mut Option<oneshot::Receiver<time::Duration>>,
+    ) -> time::Duration {
+        if let Some(it) = recv {
+            let result = it.await;
+            // The receiver must not be polled after it has resolved
+            *recv = None;
+            if let Ok(duration) = result {
+                return duration;
+            }
+        }
+        futures::future::pending().await
+    }
+
+    /// Starts the next track while the active one is fading out.
+    /// If there is no next track the active one just fades out and finishes as usual.
+    async fn start_crossfade(&mut self, duration: time::Duration) {
+        if self.orders.is_empty() {
+            self.enqueue_radio_tracks();
+        }
+        if self.orders.is_empty() {
+            return;
+        }
+
+        let _ = self
+            .show_track_finished(&self.active_track.as_ref().unwrap().order)
+            .await;
+
+        self.play_next_track_with_fade_in(Some(duration)).await;
+    }
+
     async fn play_next_track(&mut self) {
-        while let Err(err) = self.try_play_next_track().await {
-            self.send_message(self.out_channel().await, |it| err.create_msg(it))
-                .await
-                .unwrap();
+        self.play_next_track_with_fade_in(None).await
+    }
+
+    /// If `fade_in` is specified, the active track is not stopped (it is expected to
+    /// be fading out by itself), and the next track is mixed in gradually.
+    async fn play_next_track_with_fade_in(&mut self, fade_in: Option<time::Duration>) {
+        let mut radio_failures = 0;
+        loop {
+            // The queue is refilled with the radio tracks once it runs out
+            let is_radio_order = self
+                .orders
+                .front()
+                .map_or(self.radio.is_some(), |it| it.ordered_by.is_radio());
+
+            let err = match self.try_play_next_track(fade_in).await {
+                Ok(()) => return,
+                Err(err) => err,
+            };
+
+            self.report_error(None, err).await;
+
+            // Don't spin forever refilling the queue with the radio tracks that can't be played
+            if is_radio_order {
+                radio_failures += 1;
+            }
+            if matches!(&self.radio, Some(radio) if radio_failures >= radio.tracks.len()) {
+                self.turn_off_failed_radio().await;
+            }
         }
     }
 
-    async fn try_play_next_track(&mut self) -> crate::Result<()> {
-        if self.active_track.take().is_some() {
+    async fn turn_off_failed_radio(&mut self) {
+        let radio = match self.radio.take() {
+            Some(it) => it,
+            None => return,
+        };
+
+        warn!("None of the radio tracks could be played, turning the radio off");
+
+        self.orders.retain(|it| !it.ordered_by.is_radio());
+        radio.failed.store(true, atomic::Ordering::SeqCst);
+
+        let _ = self
+            .send_embed(radio.text_channel, |it| {
+                it.description(
+                    "📻 None of the radio tracks could be played, \
+                    so the radio is off air until the next scheduled time",
+                )
+            })
+            .await;
+    }
+
+    async fn try_play_next_track(&mut self, fade_in: Option<time::Duration>) -> crate::Result<()> {
+        if self.active_track.take().is_some() && fade_in.is_none() {
             self.voice_mgr
                 .lock()
                 .await
@@ -543,49 +1658,154 @@ impl AudioTrackQueue {
                 .stop();
         }
 
+        if self.orders.is_empty() {
+            self.enqueue_radio_tracks();
+        }
+
         let order = match self.orders.pop_front() {
             Some(it) => it,
-            None => return Ok(()),
+            None => {
+                self.end_session().await;
+                return Ok(());
+            }
         };
 
-        let guild = self.cache.guild_or_err(self.guild_id).await?;
+        match (&order.ordered_by, self.voice_channel) {
+            // We don't hop between the channels mid-session unless explicitly asked via `join`
+            (TrackOrderer::User(_), Some(_)) => {}
+            (TrackOrderer::User(msg), None) => {
+                self.join_user_voice_channel(msg.author.id).await?;
+            }
+            (TrackOrderer::Radio { voice_channel, .. }, current) => {
+                if current != Some(*voice_channel) {
+                    let guild = self.cache.guild_or_err(self.guild_id).await?;
+                    self.join_voice_channel(&guild, *voice_channel).await?;
+                }
+            }
+        }
+        self.paused_by_disconnect = false;
 
-        let channel_id = guild
-            .voice_states
-            .get(&order.ordered_by.author.id)
-            .and_then(|it| it.channel_id)
-            .ok_or_else(|| crate::err!(UserNotInVoiceChanel))?;
+        self.start_track(order, fade_in, time::Duration::default())
+            .await?;
 
-        let channel = guild
-            .channels
-            .get(&channel_id)
-            .expect("BUG: invalid channel id?");
+        self.show_now_playing_track(self.active_track.as_ref().unwrap())
+            .await?;
 
-        let mut voice_mgr = self.voice_mgr.lock().await;
+        Ok(())
+    }
 
-        let handler = voice_mgr
-            .join(guild.id, channel_id)
-            .ok_or_else(|| crate::err!(JoinVoiceChannel(Some(channel.name().to_owned()))))?;
+    /// Leaves the voice channel once there is nothing left to play,
+    /// so that the next track is played in the channel of whoever orders it.
+    async fn end_session(&mut self) {
+        // The recording needs the bot to stay in the channel
+        if self.recording.is_some() || self.voice_channel.take().is_none() {
+            return;
+        }
+        self.paused_by_disconnect = false;
+        if let Some(handler) = self.voice_mgr.lock().await.get_mut(&self.guild_id) {
+            handler.leave();
+        }
+    }
+
+    /// Restarts the active track from the given position.
+    async fn seek_active_track(&mut self, position: time::Duration) -> crate::Result<()> {
+        let track = self
+            .active_track
+            .take()
+            .ok_or_else(|| crate::err!(NoActiveTrack))?;
 
-        let source = voice::ytdl(order.meta.url().as_str())
+        // This also stops the previous track if it is still fading out
+        self.voice_mgr
+            .lock()
             .await
-            .map_err(|err| crate::err!(AudioStart(err)))?;
+            .get_mut(&self.guild_id)
+            .expect("BUG: the audio queue should have a handler assigned to its guild")
+            .stop();
+
+        if let Err(err) = self.start_track(track.order, None, position).await {
+            // The track can't be resumed, so at least keep the rest of the queue going
+            self.play_next_track().await;
+            return Err(err);
+        }
+
+        self.show_now_playing_track(self.active_track.as_ref().unwrap())
+            .await
+    }
 
-        let (source, finish_recv) = SubscribableAudioSource::new(source);
-        let source = handler.play_only(Box::new(source));
+    /// Starts streaming the track from the given position and makes it the active one.
+    async fn start_track(
+        &mut self,
+        order: AudioTrackOrder,
+        fade_in: Option<time::Duration>,
+        start: time::Duration,
+    ) -> crate::Result<()> {
+        let policy = self
+            .policies
+            .read(|it| it.get(&self.guild_id).cloned().unwrap_or_default())
+            .await;
+
+        let source = order
+            .meta
+            .create_audio_source(policy.normalize_loudness, start)
+            .await?;
+
+        // The position of the source is counted from `start`, not from the beginning of the track
+        let remaining = order.meta.duration().and_then(|it| it.checked_sub(start));
+
+        let fade_out = match (policy.crossfade, remaining) {
+            (Some(crossfade), Some(remaining)) => {
+                // Short tracks shouldn't spend most of their time fading
+                let len = crossfade.min(remaining / 2);
+                Some(FadeOut {
+                    start: remaining - len,
+                    len,
+                })
+            }
+            _ => None,
+        };
+
+        let (source, finish_recv, crossfade_recv) =
+            SubscribableAudioSource::new(source, fade_in, fade_out);
+
+        let source = {
+            let mut voice_mgr = self.voice_mgr.lock().await;
+            let handler = voice_mgr
+                .get_mut(&self.guild_id)
+                .expect("BUG: the audio queue should have a handler assigned to its guild");
+
+            if fade_in.is_some() {
+                // Mix with the previous track that is still fading out
+                handler.play_returning(Box::new(source))
+            } else {
+                handler.play_only(Box::new(source))
+            }
+        };
 
         self.active_track = Some(ActiveAudioTrack {
             order,
             source,
             finish_recv,
+            crossfade_recv,
+            last_livestream_position: None,
+            start_offset: start,
         });
 
-        self.show_now_playing_track(self.active_track.as_ref().unwrap())
-            .await?;
-
         Ok(())
     }
 
+    fn full_track_link(meta: &AudioTrackMeta) -> MessageBuilder {
+        match meta {
+            AudioTrackMeta::Yt(it) => Self::full_yt_video_link(it),
+            AudioTrackMeta::Ytdl(it) => Self::full_ytdl_track_link(it),
+            AudioTrackMeta::Local(it) => {
+                let mut msg = MessageBuilder::new();
+                msg.push("📁 ")
+                    .push_bold_safe(format_args!("\"{}\"", it.title()));
+                msg
+            }
+        }
+    }
+
     fn full_yt_video_link(yt_vid: &YtVideo) -> MessageBuilder {
         let mut msg = MessageBuilder::new();
         msg.push("[")
@@ -600,48 +1820,158 @@ impl AudioTrackQueue {
         msg
     }
 
+    fn full_ytdl_track_link(track: &YtdlTrack) -> MessageBuilder {
+        let mut msg = MessageBuilder::new();
+        match (track.uploader(), track.uploader_url()) {
+            (Some(uploader), Some(url)) => msg
+                .push("[")
+                .push_bold_safe(uploader)
+                .push("](")
+                .push_safe(url)
+                .push(") - "),
+            (Some(uploader), None) => msg.push_bold_safe(uploader).push(" - "),
+            (None, _) => msg.push_bold_safe(track.site()).push(" - "),
+        };
+        msg.push("[")
+            .push_bold_safe(format_args!("\"{}\"", track.title()))
+            .push("](")
+            .push_safe(track.url())
+            .push(")");
+        msg
+    }
+
     async fn show_now_playing_track(&self, track: &ActiveAudioTrack) -> crate::Result<()> {
         let meta = &track.order.meta;
-        let order_msg = &track.order.ordered_by;
+        let orderer = &track.order.ordered_by;
 
+        let position = track.position().await;
         let footer_text = format!(
             "ordered by {} ({} / {})",
             // FIXME: use `.nick_in(guild_id)`
-            track.order.ordered_by.author.name,
-            format_duration(&track.source.lock().await.position),
-            track.order.meta.format_duration(),
+            orderer.name(),
+            format_duration(&position),
+            meta.format_duration(),
         );
-        self.send_embed(order_msg.channel_id, |it| {
-            it.title("Now playing")
-                .description(Self::full_yt_video_link(meta))
-                .thumbnail(&meta.thumbnail_url())
-                .footer(|it| it.text(footer_text).icon_url(order_msg.author.face()))
+
+        let chapters = meta.chapters();
+        let chapter = chapters::chapter_index_at(&chapters, position).map(|index| {
+            let chapter = &chapters[index];
+            MessageBuilder::new()
+                .push_mono(format_args!("#{}/{}", index + 1, chapters.len()))
+                .push(" ")
+                .push_bold_safe(&chapter.title)
+                .push(" (")
+                .push(format_duration(&chapter.start))
+                .push(")")
+                .build()
+        });
+
+        let face = self.orderer_face(orderer);
+        self.send_embed(orderer.text_channel(), |it| {
+            it.title(if meta.is_livestream() {
+                "Now playing 🔴 LIVE"
+            } else {
+                "Now playing"
+            })
+            .description(Self::full_track_link(meta))
+            .footer(|it| it.text(footer_text).icon_url(face));
+            if let Some(chapter) = chapter {
+                it.field("Chapter", chapter, false);
+            }
+            if let Some(url) = meta.thumbnail_url() {
+                it.thumbnail(url);
+            }
+            it
         })
         .await?;
         Ok(())
     }
 }
 
+/// Serenity reads exactly one 20ms frame of audio per `read_pcm_frame()` call
+const PCM_FRAME_DURATION: time::Duration = time::Duration::from_millis(20);
+
+pub(crate) struct FadeOut {
+    /// Position of the track where the fade out starts
+    pub(crate) start: time::Duration,
+    pub(crate) len: time::Duration,
+}
+
 pub(crate) struct SubscribableAudioSource {
     inner: Box<dyn AudioSource>,
     finish_sender: Option<oneshot::Sender<()>>,
+    fade_out_sender: Option<oneshot::Sender<time::Duration>>,
+    fade_in: Option<time::Duration>,
+    fade_out: Option<FadeOut>,
+    /// Duration of the audio that was read from the source so far
+    position: time::Duration,
 }
 
 impl SubscribableAudioSource {
-    pub(crate) fn new(inner: Box<dyn AudioSource>) -> (Self, oneshot::Receiver<()>) {
-        let (sender, receiver) = oneshot::channel();
+    /// Returns the source and the receivers of the finish and the fade out start events.
+    /// The latter is `None` if `fade_out` is `None`.
+    pub(crate) fn new(
+        inner: Box<dyn AudioSource>,
+        fade_in: Option<time::Duration>,
+        fade_out: Option<FadeOut>,
+    ) -> (
+        Self,
+        oneshot::Receiver<()>,
+        Option<oneshot::Receiver<time::Duration>>,
+    ) {
+        let (finish_sender, finish_recv) = oneshot::channel();
+        let (fade_out_sender, fade_out_recv) = match fade_out {
+            Some(_) => {
+                let (sender, receiver) = oneshot::channel();
+                (Some(sender), Some(receiver))
+            }
+            None => (None, None),
+        };
         (
             Self {
                 inner,
-                finish_sender: Some(sender),
+                finish_sender: Some(finish_sender),
+                fade_out_sender,
+                fade_in,
+                fade_out,
+                position: time::Duration::default(),
             },
-            receiver,
+            finish_recv,
+            fade_out_recv,
         )
     }
 
-    pub(crate) fn send_finished_event_or_panic(&mut self) {
-        // Ignore if the receiver was dropped (it means track was cancelled)
-        let _ = self.finish_sender.take().unwrap().send(());
+    /// Returns the volume multiplier for the current position of the track.
+    fn fade_gain(&mut self) -> f32 {
+        let mut gain = 1.0;
+
+        if let Some(fade_in) = self.fade_in {
+            if self.position < fade_in {
+                gain *= self.position.as_secs_f32() / fade_in.as_secs_f32();
+            }
+        }
+
+        if let Some(fade_out) = &self.fade_out {
+            if self.position >= fade_out.start {
+                if let Some(sender) = self.fade_out_sender.take() {
+                    let _ = sender.send(fade_out.len);
+                }
+                let left = (fade_out.start + fade_out.len)
+                    .checked_sub(self.position)
+                    .unwrap_or_default();
+                gain *= left.as_secs_f32() / fade_out.len.as_secs_f32();
+            }
+        }
+
+        gain
+    }
+
+    pub(crate) fn send_finished_event(&mut self) {
+        // The source may be read after it has already finished, so we send the event only once
+        if let Some(sender) = self.finish_sender.take() {
+            // Ignore if the receiver was dropped (it means track was cancelled)
+            let _ = sender.send(());
+        }
     }
 }
 
@@ -658,8 +1988,18 @@ impl AudioSource for SubscribableAudioSource {
     async fn read_pcm_frame(&mut self, buffer: &mut [i16]) -> Option<usize> {
         let n_read = self.inner.read_pcm_frame(buffer).await;
         // debug!(?n_read);
-        if let Some(0) = n_read {
-            self.send_finished_event_or_panic();
+        match n_read {
+            // `None` means the source has failed, this happens e.g. when a livestream ends
+            Some(0) | None => self.send_finished_event(),
+            Some(n_read) => {
+                let gain = self.fade_gain();
+                if gain < 1.0 {
+                    for sample in &mut buffer[..n_read] {
+                        *sample = (f32::from(*sample) * gain) as i16;
+                    }
+                }
+                self.position += PCM_FRAME_DURATION;
+            }
         }
         n_read
     }
@@ -678,3 +2018,10 @@ impl AudioSource for SubscribableAudioSource {
             .await
     }
 }
+
+fn format_wait_time(wait: Option<time::Duration>) -> String {
+    match wait {
+        Some(it) => format_duration(&it),
+        None => "unknown (the duration of some track ahead is unknown)".to_owned(),
+    }
+}
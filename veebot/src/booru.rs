@@ -0,0 +1,647 @@
+//! Common interface of the image boards (boorus), so that the commands don't
+//! depend on the API of any particular one.
+
+use crate::{store::JsonStore, util::ThemeTag};
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    builder::CreateEmbed,
+    model::id::{ChannelId, GuildId, UserId},
+    prelude::Mutex,
+    utils::MessageBuilder,
+};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    iter,
+    sync::Arc,
+};
+use tracing::warn;
+use url::Url;
+
+/// How many of the recently shown media ids are remembered for each channel and booru
+const HISTORY_SIZE: usize = 50;
+
+/// How many times the random media is fetched again if all of it was shown recently
+const MAX_REROLLS: usize = 3;
+
+/// Max number of the existing tags suggested instead of a tag nothing was found with
+const MAX_TAG_SUGGESTIONS: usize = 3;
+
+/// Content rating of the media, the boorus use different names for the same levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BooruRating {
+    Safe,
+    Questionable,
+    Explicit,
+}
+
+impl BooruRating {
+    /// Parses the rating in any of the formats used by the boorus
+    /// (`s`/`q`/`e`, `safe`, `general`, `sensitive`, etc).
+    pub(crate) fn parse(rating: &str) -> Option<Self> {
+        match rating.to_lowercase().as_str() {
+            "s" | "g" | "safe" | "general" => Some(BooruRating::Safe),
+            "q" | "questionable" | "sensitive" | "suggestive" => Some(BooruRating::Questionable),
+            "e" | "explicit" => Some(BooruRating::Explicit),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            BooruRating::Safe => "safe",
+            BooruRating::Questionable => "questionable",
+            BooruRating::Explicit => "explicit",
+        }
+    }
+}
+
+/// Tags that match only the NSFW media on all of the boorus, so there is
+/// no point in searching by them in a SFW channel
+const NSFW_ONLY_TAGS: &[&str] = &[
+    "explicit",
+    "questionable",
+    "suggestive",
+    "nsfw",
+    "nude",
+    "nudity",
+    "sex",
+    "grimdark",
+    "grotesque",
+];
+
+/// Order of the found media
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BooruSort {
+    Random,
+    /// The highest score first
+    Score,
+    /// The most recently uploaded first
+    Newest,
+}
+
+impl BooruSort {
+    fn parse(input: &str) -> Option<Self> {
+        match input.to_lowercase().as_str() {
+            "random" => Some(BooruSort::Random),
+            "score" => Some(BooruSort::Score),
+            "newest" | "new" | "id" => Some(BooruSort::Newest),
+            _ => None,
+        }
+    }
+}
+
+/// Parameters of the media search that are common for all the boorus
+#[derive(Debug, Clone)]
+pub(crate) struct BooruQuery {
+    pub(crate) tags: Vec<ThemeTag>,
+    /// Whether the media with any rating may be returned, otherwise only the safe one is
+    pub(crate) nsfw: bool,
+    pub(crate) sort: BooruSort,
+    /// Max number of the media to return
+    pub(crate) limit: usize,
+    /// Ids of the media that should not be returned. The boorus that can't
+    /// exclude them in the search request may ignore it.
+    pub(crate) excluded_ids: Vec<String>,
+    /// Tags that the media must not have (e.g. the ones from the blocklists)
+    pub(crate) excluded_tags: Vec<ThemeTag>,
+}
+
+impl BooruQuery {
+    /// Creates a query for a single random media.
+    /// The sort order may be overridden with a `sort:<order>` (or `order:<order>`) tag.
+    ///
+    /// Returns an error if the query is not NSFW, but some of the tags match only the NSFW media.
+    pub(crate) fn new(tags: Vec<ThemeTag>, nsfw: bool) -> crate::Result<Self> {
+        if !nsfw {
+            if let Some(tag) = tags.iter().find(|it| is_nsfw_only_tag(it)) {
+                return Err(crate::err!(NsfwTagInSfwChannel(tag.to_string())));
+            }
+        }
+
+        let mut sort = BooruSort::Random;
+        let mut search_tags = Vec::with_capacity(tags.len());
+
+        for tag in tags {
+            let order = tag
+                .as_str()
+                .strip_prefix("sort:")
+                .or_else(|| tag.as_str().strip_prefix("order:"));
+
+            match order {
+                Some(order) => {
+                    sort = BooruSort::parse(order).ok_or_else(|| {
+                        crate::err!(InvalidArgumentValue {
+                            input: tag.to_string(),
+                            expected: "`sort:random`, `sort:score` or `sort:newest`".into(),
+                        })
+                    })?;
+                }
+                None => search_tags.push(tag),
+            }
+        }
+
+        Ok(Self {
+            tags: search_tags,
+            nsfw,
+            sort,
+            limit: 1,
+            excluded_ids: Vec::new(),
+            excluded_tags: Vec::new(),
+        })
+    }
+
+    /// Excludes the media with any of the given tags from the results.
+    /// Returns an error if the query explicitly searches by one of them.
+    pub(crate) fn exclude_tags(
+        &mut self,
+        tags: impl IntoIterator<Item = ThemeTag>,
+    ) -> crate::Result<()> {
+        for tag in tags {
+            if self
+                .tags
+                .iter()
+                .any(|it| it.as_str().eq_ignore_ascii_case(tag.as_str()))
+            {
+                return Err(crate::err!(BooruTagBlocked(tag.to_string())));
+            }
+            self.excluded_tags.push(tag);
+        }
+        Ok(())
+    }
+
+    /// Returns the tags that the boorus should search by, the excluded ones are negated.
+    pub(crate) fn search_tags(&self) -> Vec<ThemeTag> {
+        self.tags
+            .iter()
+            .cloned()
+            .chain(
+                self.excluded_tags
+                    .iter()
+                    .map(|it| format!("-{}", it).parse().unwrap()),
+            )
+            .collect()
+    }
+
+    /// Same as [`BooruQuery::search_tags`], but for the boorus that separate the tags
+    /// with spaces. The blocklist tags may be written with spaces (e.g. `big breasts`),
+    /// so their words are joined with underscores as those boorus expect.
+    pub(crate) fn underscored_search_tags(&self) -> Vec<ThemeTag> {
+        self.search_tags().iter().map(underscored_tag).collect()
+    }
+}
+
+/// Joins the words of the tag with underscores (e.g. `big breasts` -> `big_breasts`)
+pub(crate) fn underscored_tag(tag: &ThemeTag) -> ThemeTag {
+    tag.as_str().split_whitespace().join("_").parse().unwrap()
+}
+
+fn is_nsfw_only_tag(tag: &ThemeTag) -> bool {
+    let tag = tag.as_str().trim().to_lowercase();
+
+    // Negated tags exclude the media, so they are harmless
+    if tag.starts_with('-') {
+        return false;
+    }
+
+    if let Some(rating) = tag.strip_prefix("rating:") {
+        return BooruRating::parse(rating) != Some(BooruRating::Safe);
+    }
+
+    NSFW_ONLY_TAGS.contains(&tag.as_str())
+}
+
+/// Image or video from a booru normalized to the same shape for all of them
+#[derive(Debug, Clone)]
+pub(crate) struct BooruMedia {
+    /// Id of the media on the booru it comes from
+    pub(crate) id: String,
+    /// Page of the media on the booru
+    pub(crate) webpage_url: Url,
+    /// Direct link to the full-size media file
+    pub(crate) media_url: Url,
+    pub(crate) thumbnail_url: Option<Url>,
+    pub(crate) tags: Vec<String>,
+    /// Upvotes minus downvotes
+    pub(crate) score: i64,
+    pub(crate) rating: Option<BooruRating>,
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    /// E.g. `image/png` or `video/webm`
+    pub(crate) mime_type: String,
+}
+
+impl BooruMedia {
+    /// Returns `true` if the media may be embedded as an image (otherwise it's a video).
+    pub(crate) fn is_image(&self) -> bool {
+        self.mime_type.starts_with("image/")
+    }
+}
+
+/// Creates the embed with the media and its metadata, the caller is expected to add
+/// the title and the footer.
+pub(crate) fn media_embed(media: &BooruMedia) -> CreateEmbed {
+    let mut description = MessageBuilder::new();
+    description
+        .push_bold("Score:")
+        .push(" ")
+        .push_italic_line(media.score);
+
+    if let Some(rating) = media.rating {
+        description
+            .push_bold("Rating:")
+            .push(" ")
+            .push_italic_line(rating.as_str());
+    }
+
+    if let Some(created_at) = media.created_at {
+        description
+            .push_bold("Created:")
+            .push(" ")
+            .push_italic_line_safe(
+                timeago::Formatter::new().convert_chrono(created_at, chrono::Utc::now()),
+            );
+    }
+
+    if !media.is_image() {
+        description
+            .push_bold("Video:")
+            .push(" ")
+            .push_line(&media.media_url);
+    }
+
+    description.push_bold_line("Tags:").push_italic_line_safe(
+        MessageBuilder::new().push_codeblock_safe(media.tags.join(", "), None),
+    );
+
+    let mut embed = CreateEmbed::default();
+    embed.description(description).url(&media.webpage_url);
+
+    // Videos can't be embedded, so at least their preview is shown
+    let image = if media.is_image() {
+        Some(&media.media_url)
+    } else {
+        media.thumbnail_url.as_ref()
+    };
+
+    if let Some(image) = image {
+        embed.image(image);
+    }
+
+    embed
+}
+
+/// Optional credentials of the booru account, the anonymous users usually have
+/// stricter rate limits and can't see some of the posts.
+#[derive(Debug, Clone)]
+pub(crate) struct BooruCredentials {
+    /// Account name (or user id for the gelbooru-like boorus)
+    pub(crate) login: String,
+    pub(crate) api_key: String,
+}
+
+impl BooruCredentials {
+    /// Returns `None` unless both the login and the key are set
+    pub(crate) fn from_parts(login: Option<String>, api_key: Option<String>) -> Option<Self> {
+        match (login, api_key) {
+            (Some(login), Some(api_key)) => Some(Self { login, api_key }),
+            _ => None,
+        }
+    }
+}
+
+/// Existing tag found on the booru by the autocomplete or the tag search
+#[derive(Debug, Clone)]
+pub(crate) struct BooruTag {
+    pub(crate) name: String,
+    /// Number of the media with this tag
+    pub(crate) post_count: u64,
+    /// Name of the alias that resolves to this tag (if the tag was found by it)
+    pub(crate) alias: Option<String>,
+}
+
+/// Image board that we can search the media on.
+#[async_trait]
+pub(crate) trait Booru: Send + Sync {
+    /// Short name of the booru used to refer to it in the commands (e.g. `derpibooru`)
+    fn name(&self) -> &'static str;
+
+    /// Domain of the booru (shown to the users)
+    fn site(&self) -> &'static str;
+
+    /// Fetches at most `query.limit` media (images or videos) that have all the tags
+    /// from the query in the `query.sort` order.
+    /// Only the safe media must be returned if the query is not NSFW.
+    async fn fetch_media(&self, query: &BooruQuery) -> crate::Result<Vec<BooruMedia>>;
+
+    /// Returns the existing tags that look like the given one (e.g. that start the same way,
+    /// or that the given tag is an alias of). They don't have to be sorted in any way.
+    async fn find_similar_tags(&self, tag: &str) -> crate::Result<Vec<BooruTag>>;
+}
+
+/// Tags that the guild admins and the users don't want to see (lowercase)
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct BooruBlocklists {
+    guilds: HashMap<GuildId, BTreeSet<String>>,
+    users: HashMap<UserId, BTreeSet<String>>,
+}
+
+/// Whose blocklist to manage
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum BlocklistOwner {
+    Guild(GuildId),
+    User(UserId),
+}
+
+/// All the boorus the commands may search on
+pub(crate) struct BooruRegistry {
+    boorus: Vec<Arc<dyn Booru>>,
+    /// Max number of the media that may be requested with a single command
+    max_gallery_size: usize,
+    /// Ids of the media recently shown in the channel, the newest ones go last
+    history: Mutex<HashMap<(ChannelId, &'static str), VecDeque<String>>>,
+    blocklists: JsonStore<BooruBlocklists>,
+}
+
+impl BooruRegistry {
+    pub(crate) fn new(
+        boorus: Vec<Arc<dyn Booru>>,
+        max_gallery_size: usize,
+        blocklists: JsonStore<BooruBlocklists>,
+    ) -> Self {
+        Self {
+            boorus,
+            max_gallery_size,
+            history: Mutex::new(HashMap::new()),
+            blocklists,
+        }
+    }
+
+    pub(crate) async fn blocklist(&self, owner: BlocklistOwner) -> BTreeSet<String> {
+        self.blocklists
+            .read(|it| {
+                match owner {
+                    BlocklistOwner::Guild(id) => it.guilds.get(&id),
+                    BlocklistOwner::User(id) => it.users.get(&id),
+                }
+                .cloned()
+                .unwrap_or_default()
+            })
+            .await
+    }
+
+    /// Applies the given mutation to the blocklist and returns the updated blocklist.
+    pub(crate) async fn update_blocklist(
+        &self,
+        owner: BlocklistOwner,
+        f: impl FnOnce(&mut BTreeSet<String>),
+    ) -> crate::Result<BTreeSet<String>> {
+        self.blocklists
+            .update(|it| {
+                let blocklist = match owner {
+                    BlocklistOwner::Guild(id) => it.guilds.entry(id).or_default(),
+                    BlocklistOwner::User(id) => it.users.entry(id).or_default(),
+                };
+                f(blocklist);
+                blocklist.clone()
+            })
+            .await
+    }
+
+    /// Returns the tags blocked in the guild. Used where the media is not requested
+    /// by any particular user (e.g. the subscriptions).
+    pub(crate) async fn guild_blocked_tags(&self, guild_id: GuildId) -> Vec<ThemeTag> {
+        self.blocklist(BlocklistOwner::Guild(guild_id))
+            .await
+            .iter()
+            .map(|it| it.parse().unwrap())
+            .collect()
+    }
+
+    /// Returns the tags blocked either in the guild or by the user.
+    pub(crate) async fn blocked_tags(
+        &self,
+        guild_id: Option<GuildId>,
+        user_id: UserId,
+    ) -> Vec<ThemeTag> {
+        self.blocklists
+            .read(|it| {
+                guild_id
+                    .and_then(|id| it.guilds.get(&id))
+                    .into_iter()
+                    .chain(it.users.get(&user_id))
+                    .flatten()
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .map(|it| it.parse().unwrap())
+                    .collect()
+            })
+            .await
+    }
+
+    /// Same as [`Booru::fetch_media`], but the random media that was recently shown
+    /// in the given channel is excluded from the results. If the booru returns only
+    /// such media, it is fetched again a few times before we give up and show the repeats.
+    pub(crate) async fn fetch_unseen_media(
+        &self,
+        booru: &dyn Booru,
+        channel_id: ChannelId,
+        mut query: BooruQuery,
+    ) -> crate::Result<Vec<BooruMedia>> {
+        // Other orders are deterministic, so it is expected to see the same media there
+        if query.sort != BooruSort::Random {
+            return booru.fetch_media(&query).await;
+        }
+
+        let key = (channel_id, booru.name());
+
+        let recent: HashSet<String> = self
+            .history
+            .lock()
+            .await
+            .get(&key)
+            .map(|it| it.iter().cloned().collect())
+            .unwrap_or_default();
+
+        query.excluded_ids = recent.iter().cloned().collect();
+
+        let mut unseen: Vec<BooruMedia> = Vec::new();
+        let mut last_batch = Vec::new();
+
+        for _ in 0..=MAX_REROLLS {
+            let batch = booru.fetch_media(&query).await?;
+            if batch.is_empty() {
+                break;
+            }
+
+            for media in &batch {
+                if !recent.contains(&media.id) && unseen.iter().all(|it| it.id != media.id) {
+                    unseen.push(media.clone());
+                }
+            }
+
+            last_batch = batch;
+
+            if unseen.len() >= query.limit {
+                break;
+            }
+        }
+
+        let mut media = if unseen.is_empty() {
+            last_batch
+        } else {
+            unseen
+        };
+        media.truncate(query.limit);
+
+        let mut history = self.history.lock().await;
+        let shown = history.entry(key).or_default();
+        shown.extend(media.iter().map(|it| it.id.clone()));
+        while shown.len() > HISTORY_SIZE {
+            shown.pop_front();
+        }
+
+        Ok(media)
+    }
+
+    /// Returns the closest existing tags for each of the query tags that don't exist on the booru.
+    /// The failures are only logged, since the suggestions are just a nice-to-have.
+    pub(crate) async fn suggest_tags(
+        &self,
+        booru: &dyn Booru,
+        query: &BooruQuery,
+    ) -> Vec<(ThemeTag, Vec<String>)> {
+        let tags = query
+            .tags
+            .iter()
+            // Negated tags and wildcards may not match anything by design
+            .filter(|it| !it.as_str().starts_with('-') && !it.as_str().contains('*'));
+
+        let suggestions = tags.map(|tag| async move {
+            match booru.find_similar_tags(tag.as_str()).await {
+                Ok(similar) => Some((tag.clone(), rank_tag_suggestions(tag.as_str(), similar))),
+                Err(err) => {
+                    warn!(?err, %tag, booru = booru.name(), "Failed to find similar tags");
+                    None
+                }
+            }
+        });
+
+        futures::future::join_all(suggestions)
+            .await
+            .into_iter()
+            .flatten()
+            .filter(|(_, suggestions)| !suggestions.is_empty())
+            .collect()
+    }
+
+    pub(crate) fn max_gallery_size(&self) -> usize {
+        self.max_gallery_size
+    }
+
+    pub(crate) fn find(&self, name: &str) -> crate::Result<Arc<dyn Booru>> {
+        self.boorus
+            .iter()
+            .find(|it| it.name().eq_ignore_ascii_case(name))
+            .cloned()
+            .ok_or_else(|| {
+                crate::err!(UnknownBooru {
+                    name: name.to_owned(),
+                    available: self.names().collect::<Vec<_>>().join(", "),
+                })
+            })
+    }
+
+    pub(crate) fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.boorus.iter().map(|it| it.name())
+    }
+}
+
+/// Picks the similar tags that are most likely meant instead of the given one.
+/// Returns nothing if the tag exists as is.
+pub(crate) fn rank_tag_suggestions(tag: &str, similar: Vec<BooruTag>) -> Vec<String> {
+    let tag = tag.to_lowercase();
+
+    if similar.iter().any(|it| it.name.to_lowercase() == tag) {
+        return Vec::new();
+    }
+
+    // Allow roughly one typo per 4 letters, but always at least one
+    let max_distance = (tag.chars().count() / 4).max(1);
+
+    similar
+        .into_iter()
+        .filter_map(|it| {
+            let name = it.name.to_lowercase();
+            let distance = it
+                .alias
+                .iter()
+                .map(|alias| edit_distance(&tag, &alias.to_lowercase()))
+                .chain(iter::once(edit_distance(&tag, &name)))
+                .min()
+                .unwrap();
+
+            if distance <= max_distance {
+                return Some((distance, it.post_count, it.name));
+            }
+
+            // The tags that only start like the given one go after the typo fixes,
+            // the most popular first (e.g. `twilight sparkle` for `twilight`)
+            if name.starts_with(&tag) {
+                return Some((max_distance + 1, it.post_count, it.name));
+            }
+
+            None
+        })
+        .sorted_by_key(|&(distance, post_count, _)| (distance, Reverse(post_count)))
+        .map(|(_, _, name)| name)
+        .unique()
+        .take(MAX_TAG_SUGGESTIONS)
+        .collect()
+}
+
+/// Levenshtein distance between the strings (number of the inserted,
+/// removed or replaced chars that is needed to turn one into the other)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev_row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut row = Vec::with_capacity(b.len() + 1);
+        row.push(i + 1);
+        for (j, &b_char) in b.iter().enumerate() {
+            let replace = prev_row[j] + if a_char == b_char { 0 } else { 1 };
+            row.push(replace.min(prev_row[j + 1] + 1).min(row[j] + 1));
+        }
+        prev_row = row;
+    }
+
+    prev_row[b.len()]
+}
+
+/// Guesses the mime type of the media by the extension of the file in the url,
+/// for the boorus that don't tell it explicitly.
+pub(crate) fn mime_type_from_url(url: &Url) -> String {
+    let extension = url
+        .path()
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "webm" => "video/webm",
+        "mp4" => "video/mp4",
+        "swf" => "application/x-shockwave-flash",
+        _ => "application/octet-stream",
+    }
+    .to_owned()
+}
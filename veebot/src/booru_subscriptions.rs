@@ -0,0 +1,319 @@
+//! Posting of the new booru uploads that match the subscribed tags.
+//!
+//! Boorus are polled for the newest media with the tags, and everything with
+//! the id greater than the last seen one is posted (booru ids only grow).
+
+use crate::{
+    booru::{self, Booru, BooruMedia, BooruQuery, BooruRegistry, BooruSort},
+    store::JsonStore,
+    util::ThemeTag,
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, GuildId},
+};
+use std::{collections::HashMap, sync::Arc, time};
+use tracing::{info, warn};
+
+/// How often the boorus are checked for the new uploads
+pub(crate) const POLL_INTERVAL: time::Duration = time::Duration::from_secs(10 * 60);
+
+/// Max number of the newest media fetched per poll. If more was uploaded since
+/// the previous poll, only this number of the newest ones are posted.
+const NEW_MEDIA_BATCH: usize = 10;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct BooruSubscription {
+    /// Name of the booru (e.g. `derpibooru`)
+    pub(crate) booru: String,
+    /// Tags that the new uploads must have (lowercase and sorted)
+    pub(crate) tags: Vec<String>,
+    /// Channel where the new uploads are posted
+    pub(crate) text_channel: ChannelId,
+    /// Id of the newest media that was already posted (or was there before the subscription)
+    last_seen_id: u64,
+}
+
+impl BooruSubscription {
+    fn is_same(&self, other: &BooruSubscription) -> bool {
+        self.booru == other.booru
+            && self.tags == other.tags
+            && self.text_channel == other.text_channel
+    }
+
+    pub(crate) fn format_tags(&self) -> String {
+        format!("[{}]", self.tags.iter().format(", "))
+    }
+}
+
+pub(crate) struct BooruSubscriptionService {
+    subscriptions: JsonStore<HashMap<GuildId, Vec<BooruSubscription>>>,
+    registry: Arc<BooruRegistry>,
+    http: Arc<Http>,
+}
+
+impl BooruSubscriptionService {
+    pub(crate) fn new(
+        subscriptions: JsonStore<HashMap<GuildId, Vec<BooruSubscription>>>,
+        registry: Arc<BooruRegistry>,
+        http: Arc<Http>,
+    ) -> Self {
+        Self {
+            subscriptions,
+            registry,
+            http,
+        }
+    }
+
+    /// Returns the subscriptions of the guild to the given booru in the order they were created.
+    pub(crate) async fn subscriptions(
+        &self,
+        guild_id: GuildId,
+        booru: &str,
+    ) -> Vec<BooruSubscription> {
+        self.subscriptions
+            .read(|it| {
+                it.get(&guild_id)
+                    .into_iter()
+                    .flatten()
+                    .filter(|it| it.booru == booru)
+                    .cloned()
+                    .collect()
+            })
+            .await
+    }
+
+    /// Subscribes the text channel to the new uploads with the tags. The media that is
+    /// already uploaded is not posted, only the one uploaded after the subscription.
+    pub(crate) async fn subscribe(
+        &self,
+        guild_id: GuildId,
+        booru: &str,
+        tags: Vec<ThemeTag>,
+        text_channel: ChannelId,
+    ) -> crate::Result<BooruSubscription> {
+        let booru = self.registry.find(booru)?;
+
+        let tags: Vec<String> = tags
+            .iter()
+            .map(|it| it.as_str().to_lowercase())
+            .sorted()
+            .dedup()
+            .collect();
+
+        let mut subscription = BooruSubscription {
+            booru: booru.name().to_owned(),
+            tags,
+            text_channel,
+            last_seen_id: 0,
+        };
+
+        // This also checks that the tags are fine for the channel
+        let media = self
+            .fetch_newest_media(guild_id, &*booru, &subscription)
+            .await?;
+        subscription.last_seen_id = media.iter().filter_map(media_id).max().unwrap_or(0);
+
+        self.subscriptions
+            .update(|it| {
+                let subscriptions = it.entry(guild_id).or_default();
+                if subscriptions.iter().any(|it| it.is_same(&subscription)) {
+                    return Err(crate::err!(BooruAlreadySubscribed(
+                        subscription.format_tags()
+                    )));
+                }
+                subscriptions.push(subscription.clone());
+                Ok(())
+            })
+            .await??;
+
+        Ok(subscription)
+    }
+
+    /// Removes the subscription by its number (starting from 1) in the list of the
+    /// guild subscriptions to the booru.
+    pub(crate) async fn unsubscribe(
+        &self,
+        guild_id: GuildId,
+        booru: &str,
+        number: usize,
+    ) -> crate::Result<BooruSubscription> {
+        self.subscriptions
+            .update(|it| {
+                let not_found = || crate::err!(BooruSubscriptionNotFound(number));
+                let subscriptions = it.get_mut(&guild_id).ok_or_else(not_found)?;
+                let index = subscriptions
+                    .iter()
+                    .positions(|it| it.booru == booru)
+                    .nth(number.wrapping_sub(1))
+                    .ok_or_else(not_found)?;
+                Ok(subscriptions.remove(index))
+            })
+            .await?
+    }
+
+    /// Runs the infinite loop that checks the boorus for the new uploads.
+    pub(crate) async fn run_poller(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.poll().await;
+        }
+    }
+
+    async fn poll(&self) {
+        let subscriptions: Vec<(GuildId, BooruSubscription)> = self
+            .subscriptions
+            .read(|it| {
+                it.iter()
+                    .flat_map(|(guild_id, it)| it.iter().map(move |it| (*guild_id, it.clone())))
+                    .collect()
+            })
+            .await;
+
+        for (guild_id, subscription) in subscriptions {
+            if let Err(err) = self.post_new_media(guild_id, &subscription).await {
+                warn!(
+                    ?err,
+                    booru = %subscription.booru,
+                    tags = %subscription.format_tags(),
+                    "Failed to check the booru subscription"
+                );
+            }
+        }
+    }
+
+    async fn post_new_media(
+        &self,
+        guild_id: GuildId,
+        subscription: &BooruSubscription,
+    ) -> crate::Result<()> {
+        let booru = self.registry.find(&subscription.booru)?;
+
+        let media = self
+            .fetch_newest_media(guild_id, &*booru, subscription)
+            .await?;
+        let new_media = new_media(media, subscription.last_seen_id);
+
+        let newest_id = match new_media.last().and_then(media_id) {
+            Some(it) => it,
+            None => return Ok(()),
+        };
+
+        // Remember the media as seen right away, so that it is not posted
+        // twice even if sending it fails
+        let still_subscribed = self
+            .subscriptions
+            .update(|it| {
+                let stored = it
+                    .get_mut(&guild_id)
+                    .into_iter()
+                    .flatten()
+                    .find(|it| it.is_same(subscription));
+
+                match stored {
+                    Some(it) => {
+                        it.last_seen_id = it.last_seen_id.max(newest_id);
+                        true
+                    }
+                    None => false,
+                }
+            })
+            .await?;
+
+        if !still_subscribed {
+            return Ok(());
+        }
+
+        info!(
+            booru = %subscription.booru,
+            tags = %subscription.format_tags(),
+            count = new_media.len(),
+            "Posting new booru uploads"
+        );
+
+        for media in &new_media {
+            if let Err(err) = self.post_media(&*booru, subscription, media).await {
+                warn!(
+                    ?err,
+                    text_channel = %subscription.text_channel,
+                    "Failed to post the new booru upload"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The channel may be marked as NSFW (or not) at any moment, so its flag
+    /// is checked every time the media is fetched. The same goes for the guild blocklist.
+    async fn fetch_newest_media(
+        &self,
+        guild_id: GuildId,
+        booru: &dyn Booru,
+        subscription: &BooruSubscription,
+    ) -> crate::Result<Vec<BooruMedia>> {
+        let nsfw = subscription
+            .text_channel
+            .to_channel(&*self.http)
+            .await?
+            .is_nsfw();
+
+        let tags = subscription
+            .tags
+            .iter()
+            .map(|it| it.parse())
+            .collect::<crate::Result<_>>()?;
+
+        let mut query = BooruQuery::new(tags, nsfw)?;
+        query.sort = BooruSort::Newest;
+        query.limit = NEW_MEDIA_BATCH;
+        query.exclude_tags(self.registry.guild_blocked_tags(guild_id).await)?;
+
+        booru.fetch_media(&query).await
+    }
+
+    async fn post_media(
+        &self,
+        booru: &dyn Booru,
+        subscription: &BooruSubscription,
+        media: &BooruMedia,
+    ) -> crate::Result<()> {
+        let mut embed = booru::media_embed(media);
+        embed
+            .title(format_args!(
+                "New {} upload with tags {}",
+                booru.name(),
+                subscription.format_tags()
+            ))
+            .footer(|it| it.text(format_args!("Powered by {}", booru.site())));
+
+        subscription
+            .text_channel
+            .send_message(&self.http, |it| {
+                it.embed(|it| {
+                    *it = embed;
+                    it
+                })
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Returns the media uploaded after the one with the given id in the upload order.
+pub(crate) fn new_media(media: Vec<BooruMedia>, last_seen_id: u64) -> Vec<BooruMedia> {
+    media
+        .into_iter()
+        .filter(|it| media_id(it).map_or(false, |id| id > last_seen_id))
+        .sorted_by_key(media_id)
+        .collect()
+}
+
+/// Ids of the media on the supported boorus are numbers that grow with every upload
+fn media_id(media: &BooruMedia) -> Option<u64> {
+    media.id.parse().ok()
+}
@@ -0,0 +1,88 @@
+//! Chapters of the long tracks (mixes, albums, podcasts) parsed from the
+//! timestamps in their descriptions, similarly to how YouTube does it.
+
+use crate::util;
+use std::time;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Chapter {
+    pub(crate) start: time::Duration,
+    pub(crate) title: String,
+}
+
+/// YouTube doesn't show the chapters if there are fewer of them
+const MIN_CHAPTERS: usize = 2;
+
+/// Parses the lines of the description that start or end with a timestamp, e.g.
+///
+/// ```text
+/// 0:00 Intro
+/// 03:15 - Second track
+/// Third track (1:02:30)
+/// ```
+///
+/// The chapters must start at `0:00` and go in ascending order, otherwise the
+/// timestamps are probably not the chapters (e.g. "my favourite part is at 3:15").
+/// Returns an empty list if the description doesn't contain chapters.
+pub(crate) fn parse_chapters(description: &str, duration: Option<time::Duration>) -> Vec<Chapter> {
+    let line_regex = util::regex!(
+        r#"^\s*[\[(]?(?:(\d{1,2}):)?(\d{1,2}):(\d{2})[\])]?\s*[-–—:|.]?\s*(.*?)\s*$|^\s*(.*?)\s*[-–—:|]?\s*[\[(]?(?:(\d{1,2}):)?(\d{1,2}):(\d{2})[\])]?\s*$"#
+    );
+
+    let mut chapters: Vec<Chapter> = Vec::new();
+
+    for line in description.lines() {
+        let caps = match line_regex.captures(line) {
+            Some(it) => it,
+            None => continue,
+        };
+
+        // The timestamp is either at the start (groups 1-4) or at the end (groups 5-8) of the line
+        let (hours, minutes, seconds, title) = if caps.get(2).is_some() {
+            (caps.get(1), &caps[2], &caps[3], &caps[4])
+        } else {
+            (caps.get(6), &caps[7], &caps[8], &caps[5])
+        };
+
+        let hours: u64 = hours.map_or(0, |it| it.as_str().parse().unwrap());
+        let minutes: u64 = minutes.parse().unwrap();
+        let seconds: u64 = seconds.parse().unwrap();
+        if seconds >= 60 || (hours > 0 && minutes >= 60) {
+            continue;
+        }
+
+        let start = time::Duration::from_secs(hours * 60 * 60 + minutes * 60 + seconds);
+
+        if chapters.is_empty() && start != time::Duration::from_secs(0) {
+            continue;
+        }
+        if matches!(chapters.last(), Some(prev) if prev.start >= start) {
+            continue;
+        }
+        if matches!(duration, Some(duration) if start >= duration) {
+            break;
+        }
+
+        let title = title.trim_matches(|c: char| c.is_whitespace() || "-–—:|.".contains(c));
+
+        chapters.push(Chapter {
+            start,
+            title: if title.is_empty() {
+                format!("Chapter {}", chapters.len() + 1)
+            } else {
+                title.to_owned()
+            },
+        });
+    }
+
+    if chapters.len() < MIN_CHAPTERS {
+        return Vec::new();
+    }
+
+    chapters
+}
+
+/// Returns the index of the chapter that is playing at the given position of the track.
+pub(crate) fn chapter_index_at(chapters: &[Chapter], position: time::Duration) -> Option<usize> {
+    chapters.iter().rposition(|it| it.start <= position)
+}
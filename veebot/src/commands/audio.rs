@@ -1,16 +1,52 @@
 use crate::{
-    audio_queue::{AudioQueueCmd, AudioTrackOrder},
+    audio_policy::AudioPolicy,
+    audio_queue::{
+        AudioQueueCmd, AudioTrackMeta, AudioTrackOrder, ChapterTarget, TrackOrderer, TrackRemoval,
+    },
     di::{self, DiExt},
+    lyrics::{lyrics_query_from_title, Lyrics},
+    music_links::MusicLinkService,
+    pagination,
+    radio::{RadioSchedule, RadioSource},
+    recording::RecordingMode,
+    util::{format_duration, CacheExt},
+    yt::{self, YtService},
+    ytdl::YtdlTrack,
 };
-use futures::channel::mpsc;
+use chrono::NaiveTime;
+use futures::channel::{mpsc, oneshot};
+use itertools::Itertools;
 use serenity::{
-    client::Context, framework::standard::macros::group, framework::standard::Args,
-    model::channel::Message,
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::macros::group,
+    framework::standard::Args,
+    model::channel::{ChannelType, Message},
+    model::id::{ChannelId, GuildId, UserId},
+    utils::{self, MessageBuilder},
 };
+use std::{ops::RangeInclusive, time};
+use url::Url;
 use veebot_cmd::veebot_cmd;
 
 #[group]
-#[commands(play, skip, now_playing, queue, pause, resume, clear)]
+#[commands(
+    play,
+    skip,
+    now_playing,
+    queue,
+    pause,
+    resume,
+    clear,
+    join,
+    remove,
+    dedupe,
+    record,
+    radio,
+    audio_policy,
+    lyrics,
+    chapter
+)]
 pub(crate) struct Audio;
 
 #[veebot_cmd]
@@ -18,17 +54,35 @@ pub(crate) struct Audio;
 async fn play(ctx: &Context, msg: &Message, mut args: Args) -> crate::Result<()> {
     let task_send = get_or_create_audio_track_queue(ctx, msg).await?;
 
-    let yt_vid = {
+    let meta = {
         let yt = ctx.data.expect_dep::<di::YtServiceToken>().await;
         match args.single::<url::Url>() {
-            Ok(it) => yt.find_video_by_url(&it).await?,
-            Err(_) => yt.find_video_by_query(args.remains().unwrap_or("")).await?,
+            Ok(it) if MusicLinkService::is_music_link(&it) => {
+                return play_music_link(ctx, msg, &it, &task_send).await;
+            }
+            Ok(it) if YtService::is_video_url(&it) => {
+                let video = yt.find_video_by_url(&it).await?;
+                yt.check_playable(&video)?;
+                AudioTrackMeta::Yt(video)
+            }
+            // Let youtube-dl try to extract the track from any other site
+            Ok(it) => AudioTrackMeta::Ytdl(YtdlTrack::extract(&it).await?),
+            Err(_) => {
+                AudioTrackMeta::Yt(yt.find_video_by_query(args.remains().unwrap_or("")).await?)
+            }
         }
     };
 
+    ctx.data
+        .expect_dep::<di::AudioServiceToken>()
+        .await
+        .policy(guild_id(msg)?)
+        .await
+        .check(&meta)?;
+
     let order = AudioTrackOrder {
-        meta: yt_vid,
-        ordered_by: msg.clone(),
+        meta,
+        ordered_by: TrackOrderer::User(msg.clone()),
     };
 
     task_send
@@ -37,6 +91,105 @@ async fn play(ctx: &Context, msg: &Message, mut args: Args) -> crate::Result<()>
     Ok(())
 }
 
+/// Number of YouTube search results we pick the best match for a music track from
+const MUSIC_TRACK_SEARCH_RESULTS: u8 = 5;
+
+/// Discord limits the embed description to 2048 characters
+const MAX_MUSIC_LINK_REPORT_LEN: usize = 2000;
+
+/// Finds the best YouTube matches for the tracks from the given Spotify or Apple Music
+/// link and orders them. The tracks that were not found or were rejected by the audio
+/// policy are skipped, all of that is reported in a single message.
+async fn play_music_link(
+    ctx: &Context,
+    msg: &Message,
+    url: &Url,
+    task_send: &mpsc::UnboundedSender<AudioQueueCmd>,
+) -> crate::Result<()> {
+    let music_links = ctx.data.expect_dep::<di::MusicLinkServiceToken>().await;
+    let yt = ctx.data.expect_dep::<di::YtServiceToken>().await;
+    let policy = ctx
+        .data
+        .expect_dep::<di::AudioServiceToken>()
+        .await
+        .policy(guild_id(msg)?)
+        .await;
+
+    let tracks = music_links.resolve(url).await?;
+    if tracks.is_empty() {
+        return Err(crate::err!(NoTracksInMusicLink(url.clone())));
+    }
+
+    let mut report = MessageBuilder::new();
+    let mut orders = Vec::with_capacity(tracks.len());
+
+    for track in &tracks {
+        let query = track.search_query();
+        let mut line = MessageBuilder::new();
+        line.push("• ").push_safe(&query).push(" → ");
+
+        let best_match = yt
+            .find_videos_by_query(&query, MUSIC_TRACK_SEARCH_RESULTS)
+            .await?
+            .into_iter()
+            .filter(|video| yt.unplayable_reason(video).is_none())
+            .map(|video| (track.match_confidence(&video), video))
+            .max_by(|(lhs, _), (rhs, _)| lhs.partial_cmp(rhs).unwrap());
+
+        match best_match {
+            None => {
+                line.push_line("not found on YouTube");
+            }
+            Some((confidence, video)) => {
+                line.push("[")
+                    .push_mono_safe(video.title())
+                    .push("](")
+                    .push_safe(video.url())
+                    .push(") ");
+
+                let meta = AudioTrackMeta::Yt(video);
+
+                match policy.check(&meta) {
+                    Err(err) => line.push_line_safe(format_args!("rejected: {}", err)),
+                    Ok(()) => {
+                        orders.push(AudioTrackOrder {
+                            meta,
+                            ordered_by: TrackOrderer::User(msg.clone()),
+                        });
+                        line.push_line(format_args!("({:.0}% match)", confidence * 100.0))
+                    }
+                };
+            }
+        }
+
+        // Embed description is limited, so the rest of the tracks are ordered silently
+        if report.0.len() + line.0.len() < MAX_MUSIC_LINK_REPORT_LEN {
+            report.push(line);
+        }
+    }
+
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| {
+                it.title(format_args!(
+                    "Found {} of {} tracks on YouTube",
+                    orders.len(),
+                    tracks.len()
+                ))
+                .description(report)
+            })
+        })
+        .await?;
+
+    for order in orders {
+        task_send
+            .unbounded_send(AudioQueueCmd::PlayTrack(order))
+            .unwrap();
+    }
+
+    Ok(())
+}
+
 #[veebot_cmd]
 #[aliases("s", "fs")]
 async fn skip(ctx: &Context, msg: &Message, mut args: Args) -> crate::Result<()> {
@@ -126,15 +279,684 @@ async fn clear(ctx: &Context, msg: &Message) -> crate::Result<()> {
     Ok(())
 }
 
+#[veebot_cmd]
+#[aliases("summon")]
+async fn join(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    let task_send = get_or_create_audio_track_queue(ctx, msg).await?;
+
+    task_send
+        .unbounded_send(AudioQueueCmd::Join {
+            source: msg.clone(),
+        })
+        .unwrap();
+
+    Ok(())
+}
+
+#[veebot_cmd]
+#[aliases("rm")]
+async fn remove(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let task_send = get_or_create_audio_track_queue(ctx, msg).await?;
+
+    let input = args.remains().unwrap_or("").trim();
+    let removal = match input {
+        "mine" => TrackRemoval::OrderedBy(msg.author.id),
+        _ => match utils::parse_username(input) {
+            Some(user) => TrackRemoval::OrderedBy(UserId(user)),
+            None => TrackRemoval::Range(parse_track_range(input)?),
+        },
+    };
+
+    task_send
+        .unbounded_send(AudioQueueCmd::RemoveTracks {
+            source: msg.clone(),
+            removal,
+        })
+        .unwrap();
+
+    Ok(())
+}
+
+#[veebot_cmd]
+async fn dedupe(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    let task_send = get_or_create_audio_track_queue(ctx, msg).await?;
+
+    task_send
+        .unbounded_send(AudioQueueCmd::RemoveTracks {
+            source: msg.clone(),
+            removal: TrackRemoval::Duplicates,
+        })
+        .unwrap();
+
+    Ok(())
+}
+
+#[veebot_cmd]
+#[aliases("ch")]
+async fn chapter(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let task_send = get_or_create_audio_track_queue(ctx, msg).await?;
+
+    let input = args.remains().unwrap_or("").trim();
+    let target = match input {
+        "next" => ChapterTarget::Next,
+        _ => ChapterTarget::Number(input.parse().map_err(|_| {
+            crate::err!(InvalidArgumentValue {
+                input: input.to_owned(),
+                expected: "`next` or a chapter number".into(),
+            })
+        })?),
+    };
+
+    task_send
+        .unbounded_send(AudioQueueCmd::JumpToChapter {
+            source: msg.clone(),
+            target,
+        })
+        .unwrap();
+
+    Ok(())
+}
+
+/// Parses either a single track index (`3`) or an inclusive range of them (`3-7`)
+fn parse_track_range(input: &str) -> crate::Result<RangeInclusive<usize>> {
+    let invalid = || {
+        crate::err!(InvalidArgumentValue {
+            input: input.to_owned(),
+            expected: "a track index, a range of indices (e.g. `3-7`), `mine` or a user mention"
+                .into(),
+        })
+    };
+
+    let mut bounds = input.splitn(2, '-').map(|it| it.trim().parse::<usize>());
+    let start = bounds.next().unwrap().map_err(|_| invalid())?;
+    let end = bounds
+        .next()
+        .transpose()
+        .map_err(|_| invalid())?
+        .unwrap_or(start);
+
+    if start > end {
+        return Err(invalid());
+    }
+
+    Ok(start..=end)
+}
+
+#[veebot_cmd]
+#[sub_commands(record_start, record_stop)]
+async fn record(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| {
+                it.description(
+                    MessageBuilder::new()
+                        .push_mono("record start [mixed|split]")
+                        .push_line(" - start recording the voice channel I am in")
+                        .push_mono("record stop")
+                        .push_line(" - stop recording and upload the result"),
+                )
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[veebot_cmd]
+#[aliases("start")]
+async fn record_start(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let task_send = get_or_create_audio_track_queue(ctx, msg).await?;
+
+    let mode = match args.remains() {
+        Some(it) => it.parse()?,
+        None => RecordingMode::Mixed,
+    };
+
+    task_send
+        .unbounded_send(AudioQueueCmd::StartRecording {
+            source: msg.clone(),
+            mode,
+        })
+        .unwrap();
+
+    Ok(())
+}
+
+#[veebot_cmd]
+#[aliases("stop")]
+async fn record_stop(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    let task_send = get_or_create_audio_track_queue(ctx, msg).await?;
+
+    task_send
+        .unbounded_send(AudioQueueCmd::StopRecording {
+            source: msg.clone(),
+        })
+        .unwrap();
+
+    Ok(())
+}
+
+#[veebot_cmd]
+#[sub_commands(radio_set, radio_remove)]
+async fn radio(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    let guild_id = guild_id(msg)?;
+    let radio = ctx.data.expect_dep::<di::RadioServiceToken>().await;
+
+    let schedule = match radio.schedule(guild_id).await {
+        Some(it) => it,
+        None => {
+            msg.channel_id
+                .send_message(ctx, |it| {
+                    it.embed(|it| {
+                        it.description(
+                            MessageBuilder::new()
+                                .push("The radio is not scheduled. Use ")
+                                .push_mono("radio set <start HH:MM> <end HH:MM> <voice channel> <urls... | directory>")
+                                .push(" to schedule it (time is in UTC)"),
+                        )
+                    })
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let mut description = MessageBuilder::new();
+    description
+        .push("Plays from ")
+        .push_mono(schedule.start.format("%H:%M"))
+        .push(" to ")
+        .push_mono(schedule.end.format("%H:%M"))
+        .push(" (UTC) in ")
+        .channel(schedule.voice_channel)
+        .push_line("");
+
+    match &schedule.source {
+        RadioSource::Dir(dir) => {
+            description
+                .push("Audio files from ")
+                .push_mono_safe(dir.display());
+        }
+        RadioSource::Playlist(urls) => {
+            description.push_bold_line("Playlist:");
+            for (i, url) in urls.iter().enumerate() {
+                description
+                    .push_bold(format_args!("{}. ", i + 1))
+                    .push_line_safe(url);
+            }
+        }
+    }
+
+    if radio.has_failed(guild_id).await {
+        description
+            .push_line("")
+            .push("⚠️ None of the tracks could be played today, ")
+            .push("the radio is off air until the next scheduled time");
+    }
+
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| it.title("📻 Radio schedule").description(description))
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[veebot_cmd]
+#[aliases("set")]
+#[owners_only]
+async fn radio_set(ctx: &Context, msg: &Message, mut args: Args) -> crate::Result<()> {
+    let guild_id = guild_id(msg)?;
+
+    if args.len() < 4 {
+        return Err(crate::err!(InvalidNumberOfArguments {
+            expected: 4,
+            actual: args.len(),
+        }));
+    }
+
+    let start = parse_time_of_day(&mut args)?;
+    let end = parse_time_of_day(&mut args)?;
+    let voice_channel = args.single::<ChannelId>()?;
+    ctx.cache
+        .guild_channel_of_kind(guild_id, voice_channel, &[ChannelType::Voice], "voice")
+        .await?;
+
+    // The remaining arguments are either a list of urls or a path to the directory
+    let rest = args.remains().unwrap_or("");
+    let source = match rest
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<Url>, _>>()
+    {
+        Ok(urls) => RadioSource::Playlist(urls),
+        Err(_) => RadioSource::Dir(rest.into()),
+    };
+
+    let schedule = RadioSchedule {
+        voice_channel,
+        text_channel: msg.channel_id,
+        start,
+        end,
+        source,
+    };
+
+    ctx.data
+        .expect_dep::<di::RadioServiceToken>()
+        .await
+        .set_schedule(guild_id, schedule)
+        .await?;
+
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| {
+                it.description(
+                    MessageBuilder::new()
+                        .push("📻 The radio was scheduled to play from ")
+                        .push_mono(start.format("%H:%M"))
+                        .push(" to ")
+                        .push_mono(end.format("%H:%M"))
+                        .push(" (UTC) in ")
+                        .channel(voice_channel),
+                )
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[veebot_cmd]
+#[aliases("remove", "off")]
+#[owners_only]
+async fn radio_remove(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    let guild_id = guild_id(msg)?;
+
+    let removed = ctx
+        .data
+        .expect_dep::<di::RadioServiceToken>()
+        .await
+        .remove_schedule(guild_id)
+        .await?;
+
+    let description = match removed {
+        Some(_) => "📻 The radio schedule was removed",
+        None => "The radio was not scheduled",
+    };
+
+    msg.channel_id
+        .send_message(ctx, |it| it.embed(|it| it.description(description)))
+        .await?;
+
+    Ok(())
+}
+
+fn parse_time_of_day(args: &mut Args) -> crate::Result<NaiveTime> {
+    let input = args.single::<String>().unwrap_or_default();
+    NaiveTime::parse_from_str(&input, "%H:%M")
+        .map_err(|source| crate::err!(ParseTimeOfDay { input, source }))
+}
+
+#[veebot_cmd]
+#[aliases("ap")]
+#[sub_commands(
+    audio_policy_max_duration,
+    audio_policy_livestreams,
+    audio_policy_max_livestream_duration,
+    audio_policy_block,
+    audio_policy_unblock,
+    audio_policy_normalize,
+    audio_policy_crossfade
+)]
+async fn audio_policy(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    let policy = ctx
+        .data
+        .expect_dep::<di::AudioServiceToken>()
+        .await
+        .policy(guild_id(msg)?)
+        .await;
+
+    show_audio_policy(ctx, msg, &policy).await
+}
+
+#[veebot_cmd]
+#[aliases("max_duration")]
+#[required_permissions(MANAGE_GUILD)]
+async fn audio_policy_max_duration(
+    ctx: &Context,
+    msg: &Message,
+    mut args: Args,
+) -> crate::Result<()> {
+    let max_duration = parse_minutes_or_off(&mut args)?;
+    update_audio_policy(ctx, msg, |it| it.max_duration = max_duration).await
+}
+
+#[veebot_cmd]
+#[aliases("max_live")]
+#[required_permissions(MANAGE_GUILD)]
+async fn audio_policy_max_livestream_duration(
+    ctx: &Context,
+    msg: &Message,
+    mut args: Args,
+) -> crate::Result<()> {
+    let max_duration = parse_minutes_or_off(&mut args)?;
+    update_audio_policy(ctx, msg, |it| it.max_livestream_duration = max_duration).await
+}
+
+fn parse_minutes_or_off(args: &mut Args) -> crate::Result<Option<time::Duration>> {
+    if args.current() == Some("off") {
+        return Ok(None);
+    }
+    let minutes: u64 = args.single().map_err(|err| crate::err!(ParseInt(err)))?;
+    Ok(Some(time::Duration::from_secs(minutes * 60)))
+}
+
+#[veebot_cmd]
+#[aliases("livestreams")]
+#[required_permissions(MANAGE_GUILD)]
+async fn audio_policy_livestreams(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let allow = parse_on_off(&args)?;
+    update_audio_policy(ctx, msg, |it| it.allow_livestreams = allow).await
+}
+
+#[veebot_cmd]
+#[aliases("normalize")]
+#[required_permissions(MANAGE_GUILD)]
+async fn audio_policy_normalize(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let normalize = parse_on_off(&args)?;
+    update_audio_policy(ctx, msg, |it| it.normalize_loudness = normalize).await
+}
+
+/// Max duration of the crossfade, longer ones sound more like a mashup
+const MAX_CROSSFADE_SECS: u64 = 15;
+
+#[veebot_cmd]
+#[aliases("crossfade")]
+#[required_permissions(MANAGE_GUILD)]
+async fn audio_policy_crossfade(ctx: &Context, msg: &Message, mut args: Args) -> crate::Result<()> {
+    let crossfade = match args.current() {
+        Some("off") => None,
+        _ => match args
+            .single::<u64>()
+            .map_err(|err| crate::err!(ParseInt(err)))?
+        {
+            0 => None,
+            secs if secs <= MAX_CROSSFADE_SECS => Some(time::Duration::from_secs(secs)),
+            secs => {
+                return Err(crate::err!(InvalidArgumentValue {
+                    input: secs.to_string(),
+                    expected: format!(
+                        "number of seconds from 0 to {} or `off`",
+                        MAX_CROSSFADE_SECS
+                    )
+                    .into(),
+                }))
+            }
+        },
+    };
+    update_audio_policy(ctx, msg, |it| it.crossfade = crossfade).await
+}
+
+fn parse_on_off(args: &Args) -> crate::Result<bool> {
+    match args.current() {
+        Some("on") => Ok(true),
+        Some("off") => Ok(false),
+        input => Err(crate::err!(InvalidArgumentValue {
+            input: input.unwrap_or("").to_owned(),
+            expected: "`on` or `off`".into(),
+        })),
+    }
+}
+
+#[veebot_cmd]
+#[aliases("block")]
+#[required_permissions(MANAGE_GUILD)]
+async fn audio_policy_block(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let (kind, value) = parse_blocklist_entry(ctx, args).await?;
+    update_audio_policy(ctx, msg, |it| {
+        match kind {
+            BlocklistKind::Channel => it.blocked_channels.insert(value),
+            BlocklistKind::Keyword => it.blocked_keywords.insert(value),
+        };
+    })
+    .await
+}
+
+#[veebot_cmd]
+#[aliases("unblock")]
+#[required_permissions(MANAGE_GUILD)]
+async fn audio_policy_unblock(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let (kind, value) = parse_blocklist_entry(ctx, args).await?;
+    update_audio_policy(ctx, msg, |it| {
+        match kind {
+            BlocklistKind::Channel => it.blocked_channels.remove(&value),
+            BlocklistKind::Keyword => it.blocked_keywords.remove(&value),
+        };
+    })
+    .await
+}
+
+enum BlocklistKind {
+    Channel,
+    Keyword,
+}
+
+async fn parse_blocklist_entry(
+    ctx: &Context,
+    mut args: Args,
+) -> crate::Result<(BlocklistKind, String)> {
+    let kind = match args.current() {
+        Some("channel") => BlocklistKind::Channel,
+        Some("keyword") => BlocklistKind::Keyword,
+        input => {
+            return Err(crate::err!(InvalidArgumentValue {
+                input: input.unwrap_or("").to_owned(),
+                expected: "`channel` or `keyword`".into(),
+            }))
+        }
+    };
+    args.advance();
+
+    let input = args.remains().unwrap_or("").trim();
+
+    // Allow specifying the channel via its url (including the custom ones and handles).
+    // Channel ids are case-sensitive, unlike the titles.
+    let value = match (&kind, input.parse::<Url>()) {
+        (BlocklistKind::Channel, Ok(url)) => {
+            let yt = ctx.data.expect_dep::<di::YtServiceToken>().await;
+            yt.channel_id_from_url(&url).await?
+        }
+        (BlocklistKind::Channel, Err(_)) if yt::is_channel_id(input) => input.to_owned(),
+        _ => input.to_lowercase(),
+    };
+
+    if value.is_empty() {
+        return Err(crate::err!(InvalidNumberOfArguments {
+            expected: 2,
+            actual: 1,
+        }));
+    }
+
+    Ok((kind, value))
+}
+
+async fn update_audio_policy(
+    ctx: &Context,
+    msg: &Message,
+    f: impl FnOnce(&mut AudioPolicy),
+) -> crate::Result<()> {
+    let policy = ctx
+        .data
+        .expect_dep::<di::AudioServiceToken>()
+        .await
+        .update_policy(guild_id(msg)?, f)
+        .await?;
+
+    show_audio_policy(ctx, msg, &policy).await
+}
+
+async fn show_audio_policy(
+    ctx: &Context,
+    msg: &Message,
+    policy: &AudioPolicy,
+) -> crate::Result<()> {
+    let mut description = MessageBuilder::new();
+
+    description.push_bold("Max track duration: ");
+    match policy.max_duration {
+        Some(it) => description.push_mono_line(format_duration(&it)),
+        None => description.push_line("unlimited"),
+    };
+
+    description
+        .push_bold("Livestreams: ")
+        .push_line(if policy.allow_livestreams {
+            "allowed"
+        } else {
+            "not allowed"
+        });
+
+    description.push_bold("Max livestream play time: ");
+    match policy.max_livestream_duration {
+        Some(it) => description.push_mono_line(format_duration(&it)),
+        None => description.push_line("unlimited"),
+    };
+
+    description
+        .push_bold("Loudness normalization: ")
+        .push_line(if policy.normalize_loudness {
+            "on"
+        } else {
+            "off"
+        });
+
+    description.push_bold("Crossfade: ");
+    match policy.crossfade {
+        Some(it) => description.push_mono_line(format_duration(&it)),
+        None => description.push_line("off"),
+    };
+
+    description
+        .push_bold("Blocked channels: ")
+        .push_mono_line_safe(format_args!(
+            "[{}]",
+            policy.blocked_channels.iter().format(", ")
+        ))
+        .push_bold("Blocked keywords: ")
+        .push_mono_line_safe(format_args!(
+            "[{}]",
+            policy.blocked_keywords.iter().format(", ")
+        ));
+
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| it.title("Audio policy").description(description))
+        })
+        .await?;
+
+    Ok(())
+}
+
+fn guild_id(msg: &Message) -> crate::Result<GuildId> {
+    msg.guild_id.ok_or_else(|| crate::err!(UserNotInGuild))
+}
+
+/// Max length of the lyrics on a single page (discord limits the length of the embed)
+const MAX_LYRICS_PAGE_LEN: usize = 1800;
+
+#[veebot_cmd]
+#[aliases("ly")]
+async fn lyrics(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let lyrics_service = ctx.data.expect_dep::<di::LyricsServiceToken>().await;
+
+    // Lyrics for the explicit query are cached by the query itself,
+    // otherwise they are cached by the track that is playing right now
+    let (cache_key, query) = match args.remains() {
+        Some(query) => (format!("query:{}", query.to_lowercase()), query.to_owned()),
+        None => {
+            let (reply, meta) = oneshot::channel();
+            get_or_create_audio_track_queue(ctx, msg)
+                .await?
+                .unbounded_send(AudioQueueCmd::GetActiveTrack(reply))
+                .unwrap();
+
+            let meta = meta
+                .await
+                .ok()
+                .flatten()
+                .ok_or_else(|| crate::err!(NoActiveTrack))?;
+
+            let channel = match &meta {
+                AudioTrackMeta::Yt(it) => Some(it.channel_title()),
+                AudioTrackMeta::Ytdl(it) => it.uploader(),
+                AudioTrackMeta::Local(_) => None,
+            };
+            let query = lyrics_query_from_title(meta.title(), channel);
+
+            let cache_key = match meta.url() {
+                Some(url) => format!("url:{}", url),
+                None => format!("title:{}", meta.title()),
+            };
+            (cache_key, query)
+        }
+    };
+
+    let lyrics = lyrics_service
+        .find_lyrics(cache_key, &query)
+        .await?
+        .ok_or_else(|| crate::err!(LyricsNotFound(query)))?;
+
+    let pages = lyrics_pages(&lyrics, lyrics_service.provider_name());
+
+    pagination::send_paginated_embed(ctx, msg.channel_id, msg.author.id, pages).await
+}
+
+fn lyrics_pages(lyrics: &Lyrics, provider_name: &str) -> Vec<CreateEmbed> {
+    let mut chunks = vec![String::new()];
+
+    // Split by lines, so that the verses are not cut in the middle of the line
+    for line in lyrics.text.lines() {
+        let chunk = chunks.last_mut().unwrap();
+        if !chunk.is_empty() && chunk.len() + line.len() >= MAX_LYRICS_PAGE_LEN {
+            chunks.push(String::new());
+        }
+        let chunk = chunks.last_mut().unwrap();
+        chunk.push_str(line);
+        chunk.push('\n');
+    }
+
+    let total = chunks.len();
+
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut embed = CreateEmbed::default();
+            embed
+                .title(format_args!("{} - {}", lyrics.artist, lyrics.title))
+                .description(MessageBuilder::new().push_safe(chunk))
+                .footer(|it| {
+                    it.text(format_args!(
+                        "Page {} / {} • Lyrics provided by {}",
+                        i + 1,
+                        total,
+                        provider_name
+                    ))
+                });
+            embed
+        })
+        .collect()
+}
+
 async fn get_or_create_audio_track_queue(
     ctx: &Context,
     msg: &Message,
 ) -> crate::Result<mpsc::UnboundedSender<AudioQueueCmd>> {
-    let guild_id = msg.guild_id.ok_or_else(|| crate::err!(UserNotInGuild))?;
     Ok(ctx
         .data
         .expect_dep::<di::AudioServiceToken>()
         .await
-        .get_or_create_queue(guild_id)
+        .get_or_create_queue(guild_id(msg)?)
         .await)
 }
@@ -0,0 +1,591 @@
+use crate::{
+    booru::{media_embed, BlocklistOwner, BooruQuery, BooruRating, BooruSort},
+    booru_subscriptions::POLL_INTERVAL,
+    di::{self, DiExt},
+    pagination,
+    util::{format_duration, CacheExt, ThemeTag},
+};
+use itertools::Itertools;
+use serenity::{
+    client::Context,
+    framework::standard::{macros::group, Args},
+    model::{
+        channel::{ChannelType, Message},
+        id::{ChannelId, GuildId},
+    },
+    utils::MessageBuilder,
+};
+use std::collections::BTreeSet;
+use url::Url;
+use veebot_cmd::veebot_cmd;
+
+#[group]
+#[commands(pony, anime, booru, source, tag_blocklist)]
+pub(crate) struct Booru;
+
+#[veebot_cmd]
+#[sub_commands(pony_subscribe, pony_unsubscribe, pony_subscriptions)]
+async fn pony(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    send_media_gallery(ctx, msg, args, "derpibooru", "pony").await
+}
+
+#[veebot_cmd]
+#[aliases("subscribe", "sub")]
+#[required_permissions(MANAGE_GUILD)]
+async fn pony_subscribe(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    subscribe(ctx, msg, args, "derpibooru").await
+}
+
+#[veebot_cmd]
+#[aliases("unsubscribe", "unsub")]
+#[required_permissions(MANAGE_GUILD)]
+async fn pony_unsubscribe(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    unsubscribe(ctx, msg, args, "derpibooru").await
+}
+
+#[veebot_cmd]
+#[aliases("subscriptions", "subs")]
+async fn pony_subscriptions(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    show_subscriptions(ctx, msg, "derpibooru").await
+}
+
+#[veebot_cmd]
+#[sub_commands(anime_subscribe, anime_unsubscribe, anime_subscriptions)]
+async fn anime(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    send_media_gallery(ctx, msg, args, "gelbooru", "anime").await
+}
+
+#[veebot_cmd]
+#[aliases("subscribe", "sub")]
+#[required_permissions(MANAGE_GUILD)]
+async fn anime_subscribe(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    subscribe(ctx, msg, args, "gelbooru").await
+}
+
+#[veebot_cmd]
+#[aliases("unsubscribe", "unsub")]
+#[required_permissions(MANAGE_GUILD)]
+async fn anime_unsubscribe(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    unsubscribe(ctx, msg, args, "gelbooru").await
+}
+
+#[veebot_cmd]
+#[aliases("subscriptions", "subs")]
+async fn anime_subscriptions(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    show_subscriptions(ctx, msg, "gelbooru").await
+}
+
+#[veebot_cmd]
+#[aliases("b")]
+async fn booru(ctx: &Context, msg: &Message, mut args: Args) -> crate::Result<()> {
+    let name = args.current().unwrap_or_default().to_owned();
+    args.advance();
+    send_media_gallery(ctx, msg, args, &name, "image").await
+}
+
+/// Sends the media with the tags from the arguments found on the booru with the given name.
+/// The arguments may start with the number of the media to show (e.g. `pony 5 fluttershy`),
+/// several media are paged with the reactions.
+async fn send_media_gallery(
+    ctx: &Context,
+    msg: &Message,
+    mut args: Args,
+    booru_name: &str,
+    subject: &str,
+) -> crate::Result<()> {
+    let registry = ctx.data.expect_dep::<di::BooruRegistryToken>().await;
+
+    // Numbers out of the gallery size bounds are tags (e.g. `pony 2020`)
+    let max = registry.max_gallery_size();
+    let limit = match args.current().and_then(|it| it.parse::<usize>().ok()) {
+        Some(it) if (1..=max).contains(&it) => {
+            args.advance();
+            it
+        }
+        _ => 1,
+    };
+
+    let tags: Vec<ThemeTag> = args
+        .raw_quoted()
+        .map(|it| it.parse())
+        .collect::<crate::Result<Vec<_>>>()?
+        .into_iter()
+        .unique()
+        .collect();
+
+    // Explicit media is shown only in the channels marked as NSFW (never in DMs)
+    let nsfw = msg.channel_id.to_channel(ctx).await?.is_nsfw();
+    let mut query = BooruQuery::new(tags, nsfw)?;
+    query.limit = limit;
+    query.exclude_tags(registry.blocked_tags(msg.guild_id, msg.author.id).await)?;
+
+    let booru = registry.find(booru_name)?;
+
+    let footer = format!("Powered by {}", booru.site());
+
+    let media = registry
+        .fetch_unseen_media(&*booru, msg.channel_id, query.clone())
+        .await?;
+
+    if media.is_empty() {
+        let mut description = MessageBuilder::new();
+        description
+            .push(format_args!("Failed to fetch {} with tags ", subject))
+            .push_mono_line_safe(format_args!("[{}]", query.tags.iter().format(", ")));
+
+        let suggestions = registry.suggest_tags(&*booru, &query).await;
+        if !suggestions.is_empty() {
+            description.push_line("").push_bold_line("Did you mean:");
+        }
+        for (tag, suggestions) in suggestions {
+            description
+                .push_mono_safe(tag)
+                .push(" → ")
+                .push_mono_line_safe(suggestions.iter().format(", "));
+        }
+
+        msg.channel_id
+            .send_message(ctx, |it| {
+                it.embed(|it| {
+                    it.title(format_args!("No {} was found.", subject))
+                        .description(description)
+                        .footer(|it| it.text(footer))
+                })
+            })
+            .await?;
+        return Ok(());
+    }
+
+    let title = MessageBuilder::new()
+        .push(match query.sort {
+            BooruSort::Random => "Random ",
+            BooruSort::Score => "Top ",
+            BooruSort::Newest => "Newest ",
+        })
+        .push(format_args!("{} for ", subject))
+        .push_bold_safe(&msg.author.name)
+        .build();
+
+    let total = media.len();
+
+    let pages = media
+        .iter()
+        .enumerate()
+        .map(|(i, media)| {
+            let mut embed = media_embed(media);
+            embed.title(&title).footer(|it| {
+                if total > 1 {
+                    it.text(format_args!("Page {} / {} • {}", i + 1, total, footer))
+                } else {
+                    it.text(&footer)
+                }
+            });
+            embed
+        })
+        .collect();
+
+    pagination::send_paginated_embed(ctx, msg.channel_id, msg.author.id, pages).await?;
+
+    // Discord shows the video player only for the links in the message content
+    if let [media] = media.as_slice() {
+        if !media.is_image() {
+            msg.channel_id
+                .send_message(ctx, |it| it.content(&media.media_url))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Subscribes the channel from the last argument to the new uploads
+/// with the tags from the rest of the arguments (e.g. `pony subscribe fluttershy #ponies`).
+async fn subscribe(ctx: &Context, msg: &Message, mut args: Args, booru: &str) -> crate::Result<()> {
+    if args.is_empty() {
+        return Err(crate::err!(InvalidNumberOfArguments {
+            expected: 1,
+            actual: 0,
+        }));
+    }
+
+    let tags_count = args.len() - 1;
+    let tags = args
+        .raw_quoted()
+        .take(tags_count)
+        .map(|it| it.parse())
+        .collect::<crate::Result<Vec<ThemeTag>>>()?;
+
+    for _ in 0..tags_count {
+        args.advance();
+    }
+    let text_channel = args.single::<ChannelId>()?;
+    let guild_id = guild_id(msg)?;
+
+    ctx.cache
+        .guild_channel_of_kind(
+            guild_id,
+            text_channel,
+            &[ChannelType::Text, ChannelType::News],
+            "text",
+        )
+        .await?;
+
+    let subscription = ctx
+        .data
+        .expect_dep::<di::BooruSubscriptionServiceToken>()
+        .await
+        .subscribe(guild_id, booru, tags, text_channel)
+        .await?;
+
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| {
+                it.title(format_args!("Subscribed to the new {} uploads", booru))
+                    .description(
+                        MessageBuilder::new()
+                            .push("New uploads with tags ")
+                            .push_mono_safe(subscription.format_tags())
+                            .push(" will be posted in ")
+                            .mention(&subscription.text_channel),
+                    )
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn unsubscribe(
+    ctx: &Context,
+    msg: &Message,
+    mut args: Args,
+    booru: &str,
+) -> crate::Result<()> {
+    let number = args.single::<usize>()?;
+
+    let subscription = ctx
+        .data
+        .expect_dep::<di::BooruSubscriptionServiceToken>()
+        .await
+        .unsubscribe(guild_id(msg)?, booru, number)
+        .await?;
+
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| {
+                it.title(format_args!("Unsubscribed from the new {} uploads", booru))
+                    .description(
+                        MessageBuilder::new()
+                            .push("New uploads with tags ")
+                            .push_mono_safe(subscription.format_tags())
+                            .push(" will no longer be posted in ")
+                            .mention(&subscription.text_channel),
+                    )
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn show_subscriptions(ctx: &Context, msg: &Message, booru: &str) -> crate::Result<()> {
+    let subscriptions = ctx
+        .data
+        .expect_dep::<di::BooruSubscriptionServiceToken>()
+        .await
+        .subscriptions(guild_id(msg)?, booru)
+        .await;
+
+    let mut description = MessageBuilder::new();
+    if subscriptions.is_empty() {
+        description.push(format_args!(
+            "This server is not subscribed to any {} uploads",
+            booru
+        ));
+    }
+    for (i, subscription) in subscriptions.iter().enumerate() {
+        description
+            .push(format_args!("{}. ", i + 1))
+            .push_mono_safe(subscription.format_tags())
+            .push(" → ")
+            .mention(&subscription.text_channel)
+            .push_line("");
+    }
+
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| {
+                it.title(format_args!("{} subscriptions", booru))
+                    .description(description)
+                    .footer(|it| {
+                        it.text(format_args!(
+                            "New uploads are checked every {}",
+                            format_duration(&POLL_INTERVAL)
+                        ))
+                    })
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[veebot_cmd]
+#[aliases("sauce")]
+async fn source(ctx: &Context, msg: &Message, mut args: Args) -> crate::Result<()> {
+    let image_url = match args.current() {
+        Some(_) => args.single::<Url>()?,
+        None => find_image_url(ctx, msg).await?,
+    };
+
+    let derpibooru = ctx.data.expect_dep::<di::DerpibooruServiceToken>().await;
+    let mut matches = derpibooru.reverse_search(&image_url).await?;
+
+    // The searched image may be safe, while its matches are not
+    let nsfw = msg.channel_id.to_channel(ctx).await?.is_nsfw();
+    let total = matches.len();
+    if !nsfw {
+        matches.retain(|it| it.media.rating == Some(BooruRating::Safe));
+    }
+    let hidden = total - matches.len();
+
+    if matches.is_empty() {
+        let mut description = MessageBuilder::new();
+        description.push_line("Nothing similar to the image was found on derpibooru.");
+        if hidden > 0 {
+            description.push_line(format_args!(
+                "{} NSFW matches are hidden, use the command in a channel marked as NSFW",
+                hidden
+            ));
+        }
+
+        msg.channel_id
+            .send_message(ctx, |it| {
+                it.embed(|it| {
+                    it.title("No source was found.")
+                        .description(description)
+                        .footer(|it| it.text("Powered by derpibooru.org"))
+                })
+            })
+            .await?;
+        return Ok(());
+    }
+
+    let title = MessageBuilder::new()
+        .push("Source of the image for ")
+        .push_bold_safe(&msg.author.name)
+        .build();
+
+    let total = matches.len();
+
+    let pages = matches
+        .iter()
+        .enumerate()
+        .map(|(i, it)| {
+            let mut embed = media_embed(&it.media);
+
+            let artists = it
+                .media
+                .tags
+                .iter()
+                .filter_map(|tag| tag.strip_prefix("artist:"))
+                .format(", ")
+                .to_string();
+
+            embed
+                .title(&title)
+                .field(
+                    "Similarity",
+                    format_args!("≥ {:.0}%", it.min_similarity * 100.0),
+                    true,
+                )
+                .field(
+                    "Artists",
+                    if artists.is_empty() {
+                        "unknown"
+                    } else {
+                        artists.as_str()
+                    },
+                    true,
+                );
+
+            if let Some(source_url) = &it.source_url {
+                embed.field("Source", source_url, false);
+            }
+
+            embed.footer(|it| {
+                it.text(format_args!(
+                    "Match {} / {} • Powered by derpibooru.org",
+                    i + 1,
+                    total
+                ))
+            });
+            embed
+        })
+        .collect();
+
+    pagination::send_paginated_embed(ctx, msg.channel_id, msg.author.id, pages).await
+}
+
+/// Returns the url of the image attached to the message,
+/// or of the one in the message that it replies to.
+async fn find_image_url(ctx: &Context, msg: &Message) -> crate::Result<Url> {
+    if let Some(url) = image_url_in_message(msg) {
+        return Ok(url);
+    }
+
+    let reference = msg
+        .message_reference
+        .as_ref()
+        .and_then(|it| Some((it.channel_id, it.message_id?)));
+
+    if let Some((channel_id, message_id)) = reference {
+        let replied_to = channel_id.message(ctx, message_id).await?;
+        if let Some(url) = image_url_in_message(&replied_to) {
+            return Ok(url);
+        }
+    }
+
+    Err(crate::err!(NoImageToSearch))
+}
+
+fn image_url_in_message(msg: &Message) -> Option<Url> {
+    let attachments = msg.attachments.iter().map(|it| it.url.as_str());
+    let embeds = msg.embeds.iter().flat_map(|it| {
+        it.image
+            .iter()
+            .map(|it| it.url.as_str())
+            .chain(it.thumbnail.iter().map(|it| it.url.as_str()))
+    });
+    let links = msg.content.split_whitespace();
+
+    attachments
+        .chain(embeds)
+        .chain(links)
+        .filter_map(|it| it.parse::<Url>().ok())
+        .find(|it| matches!(it.scheme(), "http" | "https"))
+}
+
+#[veebot_cmd]
+#[aliases("blocklist")]
+#[sub_commands(
+    tag_blocklist_add,
+    tag_blocklist_remove,
+    tag_blocklist_guild_add,
+    tag_blocklist_guild_remove
+)]
+async fn tag_blocklist(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    show_tag_blocklists(ctx, msg).await
+}
+
+#[veebot_cmd]
+#[aliases("add")]
+async fn tag_blocklist_add(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let tags = parse_blocklist_tags(args)?;
+    update_tag_blocklist(ctx, msg, BlocklistOwner::User(msg.author.id), |it| {
+        it.extend(tags)
+    })
+    .await
+}
+
+#[veebot_cmd]
+#[aliases("remove")]
+async fn tag_blocklist_remove(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let tags = parse_blocklist_tags(args)?;
+    update_tag_blocklist(ctx, msg, BlocklistOwner::User(msg.author.id), |it| {
+        for tag in &tags {
+            it.remove(tag);
+        }
+    })
+    .await
+}
+
+#[veebot_cmd]
+#[aliases("guild_add")]
+#[required_permissions(MANAGE_GUILD)]
+async fn tag_blocklist_guild_add(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let tags = parse_blocklist_tags(args)?;
+    update_tag_blocklist(ctx, msg, BlocklistOwner::Guild(guild_id(msg)?), |it| {
+        it.extend(tags)
+    })
+    .await
+}
+
+#[veebot_cmd]
+#[aliases("guild_remove")]
+#[required_permissions(MANAGE_GUILD)]
+async fn tag_blocklist_guild_remove(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
+    let tags = parse_blocklist_tags(args)?;
+    update_tag_blocklist(ctx, msg, BlocklistOwner::Guild(guild_id(msg)?), |it| {
+        for tag in &tags {
+            it.remove(tag);
+        }
+    })
+    .await
+}
+
+/// Tags are compared case-insensitively by the boorus, so we store them lowercase
+fn parse_blocklist_tags(args: Args) -> crate::Result<Vec<String>> {
+    let tags = args
+        .raw_quoted()
+        .map(|it| -> crate::Result<_> {
+            Ok(it.parse::<ThemeTag>()?.as_str().trim().to_lowercase())
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+
+    if tags.is_empty() {
+        return Err(crate::err!(InvalidNumberOfArguments {
+            expected: 1,
+            actual: 0,
+        }));
+    }
+
+    Ok(tags)
+}
+
+async fn update_tag_blocklist(
+    ctx: &Context,
+    msg: &Message,
+    owner: BlocklistOwner,
+    f: impl FnOnce(&mut BTreeSet<String>),
+) -> crate::Result<()> {
+    ctx.data
+        .expect_dep::<di::BooruRegistryToken>()
+        .await
+        .update_blocklist(owner, f)
+        .await?;
+
+    show_tag_blocklists(ctx, msg).await
+}
+
+async fn show_tag_blocklists(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    let registry = ctx.data.expect_dep::<di::BooruRegistryToken>().await;
+
+    let mut description = MessageBuilder::new();
+
+    if let Some(guild_id) = msg.guild_id {
+        let blocklist = registry.blocklist(BlocklistOwner::Guild(guild_id)).await;
+        description
+            .push_bold("Server blocklist: ")
+            .push_mono_line_safe(format_args!("[{}]", blocklist.iter().format(", ")));
+    }
+
+    let blocklist = registry
+        .blocklist(BlocklistOwner::User(msg.author.id))
+        .await;
+    description
+        .push_bold("Your blocklist: ")
+        .push_mono_line_safe(format_args!("[{}]", blocklist.iter().format(", ")));
+
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| it.title("Blocked tags").description(description))
+        })
+        .await?;
+
+    Ok(())
+}
+
+fn guild_id(msg: &Message) -> crate::Result<GuildId> {
+    msg.guild_id.ok_or_else(|| crate::err!(UserNotInGuild))
+}
@@ -1,25 +1,19 @@
 //! Discord commands root module
 
 pub(crate) mod audio;
+pub(crate) mod booru;
 pub(crate) mod image;
+pub(crate) mod yt;
 
-use std::collections::HashSet;
-
-use crate::{
-    di::{self, DiExt},
-    util::ThemeTag,
-};
-use itertools::Itertools;
+use crate::di::{self, DiExt};
 use serenity::{
-    client::Context,
-    framework::standard::{macros::group, Args},
-    model::channel::Message,
+    client::Context, framework::standard::macros::group, model::channel::Message,
     utils::MessageBuilder,
 };
 use veebot_cmd::veebot_cmd;
 
 #[group]
-#[commands(pink, restart)]
+#[commands(pink, restart, yt_cache, yt_quota)]
 pub(crate) struct Meta;
 
 #[veebot_cmd]
@@ -54,144 +48,97 @@ async fn restart(ctx: &Context, msg: &Message) -> crate::Result<()> {
     panic!("{}", error);
 }
 
-#[group]
-#[commands(pony, anime)]
-pub(crate) struct General;
+#[veebot_cmd]
+#[owners_only]
+#[sub_commands(yt_cache_clear)]
+async fn yt_cache(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    show_yt_cache_stats(ctx, msg).await
+}
 
 #[veebot_cmd]
-async fn pony(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
-    let tags: HashSet<ThemeTag> = args
-        .raw_quoted()
-        .map(|it| it.parse())
-        .collect::<crate::Result<_>>()?;
-
-    let derpibooru = ctx.data.expect_dep::<di::DerpibooruServiceToken>().await;
-
-    let footer = "Powered by derpibooru.org";
-    let media = match derpibooru.fetch_random_media(tags.iter().cloned()).await? {
-        Some(it) => it,
-        None => {
-            msg.channel_id
-                .send_message(ctx, |it| {
-                    it.embed(|it| {
-                        it.title("Pony was not found.")
-                            .description(
-                                MessageBuilder::new()
-                                    .push("Failed to fetch pony with tags ")
-                                    .push_mono_safe(format_args!("[{}]", tags.iter().format(", "))),
-                            )
-                            .footer(|it| it.text(footer))
-                    })
-                })
-                .await?;
-            return Ok(());
-        }
-    };
+#[aliases("clear")]
+#[owners_only]
+async fn yt_cache_clear(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    ctx.data
+        .expect_dep::<di::YtServiceToken>()
+        .await
+        .cache()
+        .clear()
+        .await?;
+
+    show_yt_cache_stats(ctx, msg).await
+}
 
-    let media_url = &media.representations.full;
+async fn show_yt_cache_stats(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    let stats = ctx
+        .data
+        .expect_dep::<di::YtServiceToken>()
+        .await
+        .cache()
+        .stats()
+        .await;
+
+    let lookups = stats.hits + stats.misses;
+    let hit_rate = if lookups == 0 {
+        0.0
+    } else {
+        stats.hits as f64 / lookups as f64 * 100.0
+    };
 
     msg.channel_id
         .send_message(ctx, |it| {
             it.embed(|it| {
-                it.title(
-                    MessageBuilder::new()
-                        .push("Random pony for ")
-                        .push_bold_safe(&msg.author.name),
-                )
-                .description(
+                it.title("YouTube API cache").description(
                     MessageBuilder::new()
-                        .push_bold("Score:")
-                        .push(" ")
-                        .push_italic_line(media.score)
-                        .push_bold("Created:")
-                        .push(" ")
-                        .push_italic_line_safe(
-                            timeago::Formatter::new()
-                                .convert_chrono(media.created_at, chrono::Utc::now()),
-                        )
-                        .push_bold_line("Tags:")
-                        .push_italic_line_safe(
-                            MessageBuilder::new().push_codeblock_safe(media.tags.join(", "), None),
-                        ),
+                        .push_bold("Hits: ")
+                        .push_mono_line(stats.hits)
+                        .push_bold("Misses: ")
+                        .push_mono_line(stats.misses)
+                        .push_bold("Hit rate: ")
+                        .push_mono_line(format_args!("{:.1}%", hit_rate))
+                        .push_bold("Cached videos: ")
+                        .push_mono_line(stats.videos)
+                        .push_bold("Cached queries: ")
+                        .push_mono_line(stats.queries)
+                        .push_bold("Persistent: ")
+                        .push_line(if stats.is_persistent { "yes" } else { "no" }),
                 )
-                .url(media.webpage_url())
-                .footer(|it| it.text(footer));
-
-                if media.mime_type.is_image() {
-                    it.image(media_url);
-                }
-                it
             })
         })
         .await?;
 
-    if !media.mime_type.is_image() {
-        msg.channel_id
-            .send_message(ctx, |it| it.content(media_url))
-            .await?;
-    }
-
     Ok(())
 }
 
 #[veebot_cmd]
-async fn anime(ctx: &Context, msg: &Message, args: Args) -> crate::Result<()> {
-    let tags: HashSet<ThemeTag> = args
-        .raw_quoted()
-        .map(|it| it.parse())
-        .collect::<crate::Result<_>>()?;
-
-    let gelbooru = ctx.data.expect_dep::<di::GelbooruServiceToken>().await;
-
-    let footer = "Powered by gelbooru.com";
-    let media = match gelbooru.fetch_random_media(tags.iter().cloned()).await? {
-        Some(it) => it,
-        None => {
-            msg.channel_id
-                .send_message(ctx, |it| {
-                    it.embed(|it| {
-                        it.title("Anime was not found.")
-                            .description(
-                                MessageBuilder::new()
-                                    .push("Failed to fetch anime with tags ")
-                                    .push_mono_safe(format_args!("[{}]", tags.iter().format(", "))),
-                            )
-                            .footer(|it| it.text(footer))
-                    })
-                })
-                .await?;
-            return Ok(());
+#[owners_only]
+async fn yt_quota(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    let stats = ctx
+        .data
+        .expect_dep::<di::YtServiceToken>()
+        .await
+        .api_keys()
+        .stats()
+        .await;
+
+    let mut description = MessageBuilder::new();
+    for (i, key) in stats.iter().enumerate() {
+        description
+            .push_bold(format_args!("{}. ", i + 1))
+            .push_mono(&key.masked_key)
+            .push(format_args!(": {} units used today", key.used_units));
+        if key.exhausted {
+            description.push_bold(" (exhausted)");
         }
-    };
-
-    let media_url = &media.file_url;
+        description.push_line("");
+    }
 
     msg.channel_id
         .send_message(ctx, |it| {
             it.embed(|it| {
-                it.title(
-                    MessageBuilder::new()
-                        .push("Random anime for ")
-                        .push_bold_safe(&msg.author.name),
-                )
-                .description(
-                    MessageBuilder::new()
-                        .push_bold("Score:")
-                        .push(" ")
-                        .push_italic_line(media.score)
-                        .push_bold("Created:")
-                        .push(" ")
-                        .push_italic_line_safe(&media.created_at)
-                        .push_bold_line("Tags:")
-                        .push_italic_line_safe(
-                            MessageBuilder::new().push_codeblock_safe(&media.tags, None),
-                        ),
-                )
-                .url(media.webpage_url())
-                .footer(|it| it.text(footer));
-
-                it.image(media_url);
-                it
+                it.title("YouTube Data API quota usage")
+                    .description(description)
+                    .footer(|it| it.text("The quota is reset at midnight Pacific Time"))
             })
         })
         .await?;
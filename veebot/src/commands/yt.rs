@@ -0,0 +1,154 @@
+use crate::{
+    di::{self, DiExt},
+    util::{format_duration, CacheExt},
+    yt_subscriptions::POLL_INTERVAL,
+};
+use serenity::{
+    client::Context,
+    framework::standard::{macros::group, Args},
+    model::{
+        channel::{ChannelType, Message},
+        id::{ChannelId, GuildId},
+    },
+    utils::MessageBuilder,
+};
+use url::Url;
+use veebot_cmd::veebot_cmd;
+
+#[group]
+#[commands(yt)]
+pub(crate) struct Yt;
+
+#[veebot_cmd]
+#[sub_commands(yt_subscribe, yt_unsubscribe)]
+async fn yt(ctx: &Context, msg: &Message) -> crate::Result<()> {
+    let subscriptions = ctx
+        .data
+        .expect_dep::<di::YtSubscriptionServiceToken>()
+        .await
+        .subscriptions(guild_id(msg)?)
+        .await;
+
+    let mut description = MessageBuilder::new();
+    if subscriptions.is_empty() {
+        description.push("This server is not subscribed to any YouTube channel");
+    }
+    for subscription in &subscriptions {
+        description
+            .push("[")
+            .push_bold_safe(&subscription.channel_title)
+            .push("](")
+            .push_safe(subscription.channel_url())
+            .push(") → ")
+            .mention(&subscription.text_channel)
+            .push_line("");
+    }
+
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| {
+                it.title("YouTube subscriptions")
+                    .description(description)
+                    .footer(|it| {
+                        it.text(format_args!(
+                            "New uploads are checked every {}",
+                            format_duration(&POLL_INTERVAL)
+                        ))
+                    })
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[veebot_cmd]
+#[aliases("subscribe", "sub")]
+#[required_permissions(MANAGE_GUILD)]
+async fn yt_subscribe(ctx: &Context, msg: &Message, mut args: Args) -> crate::Result<()> {
+    if args.len() != 2 {
+        return Err(crate::err!(InvalidNumberOfArguments {
+            expected: 2,
+            actual: args.len(),
+        }));
+    }
+
+    let url = args.single::<Url>()?;
+    let text_channel = args.single::<ChannelId>()?;
+    let guild_id = guild_id(msg)?;
+
+    ctx.cache
+        .guild_channel_of_kind(
+            guild_id,
+            text_channel,
+            &[ChannelType::Text, ChannelType::News],
+            "text",
+        )
+        .await?;
+
+    let yt = ctx.data.expect_dep::<di::YtServiceToken>().await;
+    let channel_id = yt.channel_id_from_url(&url).await?;
+
+    let subscription = ctx
+        .data
+        .expect_dep::<di::YtSubscriptionServiceToken>()
+        .await
+        .subscribe(guild_id, channel_id, text_channel)
+        .await?;
+
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| {
+                it.title("Subscribed to the YouTube channel").description(
+                    MessageBuilder::new()
+                        .push("New uploads of ")
+                        .push("[")
+                        .push_bold_safe(&subscription.channel_title)
+                        .push("](")
+                        .push_safe(subscription.channel_url())
+                        .push(") will be announced in ")
+                        .mention(&subscription.text_channel),
+                )
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+#[veebot_cmd]
+#[aliases("unsubscribe", "unsub")]
+#[required_permissions(MANAGE_GUILD)]
+async fn yt_unsubscribe(ctx: &Context, msg: &Message, mut args: Args) -> crate::Result<()> {
+    let url = args.single::<Url>()?;
+
+    let yt = ctx.data.expect_dep::<di::YtServiceToken>().await;
+    let channel_id = yt.channel_id_from_url(&url).await?;
+
+    let subscription = ctx
+        .data
+        .expect_dep::<di::YtSubscriptionServiceToken>()
+        .await
+        .unsubscribe(guild_id(msg)?, &channel_id)
+        .await?;
+
+    msg.channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| {
+                it.title("Unsubscribed from the YouTube channel")
+                    .description(
+                        MessageBuilder::new()
+                            .push("New uploads of ")
+                            .push_bold_safe(&subscription.channel_title)
+                            .push(" will no longer be announced"),
+                    )
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+fn guild_id(msg: &Message) -> crate::Result<GuildId> {
+    msg.guild_id.ok_or_else(|| crate::err!(UserNotInGuild))
+}
@@ -0,0 +1,236 @@
+//! Symbols related to communicating with the Danbooru API
+
+use crate::{
+    booru::{
+        self, Booru, BooruCredentials, BooruMedia, BooruQuery, BooruRating, BooruSort, BooruTag,
+    },
+    util::{self, ReqwestBuilderExt, ThemeTag},
+};
+use itertools::Itertools;
+use serenity::async_trait;
+use std::sync::Arc;
+use url::Url;
+
+/// Declarations of the danbooru JSON API types.
+/// Reference: https://danbooru.donmai.us/wiki_pages/help:api
+pub(crate) mod rpc {
+    use chrono::Utc;
+    use serde::Deserialize;
+    use url::Url;
+
+    pub(crate) mod search {
+        use super::*;
+
+        #[derive(Debug, Deserialize)]
+        pub(crate) struct Response(pub(crate) Vec<Post>);
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Post {
+        pub(crate) id: u128,
+        /// Absent for the posts that are visible only to the privileged accounts
+        pub(crate) file_url: Option<Url>,
+        pub(crate) preview_file_url: Option<Url>,
+        /// Space-separated list of tags
+        pub(crate) tag_string: String,
+        /// `g` (general), `s` (sensitive), `q` (questionable) or `e` (explicit)
+        pub(crate) rating: String,
+        pub(crate) created_at: chrono::DateTime<Utc>,
+        pub(crate) score: i64,
+    }
+
+    pub(crate) mod autocomplete {
+        use super::*;
+
+        #[derive(Debug, Deserialize)]
+        pub(crate) struct Response(pub(crate) Vec<Item>);
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Item {
+        /// Name of the tag
+        pub(crate) value: String,
+        #[serde(default)]
+        pub(crate) post_count: u64,
+        /// Alias (or a misspelling) of the tag that matched the query
+        #[serde(default)]
+        pub(crate) antecedent: Option<String>,
+    }
+}
+
+// Links that are shown to the users, the requests go to `DanbooruService::api_url`
+util::def_url_base!(danbooru, "https://danbooru.donmai.us");
+
+/// Some posts are visible only to the privileged accounts (they have no file url),
+/// and the anonymous searches may return the posts with the blocked tags, so we
+/// request this many more posts than needed to make up for the skipped ones.
+const EXTRA_POSTS: usize = 20;
+
+impl rpc::Post {
+    pub(crate) fn webpage_url(&self) -> Url {
+        danbooru(&["posts", &self.id.to_string()])
+    }
+
+    /// Danbooru's `s` means `sensitive` (unlike `safe` on the other boorus)
+    fn rating(&self) -> Option<BooruRating> {
+        match self.rating.as_str() {
+            "g" => Some(BooruRating::Safe),
+            "s" | "q" => Some(BooruRating::Questionable),
+            "e" => Some(BooruRating::Explicit),
+            _ => None,
+        }
+    }
+
+    fn into_media(self) -> Option<BooruMedia> {
+        let webpage_url = self.webpage_url();
+        let rating = self.rating();
+        let media_url = self.file_url?;
+        Some(BooruMedia {
+            id: self.id.to_string(),
+            webpage_url,
+            rating,
+            mime_type: booru::mime_type_from_url(&media_url),
+            media_url,
+            thumbnail_url: self.preview_file_url,
+            tags: self
+                .tag_string
+                .split_whitespace()
+                .map(ToOwned::to_owned)
+                .collect(),
+            score: self.score,
+            created_at: Some(self.created_at),
+        })
+    }
+}
+
+pub(crate) struct DanbooruService {
+    http_client: Arc<reqwest::Client>,
+    credentials: Option<BooruCredentials>,
+    /// Base url of the API (`https://danbooru.donmai.us`)
+    api_url: Url,
+}
+
+impl DanbooruService {
+    pub(crate) fn new(
+        credentials: Option<BooruCredentials>,
+        api_url: Url,
+        http_client: Arc<reqwest::Client>,
+    ) -> Self {
+        Self {
+            http_client,
+            credentials,
+            api_url,
+        }
+    }
+}
+
+#[async_trait]
+impl Booru for DanbooruService {
+    fn name(&self) -> &'static str {
+        "danbooru"
+    }
+
+    fn site(&self) -> &'static str {
+        "danbooru.donmai.us"
+    }
+
+    async fn fetch_media(&self, query: &BooruQuery) -> crate::Result<Vec<BooruMedia>> {
+        // The rating metatag doesn't count towards the tags limit
+        let safe_tag: ThemeTag = "rating:g".parse().unwrap();
+        let rating_tag = if query.nsfw { None } else { Some(&safe_tag) };
+
+        // Anonymous users may search by at most 2 tags, and `random=true`
+        // doesn't count as a tag (unlike `order:random`)
+        let score_tag: ThemeTag = "order:score".parse().unwrap();
+        let sort_tag = if query.sort == BooruSort::Score {
+            Some(&score_tag)
+        } else {
+            None
+        };
+
+        // Negated tags count towards the limit too, so without an account
+        // the blocked tags are filtered out of the results instead
+        let (search_tags, filtered_tags) = if self.credentials.is_some() {
+            (query.underscored_search_tags(), Vec::new())
+        } else {
+            let excluded = query
+                .excluded_tags
+                .iter()
+                .map(|it| booru::underscored_tag(it).as_str().to_lowercase())
+                .collect();
+            (
+                query.tags.iter().map(booru::underscored_tag).collect(),
+                excluded,
+            )
+        };
+
+        let tags = search_tags
+            .iter()
+            .chain(rating_tag)
+            .chain(sort_tag)
+            .unique()
+            .join(" ");
+
+        let limit = (query.limit + EXTRA_POSTS).to_string();
+
+        let mut params = vec![("tags", tags.as_str()), ("limit", &limit)];
+
+        if query.sort == BooruSort::Random {
+            params.push(("random", "true"));
+        }
+
+        if let Some(credentials) = &self.credentials {
+            params.push(("login", &credentials.login));
+            params.push(("api_key", &credentials.api_key));
+        }
+
+        let res: rpc::search::Response = self
+            .http_client
+            .get(util::url_with_segments(&self.api_url, &["posts.json"]))
+            .query(&params)
+            .read_json()
+            .await?;
+
+        Ok(res
+            .0
+            .into_iter()
+            .filter_map(rpc::Post::into_media)
+            .filter(|it| !it.tags.iter().any(|tag| filtered_tags.contains(tag)))
+            .take(query.limit)
+            .collect())
+    }
+
+    /// Danbooru's autocomplete resolves the aliases and corrects the typos by itself
+    async fn find_similar_tags(&self, tag: &str) -> crate::Result<Vec<BooruTag>> {
+        let mut params = vec![
+            ("search[query]", tag),
+            ("search[type]", "tag_query"),
+            ("limit", "20"),
+        ];
+
+        if let Some(credentials) = &self.credentials {
+            params.push(("login", &credentials.login));
+            params.push(("api_key", &credentials.api_key));
+        }
+
+        let res: rpc::autocomplete::Response = self
+            .http_client
+            .get(util::url_with_segments(
+                &self.api_url,
+                &["autocomplete.json"],
+            ))
+            .query(&params)
+            .read_json()
+            .await?;
+
+        Ok(res
+            .0
+            .into_iter()
+            .map(|it| BooruTag {
+                name: it.value,
+                post_count: it.post_count,
+                alias: it.antecedent,
+            })
+            .collect())
+    }
+}
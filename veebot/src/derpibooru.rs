@@ -1,7 +1,11 @@
 //! Symbols related to communicating with the Derpibooru API
 
-use crate::util::{self, ReqwestBuilderExt, ThemeTag};
+use crate::{
+    booru::{Booru, BooruMedia, BooruQuery, BooruRating, BooruSort, BooruTag},
+    util::{self, ReqwestBuilderExt, ThemeTag},
+};
 use itertools::Itertools;
+use serenity::async_trait;
 use std::{collections::HashSet, sync::Arc};
 use url::Url;
 
@@ -11,6 +15,7 @@ use url::Url;
 pub(crate) mod rpc {
     use chrono::Utc;
     use serde::Deserialize;
+    use url::Url;
 
     pub(crate) mod search {
         use super::*;
@@ -24,108 +29,293 @@ pub(crate) mod rpc {
     #[derive(Debug, Deserialize)]
     pub(crate) struct Image {
         pub(crate) id: u128,
-        pub(crate) mime_type: ImageMimeType,
+        /// `image/gif`, `image/jpeg`, `image/png`, `image/svg+xml` or `video/webm`
+        pub(crate) mime_type: String,
         pub(crate) representations: ImageRepresentations,
         pub(crate) tags: Vec<String>,
         pub(crate) created_at: chrono::DateTime<Utc>,
         /// The image's number of upvotes minus the image's number of downvotes.
-        pub(crate) score: u64,
+        pub(crate) score: i64,
+        /// Where the image was originally posted (according to the uploader)
+        #[serde(default)]
+        pub(crate) source_url: Option<String>,
     }
 
     #[derive(Debug, Deserialize)]
     pub(crate) struct ImageRepresentations {
-        pub(crate) full: String,
-        pub(crate) thumb: String,
+        pub(crate) full: Url,
+        pub(crate) thumb: Url,
+    }
+
+    pub(crate) mod tags {
+        use super::*;
+
+        #[derive(Debug, Deserialize)]
+        pub(crate) struct Response {
+            pub(crate) tags: Vec<Tag>,
+        }
     }
 
     #[derive(Debug, Deserialize)]
-    pub(crate) enum ImageMimeType {
-        #[serde(rename = "image/gif")]
-        ImageGif,
-        #[serde(rename = "image/jpeg")]
-        ImageJpeg,
-        #[serde(rename = "image/png")]
-        ImagePng,
-        #[serde(rename = "image/svg+xml")]
-        ImageSvgXml,
-        #[serde(rename = "video/webm")]
-        VideoWebm,
+    pub(crate) struct Tag {
+        pub(crate) name: String,
+        /// Number of the images with this tag
+        pub(crate) images: u64,
+        /// Slug of the tag this one is an alias of
+        pub(crate) aliased_tag: Option<String>,
     }
 }
 
-util::def_url_base!(derpibooru_api, "https://derpibooru.org/api/v1/json");
+// Links that are shown to the users, the requests go to `DerpibooruService::api_url`
 util::def_url_base!(derpibooru, "https://derpibooru.org");
 
 impl rpc::Image {
     pub(crate) fn webpage_url(&self) -> Url {
         derpibooru(&["images", &self.id.to_string()])
     }
+
+    /// Derpibooru has no separate rating field, the rating is one of the tags
+    fn rating(&self) -> Option<BooruRating> {
+        self.tags.iter().find_map(|tag| match tag.as_str() {
+            "safe" => Some(BooruRating::Safe),
+            "suggestive" | "questionable" => Some(BooruRating::Questionable),
+            "explicit" => Some(BooruRating::Explicit),
+            _ => None,
+        })
+    }
+
+    fn into_media(self) -> BooruMedia {
+        BooruMedia {
+            id: self.id.to_string(),
+            webpage_url: self.webpage_url(),
+            rating: self.rating(),
+            media_url: self.representations.full,
+            thumbnail_url: Some(self.representations.thumb),
+            tags: self.tags,
+            score: self.score,
+            created_at: Some(self.created_at),
+            mime_type: self.mime_type,
+        }
+    }
+}
+
+/// Thresholds of the distance between the images that the reverse search is done with,
+/// the closest matches go first. The API doesn't tell the distance of each match,
+/// so this is the only way to know how similar the found images are.
+const REVERSE_SEARCH_DISTANCES: &[f64] = &[0.05, 0.15, 0.25];
+
+/// Max number of the images returned by the reverse search
+const MAX_REVERSE_SEARCH_MATCHES: usize = 5;
+
+/// Image found by the reverse search
+pub(crate) struct ReverseSearchMatch {
+    pub(crate) media: BooruMedia,
+    /// Lower bound of the similarity to the searched image (from 0 to 1)
+    pub(crate) min_similarity: f64,
+    /// Where the image was originally posted (according to the uploader)
+    pub(crate) source_url: Option<String>,
 }
 
-impl rpc::ImageMimeType {
-    pub(crate) fn is_image(&self) -> bool {
-        match self {
-            rpc::ImageMimeType::ImageGif
-            | rpc::ImageMimeType::ImageJpeg
-            | rpc::ImageMimeType::ImagePng
-            | rpc::ImageMimeType::ImageSvgXml => true,
-            rpc::ImageMimeType::VideoWebm => false,
+/// Number of the leading chars of the tag that we assume to have no typos
+const SIMILAR_TAGS_PREFIX_LEN: usize = 3;
+
+/// Max number of the tags returned per page by the API
+const MAX_TAGS_PER_PAGE: &str = "50";
+
+impl rpc::Tag {
+    pub(crate) fn into_booru_tag(self) -> BooruTag {
+        match self.aliased_tag {
+            Some(slug) => BooruTag {
+                name: tag_name_from_slug(&slug),
+                post_count: self.images,
+                alias: Some(self.name),
+            },
+            None => BooruTag {
+                name: self.name,
+                post_count: self.images,
+                alias: None,
+            },
         }
     }
 }
 
+/// Philomena refers to the tags by their slugs, where the special chars are replaced
+/// (e.g. `artist-colon-somebody` for `artist:somebody`, `twilight+sparkle` for `twilight sparkle`)
+fn tag_name_from_slug(slug: &str) -> String {
+    [
+        ("+", " "),
+        ("-colon-", ":"),
+        ("-dot-", "."),
+        ("-plus-", "+"),
+        ("-fwslash-", "/"),
+        ("-bwslash-", "\\"),
+        ("-dash-", "-"),
+    ]
+    .iter()
+    .fold(slug.to_owned(), |name, (from, to)| name.replace(from, to))
+}
+
+/// Philomena has no typo-tolerant search, so we search for the tags that start the same way
+/// as the given one (which also finds the aliases), and let the caller rank them.
+pub(crate) fn similar_tags_query(tag: &str) -> String {
+    let prefix: String = tag.chars().take(SIMILAR_TAGS_PREFIX_LEN).collect();
+    format!("name:{}*", prefix.to_lowercase())
+}
+
 pub(crate) struct DerpibooruService {
     http_client: Arc<reqwest::Client>,
     derpibooru_api_key: String,
-    filter_id: String,
+    /// Filter that is used in the SFW channels
+    sfw_filter_id: String,
+    /// Filter that is used in the NSFW channels
+    nsfw_filter_id: String,
     always_on_tags: HashSet<ThemeTag>,
+    /// Base url of the API (`https://derpibooru.org/api/v1/json`)
+    api_url: Url,
 }
 
 impl DerpibooruService {
     pub(crate) fn new(
         derpibooru_api_key: String,
-        filter_id: String,
+        sfw_filter_id: String,
+        nsfw_filter_id: String,
         always_on_tags: HashSet<ThemeTag>,
+        api_url: Url,
         http_client: Arc<reqwest::Client>,
     ) -> Self {
         Self {
             http_client,
             derpibooru_api_key,
-            filter_id,
+            sfw_filter_id,
+            nsfw_filter_id,
             always_on_tags,
+            api_url,
         }
     }
 
-    /// Fetches random pony media (image or video) based on the given tags (if there are any).
-    pub(crate) async fn fetch_random_media(
+    /// Finds the images that look like the one at the given url, the most similar go first.
+    pub(crate) async fn reverse_search(
         &self,
-        tags: impl IntoIterator<Item = ThemeTag>,
-    ) -> crate::Result<Option<rpc::Image>> {
-        let tags_with_always_on_ones = tags
-            .into_iter()
-            .chain(self.always_on_tags.iter().cloned())
+        image_url: &Url,
+    ) -> crate::Result<Vec<ReverseSearchMatch>> {
+        let mut matches: Vec<ReverseSearchMatch> = Vec::new();
+
+        for distance in REVERSE_SEARCH_DISTANCES {
+            let res: rpc::search::Response = self
+                .http_client
+                .post(util::url_with_segments(
+                    &self.api_url,
+                    &["search", "reverse"],
+                ))
+                .query(&[
+                    ("url", image_url.as_str()),
+                    ("distance", &distance.to_string()),
+                    ("key", &self.derpibooru_api_key),
+                ])
+                .read_json()
+                .await?;
+
+            for image in res.images {
+                let id = image.id.to_string();
+                if matches.iter().any(|it| it.media.id == id) {
+                    continue;
+                }
+                matches.push(ReverseSearchMatch {
+                    source_url: image.source_url.clone(),
+                    media: image.into_media(),
+                    min_similarity: 1.0 - distance,
+                });
+            }
+
+            if matches.len() >= MAX_REVERSE_SEARCH_MATCHES {
+                break;
+            }
+        }
+
+        matches.truncate(MAX_REVERSE_SEARCH_MATCHES);
+        Ok(matches)
+    }
+}
+
+#[async_trait]
+impl Booru for DerpibooruService {
+    fn name(&self) -> &'static str {
+        "derpibooru"
+    }
+
+    fn site(&self) -> &'static str {
+        "derpibooru.org"
+    }
+
+    /// Fetches pony media (images or videos) based on the given tags (if there are any).
+    async fn fetch_media(&self, query: &BooruQuery) -> crate::Result<Vec<BooruMedia>> {
+        // The SFW filter may still let some suggestive images through,
+        // so we ask for the safe rating explicitly
+        let safe_tag: ThemeTag = "safe".parse().unwrap();
+        let rating_tag = if query.nsfw { None } else { Some(&safe_tag) };
+
+        let tags_with_always_on_ones = query
+            .search_tags()
+            .iter()
+            .chain(&self.always_on_tags)
+            .chain(rating_tag)
             .collect::<HashSet<_>>()
             .iter()
+            .map(ToString::to_string)
+            // The search syntax lets us exclude the recently shown images right away
+            .chain(query.excluded_ids.iter().map(|id| format!("-id:{}", id)))
             .join(",");
 
-        let mut query = vec![
-            ("sf", "random"),
-            ("per_page", "1"),
-            ("filter_id", &self.filter_id),
+        let filter_id = if query.nsfw {
+            &self.nsfw_filter_id
+        } else {
+            &self.sfw_filter_id
+        };
+
+        let sort_field = match query.sort {
+            BooruSort::Random => "random",
+            BooruSort::Score => "score",
+            BooruSort::Newest => "created_at",
+        };
+        let per_page = query.limit.to_string();
+
+        let mut params = vec![
+            ("sf", sort_field),
+            ("sd", "desc"),
+            ("per_page", &per_page),
+            ("filter_id", filter_id),
             ("key", &self.derpibooru_api_key),
         ];
 
         if !tags_with_always_on_ones.is_empty() {
-            query.push(("q", &tags_with_always_on_ones));
+            params.push(("q", &tags_with_always_on_ones));
         }
 
         let res: rpc::search::Response = self
             .http_client
-            .get(derpibooru_api(&["search", "images"]))
-            .query(&query)
+            .get(util::url_with_segments(
+                &self.api_url,
+                &["search", "images"],
+            ))
+            .query(&params)
+            .read_json()
+            .await?;
+
+        Ok(res.images.into_iter().map(rpc::Image::into_media).collect())
+    }
+
+    async fn find_similar_tags(&self, tag: &str) -> crate::Result<Vec<BooruTag>> {
+        let res: rpc::tags::Response = self
+            .http_client
+            .get(util::url_with_segments(&self.api_url, &["search", "tags"]))
+            .query(&[
+                ("q", similar_tags_query(tag).as_str()),
+                ("per_page", MAX_TAGS_PER_PAGE),
+                ("key", &self.derpibooru_api_key),
+            ])
             .read_json()
             .await?;
 
-        Ok(res.images.into_iter().next())
+        Ok(res.tags.into_iter().map(rpc::Tag::into_booru_tag).collect())
     }
 }
@@ -38,9 +38,14 @@ def_type_map_keys! {
     dep1, YtServiceToken => Arc<crate::yt::YtService>,
     dep2, AudioServiceToken => Arc<crate::audio_queue::AudioService>,
     dep3, DerpibooruServiceToken => Arc<crate::derpibooru::DerpibooruService>,
-    dep4, GelbooruServiceToken => Arc<crate::gelbooru::GelbooruService>,
+    dep4, BooruRegistryToken => Arc<crate::booru::BooruRegistry>,
     dep5, HttpClientToken => Arc<reqwest::Client>,
     dep6, ClientShardManagerToken => Arc<Mutex<ShardManager>>,
+    dep7, RadioServiceToken => Arc<crate::radio::RadioService>,
+    dep8, MusicLinkServiceToken => Arc<crate::music_links::MusicLinkService>,
+    dep9, LyricsServiceToken => Arc<crate::lyrics::LyricsService>,
+    dep10, YtSubscriptionServiceToken => Arc<crate::yt_subscriptions::YtSubscriptionService>,
+    dep11, BooruSubscriptionServiceToken => Arc<crate::booru_subscriptions::BooruSubscriptionService>,
 }
 
 /// Utility trait to reduce boilerplate for retrieving and acquiring locks
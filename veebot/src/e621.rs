@@ -0,0 +1,290 @@
+//! Symbols related to communicating with the e621 API (and its SFW mirror e926)
+
+use crate::{
+    booru::{Booru, BooruCredentials, BooruMedia, BooruQuery, BooruRating, BooruSort, BooruTag},
+    util::{self, ReqwestBuilderExt, ThemeTag},
+};
+use itertools::Itertools;
+use serenity::async_trait;
+use std::sync::Arc;
+use url::Url;
+
+/// Declarations of the e621 JSON API types.
+/// Reference: https://e621.net/help/api
+pub(crate) mod rpc {
+    use chrono::Utc;
+    use serde::Deserialize;
+    use url::Url;
+
+    pub(crate) mod search {
+        use super::*;
+
+        #[derive(Debug, Deserialize)]
+        pub(crate) struct Response {
+            pub(crate) posts: Vec<Post>,
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Post {
+        pub(crate) id: u128,
+        pub(crate) created_at: chrono::DateTime<Utc>,
+        pub(crate) file: File,
+        pub(crate) preview: Preview,
+        pub(crate) score: Score,
+        pub(crate) tags: Tags,
+        /// `s`, `q` or `e`
+        pub(crate) rating: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct File {
+        /// `jpg`, `png`, `gif`, `swf` or `webm`
+        pub(crate) ext: String,
+        /// Absent for the deleted posts and for the posts that are
+        /// hidden by the global blacklist from the anonymous users
+        pub(crate) url: Option<Url>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Preview {
+        pub(crate) url: Option<Url>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Score {
+        /// Upvotes minus downvotes
+        pub(crate) total: i64,
+    }
+
+    /// The tags are grouped by their categories
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Tags {
+        #[serde(default)]
+        pub(crate) artist: Vec<String>,
+        #[serde(default)]
+        pub(crate) copyright: Vec<String>,
+        #[serde(default)]
+        pub(crate) character: Vec<String>,
+        #[serde(default)]
+        pub(crate) species: Vec<String>,
+        #[serde(default)]
+        pub(crate) general: Vec<String>,
+        #[serde(default)]
+        pub(crate) meta: Vec<String>,
+        #[serde(default)]
+        pub(crate) lore: Vec<String>,
+    }
+
+    pub(crate) mod autocomplete {
+        use super::*;
+
+        #[derive(Debug, Deserialize)]
+        pub(crate) struct Response(pub(crate) Vec<Tag>);
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Tag {
+        pub(crate) name: String,
+        pub(crate) post_count: u64,
+        /// Alias of the tag that matched the query
+        pub(crate) antecedent_name: Option<String>,
+    }
+}
+
+/// e621 and e926 share the same API and the database, but e926 shows only the safe posts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum E621Site {
+    E621,
+    E926,
+}
+
+// Links that are shown to the users, the requests go to `E621Service::api_url`
+util::def_url_base!(e621, "https://e621.net");
+util::def_url_base!(e926, "https://e926.net");
+
+/// Some posts are visible only to the logged-in users (they have no file url),
+/// so we request this many more posts than needed to make up for the skipped ones.
+const EXTRA_POSTS: usize = 20;
+
+/// The autocomplete refuses to search by the shorter prefixes
+const MIN_AUTOCOMPLETE_LEN: usize = 3;
+
+impl E621Site {
+    fn name(self) -> &'static str {
+        match self {
+            E621Site::E621 => "e621",
+            E621Site::E926 => "e926",
+        }
+    }
+
+    fn domain(self) -> &'static str {
+        match self {
+            E621Site::E621 => "e621.net",
+            E621Site::E926 => "e926.net",
+        }
+    }
+
+    fn post_url(self, id: u128) -> Url {
+        let segments = ["posts".to_owned(), id.to_string()];
+        match self {
+            E621Site::E621 => e621(&segments),
+            E621Site::E926 => e926(&segments),
+        }
+    }
+}
+
+impl rpc::Post {
+    fn mime_type(&self) -> String {
+        match self.file.ext.as_str() {
+            "jpg" | "jpeg" => "image/jpeg",
+            "png" => "image/png",
+            "gif" => "image/gif",
+            "webm" => "video/webm",
+            "swf" => "application/x-shockwave-flash",
+            _ => "application/octet-stream",
+        }
+        .to_owned()
+    }
+
+    fn into_media(self, site: E621Site) -> Option<BooruMedia> {
+        let mime_type = self.mime_type();
+        let rpc::Tags {
+            artist,
+            copyright,
+            character,
+            species,
+            general,
+            meta,
+            lore,
+        } = self.tags;
+
+        Some(BooruMedia {
+            id: self.id.to_string(),
+            webpage_url: site.post_url(self.id),
+            media_url: self.file.url?,
+            thumbnail_url: self.preview.url,
+            tags: artist
+                .into_iter()
+                .chain(copyright)
+                .chain(character)
+                .chain(species)
+                .chain(general)
+                .chain(meta)
+                .chain(lore)
+                .collect(),
+            score: self.score.total,
+            rating: BooruRating::parse(&self.rating),
+            created_at: Some(self.created_at),
+            mime_type,
+        })
+    }
+}
+
+pub(crate) struct E621Service {
+    http_client: Arc<reqwest::Client>,
+    site: E621Site,
+    credentials: Option<BooruCredentials>,
+    /// Base url of the API (`https://e621.net` or `https://e926.net`)
+    api_url: Url,
+}
+
+impl E621Service {
+    pub(crate) fn new(
+        site: E621Site,
+        credentials: Option<BooruCredentials>,
+        api_url: Url,
+        http_client: Arc<reqwest::Client>,
+    ) -> Self {
+        Self {
+            http_client,
+            site,
+            credentials,
+            api_url,
+        }
+    }
+}
+
+#[async_trait]
+impl Booru for E621Service {
+    fn name(&self) -> &'static str {
+        self.site.name()
+    }
+
+    fn site(&self) -> &'static str {
+        self.site.domain()
+    }
+
+    async fn fetch_media(&self, query: &BooruQuery) -> crate::Result<Vec<BooruMedia>> {
+        let search_tags = query.underscored_search_tags();
+        let mut tags = search_tags
+            .iter()
+            .map(ThemeTag::as_str)
+            .unique()
+            .collect::<Vec<_>>();
+
+        match query.sort {
+            BooruSort::Random => tags.push("order:random"),
+            BooruSort::Score => tags.push("order:score"),
+            // The newest posts go first by default
+            BooruSort::Newest => {}
+        }
+
+        // e926 returns only the safe posts anyway
+        if !query.nsfw && self.site == E621Site::E621 {
+            tags.push("rating:s");
+        }
+
+        let tags = tags.join(" ");
+        let limit = (query.limit + EXTRA_POSTS).to_string();
+
+        let mut request = self
+            .http_client
+            .get(util::url_with_segments(&self.api_url, &["posts.json"]))
+            .query(&[("tags", &tags), ("limit", &limit)]);
+
+        if let Some(credentials) = &self.credentials {
+            request = request.basic_auth(&credentials.login, Some(&credentials.api_key));
+        }
+
+        let res: rpc::search::Response = request.read_json().await?;
+
+        let site = self.site;
+        Ok(res
+            .posts
+            .into_iter()
+            .filter_map(|it| it.into_media(site))
+            .take(query.limit)
+            .collect())
+    }
+
+    async fn find_similar_tags(&self, tag: &str) -> crate::Result<Vec<BooruTag>> {
+        if tag.chars().count() < MIN_AUTOCOMPLETE_LEN {
+            return Ok(Vec::new());
+        }
+
+        let mut request = self
+            .http_client
+            .get(util::url_with_segments(
+                &self.api_url,
+                &["tags", "autocomplete.json"],
+            ))
+            .query(&[("search[name_matches]", tag)]);
+
+        if let Some(credentials) = &self.credentials {
+            request = request.basic_auth(&credentials.login, Some(&credentials.api_key));
+        }
+
+        let res: rpc::autocomplete::Response = request.read_json().await?;
+
+        Ok(res
+            .0
+            .into_iter()
+            .map(|it| BooruTag {
+                name: it.name,
+                post_count: it.post_count,
+                alias: it.antecedent_name,
+            })
+            .collect())
+    }
+}
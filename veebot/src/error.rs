@@ -1,8 +1,15 @@
 use std::num::ParseIntError;
 
 use serenity::{
-    builder::CreateMessage, framework::standard::ArgError, model::id::GuildId, utils::Color,
+    builder::CreateMessage,
+    framework::standard::ArgError,
+    model::{
+        id::{ChannelId, GuildId},
+        misc::ChannelIdParseError,
+    },
+    utils::Color,
 };
+use std::{borrow::Cow, path::PathBuf};
 use thiserror::Error;
 use url::Url;
 // We have to rename it because `thiserror` implements
@@ -48,8 +55,40 @@ impl<T: Into<ErrorKind>> From<T> for Error {
             | ErrorKind::CommaInImageTag { .. }
             | ErrorKind::InvalidNumberOfArguments { .. }
             | ErrorKind::UserNotInVoiceChanel { .. }
-            | ErrorKind::NoActiveTrack { .. } => true,
+            | ErrorKind::NoActiveTrack { .. }
+            | ErrorKind::BotNotInVoiceChannel { .. }
+            | ErrorKind::RecordingAlreadyActive { .. }
+            | ErrorKind::NoActiveRecording { .. }
+            | ErrorKind::UnknownRecordingMode { .. }
+            | ErrorKind::ParseChannelId { .. }
+            | ErrorKind::ParseTimeOfDay { .. }
+            | ErrorKind::RadioPlaylistEmpty { .. }
+            | ErrorKind::InvalidArgumentValue { .. }
+            | ErrorKind::UnexpectedChannel { .. }
+            | ErrorKind::TrackTooLong { .. }
+            | ErrorKind::LivestreamsNotAllowed { .. }
+            | ErrorKind::TrackChannelBlocked { .. }
+            | ErrorKind::TrackKeywordBlocked { .. }
+            | ErrorKind::TrackAlreadyQueued { .. }
+            | ErrorKind::UnsupportedMusicLink { .. }
+            | ErrorKind::SpotifyNotConfigured { .. }
+            | ErrorKind::NoTracksInMusicLink { .. }
+            | ErrorKind::YtdlExtract { .. }
+            | ErrorKind::LyricsNotFound { .. }
+            | ErrorKind::YtInferChannelId { .. }
+            | ErrorKind::YtAlreadySubscribed { .. }
+            | ErrorKind::YtSubscriptionNotFound { .. }
+            | ErrorKind::BooruAlreadySubscribed { .. }
+            | ErrorKind::BooruSubscriptionNotFound { .. }
+            | ErrorKind::NoChapters { .. }
+            | ErrorKind::ChapterIndexOutOfBounds { .. }
+            | ErrorKind::YtVideoUnplayable { .. }
+            | ErrorKind::UnknownBooru { .. }
+            | ErrorKind::NsfwTagInSfwChannel { .. }
+            | ErrorKind::BooruTagBlocked { .. }
+            | ErrorKind::NoImageToSearch => true,
             ErrorKind::JoinVoiceChannel { .. }
+            | ErrorKind::NoOutChannel { .. }
             | ErrorKind::TokioJoinError { .. }
             | ErrorKind::TextureSynthesis { .. }
             | ErrorKind::AudioStart { .. }
@@ -60,7 +99,15 @@ impl<T: Into<ErrorKind>> From<T> for Error {
             | ErrorKind::UnexpectedHttpResponseJsonShape { .. }
             | ErrorKind::YtVidNotFound { .. }
             | ErrorKind::YtInferVideoId { .. }
-            | ErrorKind::DiscordGuildCacheMiss { .. } => false,
+            | ErrorKind::YtQuotaExhausted { .. }
+            | ErrorKind::YtVideoDuration { .. }
+            | ErrorKind::DiscordGuildCacheMiss { .. }
+            | ErrorKind::SaveRecording { .. }
+            | ErrorKind::EncodeRecording { .. }
+            | ErrorKind::PersistentStoreIo { .. }
+            | ErrorKind::PersistentStoreJson { .. }
+            | ErrorKind::ReadAudioDir { .. }
+            | ErrorKind::ProbeAudioFile { .. } => false,
         };
 
         // No need for a backtrace if the error is an expected one
@@ -109,9 +156,32 @@ pub enum ErrorKind {
     #[error("Failed to parse the argument as url: {0}")]
     ParseUrl(#[from] ArgError<url::ParseError>),
 
+    #[error("Failed to parse the argument as a channel: {0}")]
+    ParseChannelId(#[from] ArgError<ChannelIdParseError>),
+
+    #[error(
+        "Failed to parse `{input}` as a time of the day (expected format is `HH:MM`): {source}"
+    )]
+    ParseTimeOfDay {
+        input: String,
+        source: chrono::ParseError,
+    },
+
     #[error("The specified image tags contain a comma (which is prohibited): {input}")]
     CommaInImageTag { input: String },
 
+    #[error("Invalid argument `{input}`, expected {expected}")]
+    InvalidArgumentValue {
+        input: String,
+        expected: Cow<'static, str>,
+    },
+
+    #[error("<#{channel}> is not a {expected} channel of this server")]
+    UnexpectedChannel {
+        channel: ChannelId,
+        expected: &'static str,
+    },
+
     #[error("Expected: {expected} arguments, but got {actual}")]
     InvalidNumberOfArguments { expected: usize, actual: usize },
 
@@ -137,6 +207,12 @@ pub enum ErrorKind {
         available: Option<std::ops::Range<usize>>,
     },
 
+    #[error("The current track has no chapters in its description")]
+    NoChapters,
+
+    #[error("There is no chapter `#{index}`, the current track has {available} chapters")]
+    ChapterIndexOutOfBounds { index: usize, available: usize },
+
     #[error(
         "You are not in a voice channel. You need to connect to one first so that \
         I can understand which channel to join."
@@ -146,9 +222,66 @@ pub enum ErrorKind {
     #[error("I cannot join the voice channel {}", .0.as_deref().unwrap_or("<unknown channel name>"))]
     JoinVoiceChannel(Option<String>),
 
+    #[error("There is no text channel in the server {0} where I can send messages")]
+    NoOutChannel(GuildId),
+
     #[error("Falied to start streaming the audio: {0}")]
     AudioStart(serenity::Error),
 
+    #[error(
+        "I am not in a voice channel right now. Order some track first, \
+        so that I join your channel."
+    )]
+    BotNotInVoiceChannel,
+
+    #[error("The voice channel is already being recorded")]
+    RecordingAlreadyActive,
+
+    #[error("The voice channel is not being recorded right now")]
+    NoActiveRecording,
+
+    #[error("Unknown recording mode `{input}`, expected one of: `mixed`, `split`")]
+    UnknownRecordingMode { input: String },
+
+    #[error("Failed to save the recording to the disk: {0}")]
+    SaveRecording(std::io::Error),
+
+    #[error("Failed to encode the recording `{0}`: {1}")]
+    EncodeRecording(String, String),
+
+    #[error(
+        "The track is too long ({duration}), the maximum allowed duration \
+        on this server is {max}"
+    )]
+    TrackTooLong { duration: String, max: String },
+
+    #[error("Livestreams are not allowed on this server")]
+    LivestreamsNotAllowed,
+
+    #[error("Tracks from the channel `{channel}` are blocked on this server")]
+    TrackChannelBlocked { channel: String },
+
+    #[error("Tracks with `{keyword}` in the title are blocked on this server")]
+    TrackKeywordBlocked { keyword: String },
+
+    #[error("This track is already in the queue at position `#{index}`")]
+    TrackAlreadyQueued { index: usize },
+
+    #[error("The radio has nothing to play, its playlist is empty")]
+    RadioPlaylistEmpty,
+
+    #[error("Failed to read the directory with audio files `{}`: {}", .0.display(), .1)]
+    ReadAudioDir(PathBuf, std::io::Error),
+
+    #[error("Failed to read the metadata of the audio file `{}`: {}", .0.display(), .1)]
+    ProbeAudioFile(PathBuf, String),
+
+    #[error("Failed to access the persistent store file: {0}")]
+    PersistentStoreIo(std::io::Error),
+
+    #[error("The persistent store file contains invalid JSON: {0}")]
+    PersistentStoreJson(serde_json::Error),
+
     #[error("Failed to get information about the guild {0} from the cache")]
     DiscordGuildCacheMiss(GuildId),
 
@@ -175,6 +308,62 @@ pub enum ErrorKind {
 
     #[error("Could not infer YouTube video id from the url `{0}`")]
     YtInferVideoId(Url),
+
+    #[error("Could not infer YouTube channel id from the url `{0}`")]
+    YtInferChannelId(Url),
+
+    #[error("Unexpected duration of the YouTube video: `{0}`")]
+    YtVideoDuration(String),
+
+    #[error("This server is already subscribed to the channel `{0}`")]
+    YtAlreadySubscribed(String),
+
+    #[error("This server is not subscribed to the channel `{0}`")]
+    YtSubscriptionNotFound(String),
+
+    #[error("The channel is already subscribed to the new uploads with tags `{0}`")]
+    BooruAlreadySubscribed(String),
+
+    #[error("There is no subscription number {0}, see the list of the subscriptions")]
+    BooruSubscriptionNotFound(usize),
+
+    #[error("The url `{0}` is not a link to a Spotify or Apple Music track, album or playlist")]
+    UnsupportedMusicLink(Url),
+
+    #[error("Spotify links are not supported, because the bot has no Spotify API credentials")]
+    SpotifyNotConfigured,
+
+    #[error("No tracks were found by the link `{0}`")]
+    NoTracksInMusicLink(Url),
+
+    #[error("Failed to extract the track from `{0}` via youtube-dl:\n{1}")]
+    YtdlExtract(String, String),
+
+    #[error("\"{title}\" can't be played: {reason}")]
+    YtVideoUnplayable {
+        title: String,
+        reason: crate::yt::YtUnplayableReason,
+    },
+
+    #[error("All YouTube Data API keys have exhausted their quota or are rate limited")]
+    YtQuotaExhausted,
+
+    #[error("No lyrics were found for \"{0}\"")]
+    LyricsNotFound(String),
+
+    #[error("Unknown booru `{name}`, available ones: {available}")]
+    UnknownBooru { name: String, available: String },
+
+    #[error("The tag `{0}` matches only NSFW media, use it in a channel marked as NSFW")]
+    NsfwTagInSfwChannel(String),
+
+    #[error(
+        "The tag `{0}` is in the blocklist of this server or yours, see `tag_blocklist` command"
+    )]
+    BooruTagBlocked(String),
+
+    #[error("Specify the url of the image, attach it or reply to the message with it")]
+    NoImageToSearch,
 }
 
 impl ErrorKind {
@@ -183,25 +372,68 @@ impl ErrorKind {
         match self {
             ErrorKind::TokioJoinError { .. } => "Async task join error",
             ErrorKind::TextureSynthesis { .. } => "Texture synthesis error",
-            ErrorKind::NoActiveTrack { .. } => "Invalid command error",
+            ErrorKind::NoActiveTrack { .. }
+            | ErrorKind::BotNotInVoiceChannel { .. }
+            | ErrorKind::RecordingAlreadyActive { .. }
+            | ErrorKind::NoActiveRecording { .. }
+            | ErrorKind::RadioPlaylistEmpty { .. }
+            | ErrorKind::NoChapters { .. } => "Invalid command error",
             ErrorKind::UserNotInGuild { .. } => "Not in a guild error",
             ErrorKind::ParseArg { .. }
             | ErrorKind::ParseInt { .. }
             | ErrorKind::ParseUrl { .. }
             | ErrorKind::CommaInImageTag { .. }
-            | ErrorKind::TrackIndexOutOfBounds { .. } => "Invalid argument error",
+            | ErrorKind::UnknownRecordingMode { .. }
+            | ErrorKind::ParseChannelId { .. }
+            | ErrorKind::ParseTimeOfDay { .. }
+            | ErrorKind::InvalidArgumentValue { .. }
+            | ErrorKind::UnexpectedChannel { .. }
+            | ErrorKind::TrackIndexOutOfBounds { .. }
+            | ErrorKind::ChapterIndexOutOfBounds { .. }
+            | ErrorKind::UnknownBooru { .. }
+            | ErrorKind::NoImageToSearch => "Invalid argument error",
+            ErrorKind::TrackTooLong { .. }
+            | ErrorKind::LivestreamsNotAllowed { .. }
+            | ErrorKind::TrackChannelBlocked { .. }
+            | ErrorKind::TrackKeywordBlocked { .. } => "Track rejected by the server policy",
+            ErrorKind::TrackAlreadyQueued { .. } => "Duplicate track error",
             ErrorKind::InvalidNumberOfArguments { .. } => "Invalid number of arguments error",
             ErrorKind::UserNotInVoiceChanel => "Not in a voice channel error",
-            ErrorKind::JoinVoiceChannel { .. } => "Permissions error",
+            ErrorKind::JoinVoiceChannel { .. } | ErrorKind::NoOutChannel { .. } => {
+                "Permissions error"
+            }
             ErrorKind::AudioStart { .. }
+            | ErrorKind::SaveRecording { .. }
+            | ErrorKind::EncodeRecording { .. }
+            | ErrorKind::PersistentStoreIo { .. }
+            | ErrorKind::PersistentStoreJson { .. }
+            | ErrorKind::ReadAudioDir { .. }
+            | ErrorKind::ProbeAudioFile { .. }
             | ErrorKind::UnknownDiscord { .. }
             | ErrorKind::DiscordGuildCacheMiss { .. } => "Internal error",
             ErrorKind::SendHttpRequest { .. } => "HTTP error (sending request)",
             ErrorKind::ReadHttpResponse { .. } => "HTTP error (reading response)",
             ErrorKind::BadHttpResponseStatusCode { .. }
             | ErrorKind::UnexpectedHttpResponseJsonShape { .. } => "HTTP error (status code)",
-            ErrorKind::YtVidNotFound { .. } => "YouTube error",
-            ErrorKind::YtInferVideoId { .. } => "Bad YouTube URL",
+            ErrorKind::YtVidNotFound { .. }
+            | ErrorKind::YtQuotaExhausted { .. }
+            | ErrorKind::YtVideoDuration { .. } => "YouTube error",
+            ErrorKind::YtVideoUnplayable { .. } => "Unplayable YouTube video",
+            ErrorKind::YtInferVideoId { .. } | ErrorKind::YtInferChannelId { .. } => {
+                "Bad YouTube URL"
+            }
+            ErrorKind::YtAlreadySubscribed { .. } | ErrorKind::YtSubscriptionNotFound { .. } => {
+                "YouTube subscription error"
+            }
+            ErrorKind::BooruAlreadySubscribed { .. }
+            | ErrorKind::BooruSubscriptionNotFound { .. } => "Booru subscription error",
+            ErrorKind::UnsupportedMusicLink { .. }
+            | ErrorKind::SpotifyNotConfigured { .. }
+            | ErrorKind::NoTracksInMusicLink { .. } => "Music link error",
+            ErrorKind::YtdlExtract { .. } => "Unsupported track URL",
+            ErrorKind::LyricsNotFound { .. } => "Lyrics error",
+            ErrorKind::NsfwTagInSfwChannel { .. } => "NSFW content error",
+            ErrorKind::BooruTagBlocked { .. } => "Blocked tag error",
         }
     }
 }
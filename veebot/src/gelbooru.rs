@@ -1,7 +1,12 @@
 //! Symbols related to communicating with the Gelbooru API
 
-use crate::util::{self, ReqwestBuilderExt, ThemeTag};
+use crate::{
+    booru::{self, Booru, BooruMedia, BooruQuery, BooruRating, BooruSort, BooruTag},
+    util::{self, ReqwestBuilderExt, ThemeTag},
+};
+use chrono::{DateTime, Utc};
 use itertools::Itertools;
+use serenity::async_trait;
 use std::{collections::HashSet, iter, sync::Arc};
 use url::Url;
 
@@ -21,14 +26,34 @@ pub(crate) mod rpc {
     pub(crate) struct Image {
         pub(crate) id: u128,
         pub(crate) file_url: Url,
+        pub(crate) preview_url: Option<Url>,
+        /// Space-separated list of tags
         pub(crate) tags: String,
-        // FIXME: properly parse the date
+        /// `s`, `q` or `e` (or `general`, `sensitive`, `questionable`, `explicit`)
+        pub(crate) rating: String,
+        /// Formatted like `Wed Oct 14 13:51:08 -0500 2020`
         pub(crate) created_at: String,
-        pub(crate) score: u64,
+        pub(crate) score: i64,
+    }
+
+    pub(crate) mod tags {
+        use super::*;
+
+        #[derive(Debug, Deserialize)]
+        pub(crate) struct Response(pub(crate) Vec<Tag>);
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Tag {
+        /// The older versions of the API call it `tag`
+        #[serde(alias = "tag")]
+        pub(crate) name: String,
+        /// Number of the images with this tag
+        pub(crate) count: u64,
     }
 }
 
-util::def_url_base!(gelbooru_api, "https://gelbooru.com/index.php");
+// Links that are shown to the users, the requests go to `GelbooruService::api_url`
 util::def_url_base!(gelbooru, "https://gelbooru.com/index.php");
 
 impl rpc::Image {
@@ -41,44 +66,95 @@ impl rpc::Image {
         ]);
         url
     }
+
+    fn created_at(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_str(&self.created_at, "%a %b %d %H:%M:%S %z %Y")
+            .ok()
+            .map(|it| it.with_timezone(&Utc))
+    }
+
+    fn into_media(self) -> BooruMedia {
+        BooruMedia {
+            id: self.id.to_string(),
+            webpage_url: self.webpage_url(),
+            created_at: self.created_at(),
+            rating: BooruRating::parse(&self.rating),
+            mime_type: booru::mime_type_from_url(&self.file_url),
+            media_url: self.file_url,
+            thumbnail_url: self.preview_url,
+            tags: self
+                .tags
+                .split_whitespace()
+                .map(ToOwned::to_owned)
+                .collect(),
+            score: self.score,
+        }
+    }
 }
 
+/// Gelbooru has no typo-tolerant tag search, so we search for the tags that
+/// start with this many of the same chars as the given one
+const SIMILAR_TAGS_PREFIX_LEN: usize = 3;
+
 pub(crate) struct GelbooruService {
     http_client: Arc<reqwest::Client>,
     gelbooru_api_key: String,
     gelbooru_user_id: String,
+    /// Url of the API endpoint (`https://gelbooru.com/index.php`)
+    api_url: Url,
 }
 
 impl GelbooruService {
     pub(crate) fn new(
         gelbooru_api_key: String,
         gelbooru_user_id: String,
+        api_url: Url,
         http_client: Arc<reqwest::Client>,
     ) -> Self {
         Self {
             http_client,
             gelbooru_api_key,
             gelbooru_user_id,
+            api_url,
         }
     }
+}
+
+#[async_trait]
+impl Booru for GelbooruService {
+    fn name(&self) -> &'static str {
+        "gelbooru"
+    }
+
+    fn site(&self) -> &'static str {
+        "gelbooru.com"
+    }
 
-    pub(crate) async fn fetch_random_media(
-        &self,
-        tags: impl IntoIterator<Item = ThemeTag>,
-    ) -> crate::Result<Option<rpc::Image>> {
-        let mut tags = tags.into_iter().collect::<HashSet<_>>();
+    async fn fetch_media(&self, query: &BooruQuery) -> crate::Result<Vec<BooruMedia>> {
+        let mut tags = query
+            .underscored_search_tags()
+            .into_iter()
+            .collect::<HashSet<_>>();
 
-        if !tags.iter().any(|it| it.as_str().starts_with("sort:")) {
-            tags.insert("sort:random".parse().unwrap());
+        let sort_tag = match query.sort {
+            BooruSort::Random => "sort:random",
+            BooruSort::Score => "sort:score",
+            BooruSort::Newest => "sort:id",
+        };
+        tags.insert(sort_tag.parse().unwrap());
+
+        if !query.nsfw {
+            tags.insert("rating:general".parse().unwrap());
         }
 
         let tags = tags.iter().join(" ");
+        let limit = query.limit.to_string();
 
-        let query = vec![
+        let params = vec![
             ("page", "dapi"),
             ("s", "post"),
             ("q", "index"),
-            ("limit", "1"),
+            ("limit", &limit),
             ("json", "1"),
             ("tags", &tags),
             ("api_key", &self.gelbooru_api_key),
@@ -87,19 +163,62 @@ impl GelbooruService {
 
         let res: crate::Result<rpc::search::Response> = self
             .http_client
-            .get(gelbooru_api(iter::empty::<&str>()))
-            .query(&query)
+            .get(self.api_url.clone())
+            .query(&params)
             .read_json()
             .await;
 
         match res {
-            Ok(it) => Ok(it.0.into_iter().next()),
+            Ok(it) => Ok(it.0.into_iter().map(rpc::Image::into_media).collect()),
             // When no image was found, the response has empty body...
             Err(crate::Error {
                 kind: crate::ErrorKind::UnexpectedHttpResponseJsonShape(_),
                 ..
-            }) => Ok(None),
+            }) => Ok(Vec::new()),
             Err(it) => Err(it),
         }
     }
+
+    async fn find_similar_tags(&self, tag: &str) -> crate::Result<Vec<BooruTag>> {
+        let prefix: String = tag.chars().take(SIMILAR_TAGS_PREFIX_LEN).collect();
+        let name_pattern = format!("{}%", prefix.to_lowercase());
+
+        let params = vec![
+            ("page", "dapi"),
+            ("s", "tag"),
+            ("q", "index"),
+            ("json", "1"),
+            ("name_pattern", &name_pattern),
+            ("orderby", "count"),
+            ("limit", "100"),
+            ("api_key", &self.gelbooru_api_key),
+            ("user_id", &self.gelbooru_user_id),
+        ];
+
+        let res: crate::Result<rpc::tags::Response> = self
+            .http_client
+            .get(self.api_url.clone())
+            .query(&params)
+            .read_json()
+            .await;
+
+        let tags = match res {
+            Ok(it) => it.0,
+            // Same as for the images, the response has empty body if nothing was found
+            Err(crate::Error {
+                kind: crate::ErrorKind::UnexpectedHttpResponseJsonShape(_),
+                ..
+            }) => return Ok(Vec::new()),
+            Err(it) => return Err(it),
+        };
+
+        Ok(tags
+            .into_iter()
+            .map(|it| BooruTag {
+                name: it.name,
+                post_count: it.count,
+                alias: None,
+            })
+            .collect())
+    }
 }
@@ -1,14 +1,37 @@
+pub(crate) mod audio_policy;
 pub(crate) mod audio_queue;
+pub(crate) mod booru;
+pub(crate) mod booru_subscriptions;
+pub(crate) mod chapters;
 pub(crate) mod commands;
+pub(crate) mod danbooru;
 pub(crate) mod derpibooru;
 pub(crate) mod di;
+pub(crate) mod e621;
 pub(crate) mod error;
 pub(crate) mod gelbooru;
+pub(crate) mod local_audio;
+pub(crate) mod lyrics;
+pub(crate) mod music_links;
+pub(crate) mod pagination;
+pub(crate) mod radio;
+pub(crate) mod recording;
+pub(crate) mod safebooru;
+pub(crate) mod store;
+pub(crate) mod twibooru;
 pub(crate) mod util;
 pub(crate) mod yt;
+pub(crate) mod yt_cache;
+pub(crate) mod yt_quota;
+pub(crate) mod yt_subscriptions;
+pub(crate) mod ytdl;
+
+#[cfg(test)]
+mod tests;
 
 pub(crate) use crate::error::{err, Error, ErrorKind, Result};
-use audio_queue::AudioService;
+use audio_queue::{AudioQueueCmd, AudioService};
+use di::DiExt;
 use serde::Deserialize;
 use serenity::{
     async_trait,
@@ -19,10 +42,12 @@ use serenity::{
     http::Http,
     model::channel::Message,
     model::gateway::Ready,
-    model::id::UserId,
+    model::id::{GuildId, UserId},
+    model::voice::VoiceState,
 };
-use std::{collections::HashSet, iter, sync::Arc};
+use std::{collections::HashSet, iter, path::PathBuf, sync::Arc};
 use tracing::{info, warn};
+use url::Url;
 
 #[derive(Debug)]
 struct Handler;
@@ -32,6 +57,27 @@ impl EventHandler for Handler {
     async fn ready(&self, _: Context, ready_event: Ready) {
         info!(?ready_event, "🚀 Discord bot is listening!");
     }
+
+    async fn voice_state_update(
+        &self,
+        ctx: Context,
+        guild_id: Option<GuildId>,
+        _old: Option<VoiceState>,
+        new: VoiceState,
+    ) {
+        let guild_id = match guild_id {
+            Some(it) => it,
+            None => return,
+        };
+        let audio = ctx.data.expect_dep::<di::AudioServiceToken>().await;
+
+        // There is nothing to do if no music was ever played in this guild
+        if let Some(queue) = audio.queue(guild_id).await {
+            queue
+                .unbounded_send(AudioQueueCmd::VoiceStateUpdate(new))
+                .unwrap();
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -39,11 +85,57 @@ pub struct Config {
     discord_bot_token: String,
     cmd_prefix: String,
     yt_data_api_key: String,
+    /// Additional keys that are used when the main one has exhausted its daily quota
+    #[serde(default)]
+    yt_data_api_extra_keys: Vec<String>,
     derpibooru_api_key: String,
     derpibooru_always_on_tags: HashSet<String>,
+    /// Filter that is used in the SFW channels
     derpibooru_filter: String,
+    /// Filter that is used in the NSFW channels (the same as `derpibooru_filter` by default,
+    /// so that the explicit media is not shown unless the bot owner asks for it)
+    derpibooru_nsfw_filter: Option<String>,
     gelbooru_api_key: String,
     gelbooru_user_id: String,
+    /// Optional credentials of the other boorus, they are used anonymously if not set
+    danbooru_login: Option<String>,
+    danbooru_api_key: Option<String>,
+    e621_login: Option<String>,
+    e621_api_key: Option<String>,
+    safebooru_user_id: Option<String>,
+    safebooru_api_key: Option<String>,
+    twibooru_api_key: Option<String>,
+    /// Directory where voice recordings that are too big to upload to discord are saved
+    recordings_dir: Option<PathBuf>,
+    /// Directory where the bot state that should survive restarts is stored
+    data_dir: Option<PathBuf>,
+    /// Spotify API credentials, Spotify links are not supported if they are not set
+    spotify_client_id: Option<String>,
+    spotify_client_secret: Option<String>,
+    /// Overrides for the base urls of the music services (e.g. to point them to a local stub)
+    spotify_api_url: Option<Url>,
+    spotify_accounts_url: Option<Url>,
+    itunes_api_url: Option<Url>,
+    /// Override for the base url of the lyrics provider
+    lyrics_api_url: Option<Url>,
+    /// Max number of the images that may be requested with a single booru command (10 by default)
+    booru_max_gallery_size: Option<usize>,
+    /// Overrides for the base urls of YouTube and the image boards
+    yt_api_url: Option<Url>,
+    yt_url: Option<Url>,
+    derpibooru_api_url: Option<Url>,
+    gelbooru_api_url: Option<Url>,
+    danbooru_api_url: Option<Url>,
+    e621_api_url: Option<Url>,
+    e926_api_url: Option<Url>,
+    safebooru_api_url: Option<Url>,
+    twibooru_api_url: Option<Url>,
+    /// Save the YouTube API responses cache to `data_dir`, so that it survives restarts
+    #[serde(default)]
+    yt_cache_persistent: bool,
+    /// ISO 3166-1 alpha-2 code of the country the bot streams the audio from.
+    /// Region-locked YouTube videos are rejected only if it is set.
+    yt_region_code: Option<String>,
 }
 
 /// Run the discord bot event loop
@@ -56,10 +148,11 @@ pub async fn run(config: Config) -> eyre::Result<()> {
             c.owners(iter::once(bot_app_info.owner.id).collect())
                 .prefix(&config.cmd_prefix)
         })
-        .group(&commands::GENERAL_GROUP)
+        .group(&commands::booru::BOORU_GROUP)
         .group(&commands::META_GROUP)
         .group(&commands::audio::AUDIO_GROUP)
         .group(&commands::image::IMAGE_GROUP)
+        .group(&commands::yt::YT_GROUP)
         .help(&HELP);
 
     let mut client = Client::builder(config.discord_bot_token)
@@ -73,14 +166,24 @@ pub async fn run(config: Config) -> eyre::Result<()> {
 
     let http_client = Arc::new(util::create_http_client());
 
+    let data_dir = config.data_dir.unwrap_or_else(|| PathBuf::from("data"));
+
+    let derpibooru_nsfw_filter = config
+        .derpibooru_nsfw_filter
+        .unwrap_or_else(|| config.derpibooru_filter.clone());
+
     let derpibooru_service = Arc::new(derpibooru::DerpibooruService::new(
         config.derpibooru_api_key,
         config.derpibooru_filter,
+        derpibooru_nsfw_filter,
         config
             .derpibooru_always_on_tags
             .into_iter()
             .map(|it| it.parse().unwrap())
             .collect(),
+        config
+            .derpibooru_api_url
+            .unwrap_or_else(|| "https://derpibooru.org/api/v1/json".parse().unwrap()),
         Arc::clone(&http_client),
     ));
 
@@ -89,8 +192,151 @@ pub async fn run(config: Config) -> eyre::Result<()> {
         Arc::clone(&client.cache_and_http),
         Arc::clone(&derpibooru_service),
         bot_user,
+        config
+            .recordings_dir
+            .unwrap_or_else(|| PathBuf::from("recordings")),
+        store::JsonStore::open(data_dir.join("audio_policy.json")).await?,
+    ));
+
+    let yt_cache = yt_cache::YtCache::open(if config.yt_cache_persistent {
+        Some(data_dir.join("yt_cache.json"))
+    } else {
+        None
+    })
+    .await?;
+
+    let yt_service = Arc::new(yt::YtService::new(
+        yt_quota::YtApiKeyPool::new(
+            iter::once(config.yt_data_api_key).chain(config.yt_data_api_extra_keys),
+        ),
+        yt_cache,
+        config.yt_region_code,
+        config
+            .yt_api_url
+            .unwrap_or_else(|| "https://www.googleapis.com/youtube/v3".parse().unwrap()),
+        config
+            .yt_url
+            .unwrap_or_else(|| "https://www.youtube.com".parse().unwrap()),
+        Arc::clone(&http_client),
+    ));
+
+    tokio::spawn(Arc::clone(&yt_service).run_cache_saver());
+
+    let radio_service = Arc::new(radio::RadioService::new(
+        store::JsonStore::open(data_dir.join("radio.json")).await?,
+        Arc::clone(&audio_service),
+        Arc::clone(&yt_service),
+        Arc::clone(&client.cache_and_http.http),
+    ));
+
+    tokio::spawn(Arc::clone(&radio_service).run_scheduler());
+
+    let yt_subscription_service = Arc::new(yt_subscriptions::YtSubscriptionService::new(
+        store::JsonStore::open(data_dir.join("yt_subscriptions.json")).await?,
+        Arc::clone(&yt_service),
+        Arc::clone(&client.cache_and_http.http),
+    ));
+
+    tokio::spawn(Arc::clone(&yt_subscription_service).run_poller());
+
+    let spotify_credentials = match (config.spotify_client_id, config.spotify_client_secret) {
+        (Some(client_id), Some(client_secret)) => Some(music_links::SpotifyCredentials {
+            client_id,
+            client_secret,
+        }),
+        _ => None,
+    };
+
+    // The same account is used for e621 and its SFW mirror e926
+    let e621_credentials =
+        booru::BooruCredentials::from_parts(config.e621_login, config.e621_api_key);
+
+    let boorus: Vec<Arc<dyn booru::Booru>> = vec![
+        derpibooru_service.clone(),
+        Arc::new(gelbooru::GelbooruService::new(
+            config.gelbooru_api_key,
+            config.gelbooru_user_id,
+            config
+                .gelbooru_api_url
+                .unwrap_or_else(|| "https://gelbooru.com/index.php".parse().unwrap()),
+            Arc::clone(&http_client),
+        )),
+        Arc::new(danbooru::DanbooruService::new(
+            booru::BooruCredentials::from_parts(config.danbooru_login, config.danbooru_api_key),
+            config
+                .danbooru_api_url
+                .unwrap_or_else(|| "https://danbooru.donmai.us".parse().unwrap()),
+            Arc::clone(&http_client),
+        )),
+        Arc::new(e621::E621Service::new(
+            e621::E621Site::E621,
+            e621_credentials.clone(),
+            config
+                .e621_api_url
+                .unwrap_or_else(|| "https://e621.net".parse().unwrap()),
+            Arc::clone(&http_client),
+        )),
+        Arc::new(e621::E621Service::new(
+            e621::E621Site::E926,
+            e621_credentials,
+            config
+                .e926_api_url
+                .unwrap_or_else(|| "https://e926.net".parse().unwrap()),
+            Arc::clone(&http_client),
+        )),
+        Arc::new(safebooru::SafebooruService::new(
+            booru::BooruCredentials::from_parts(config.safebooru_user_id, config.safebooru_api_key),
+            config
+                .safebooru_api_url
+                .unwrap_or_else(|| "https://safebooru.org/index.php".parse().unwrap()),
+            Arc::clone(&http_client),
+        )),
+        Arc::new(twibooru::TwibooruService::new(
+            config.twibooru_api_key,
+            config
+                .twibooru_api_url
+                .unwrap_or_else(|| "https://twibooru.org/api/v3".parse().unwrap()),
+            Arc::clone(&http_client),
+        )),
+    ];
+
+    let booru_registry = Arc::new(booru::BooruRegistry::new(
+        boorus,
+        config.booru_max_gallery_size.unwrap_or(10),
+        store::JsonStore::open(data_dir.join("booru_blocklists.json")).await?,
     ));
 
+    let booru_subscription_service = Arc::new(booru_subscriptions::BooruSubscriptionService::new(
+        store::JsonStore::open(data_dir.join("booru_subscriptions.json")).await?,
+        Arc::clone(&booru_registry),
+        Arc::clone(&client.cache_and_http.http),
+    ));
+
+    tokio::spawn(Arc::clone(&booru_subscription_service).run_poller());
+
+    let music_link_service = Arc::new(music_links::MusicLinkService::new(
+        spotify_credentials,
+        config
+            .spotify_api_url
+            .unwrap_or_else(|| "https://api.spotify.com/v1".parse().unwrap()),
+        config
+            .spotify_accounts_url
+            .unwrap_or_else(|| "https://accounts.spotify.com".parse().unwrap()),
+        config
+            .itunes_api_url
+            .unwrap_or_else(|| "https://itunes.apple.com".parse().unwrap()),
+        Arc::clone(&http_client),
+    ));
+
+    let lyrics_service = Arc::new(lyrics::LyricsService::new(Box::new(
+        lyrics::LrclibLyricsProvider::new(
+            config
+                .lyrics_api_url
+                .unwrap_or_else(|| "https://lrclib.net".parse().unwrap()),
+            Arc::clone(&http_client),
+        ),
+    )));
+
     // Inject the necessary dependencies
     {
         let mut data = client.data.write().await;
@@ -100,28 +346,23 @@ pub async fn run(config: Config) -> eyre::Result<()> {
                 di::ClientVoiceManagerToken,
                 Arc::clone(&client.voice_manager),
             ),
-            (
-                di::YtServiceToken,
-                Arc::new(yt::YtService::new(
-                    config.yt_data_api_key,
-                    Arc::clone(&http_client),
-                )),
-            ),
+            (di::YtServiceToken, yt_service),
             (di::AudioServiceToken, audio_service),
             (di::DerpibooruServiceToken, derpibooru_service),
-            (
-                di::GelbooruServiceToken,
-                Arc::new(gelbooru::GelbooruService::new(
-                    config.gelbooru_api_key,
-                    config.gelbooru_user_id,
-                    Arc::clone(&http_client),
-                )),
-            ),
+            (di::BooruRegistryToken, booru_registry),
             (di::HttpClientToken, http_client),
             (
                 di::ClientShardManagerToken,
                 Arc::clone(&client.shard_manager),
             ),
+            (di::RadioServiceToken, radio_service),
+            (di::MusicLinkServiceToken, music_link_service),
+            (di::LyricsServiceToken, lyrics_service),
+            (di::YtSubscriptionServiceToken, yt_subscription_service),
+            (
+                di::BooruSubscriptionServiceToken,
+                booru_subscription_service,
+            ),
         );
     }
 
@@ -0,0 +1,100 @@
+//! Symbols related to playing audio files from the local file system
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    time,
+};
+use tracing::warn;
+
+const AUDIO_FILE_EXTENSIONS: &[&str] = &["mp3", "ogg", "opus", "flac", "wav", "m4a", "webm"];
+
+#[derive(Clone)]
+pub(crate) struct LocalAudioFile {
+    path: PathBuf,
+    duration: time::Duration,
+}
+
+impl LocalAudioFile {
+    /// Reads the metadata of the given audio file via `ffprobe`.
+    pub(crate) async fn probe(path: PathBuf) -> crate::Result<Self> {
+        let output = tokio::process::Command::new("ffprobe")
+            .args(&[
+                "-v",
+                "error",
+                "-show_entries",
+                "format=duration",
+                "-of",
+                "default=noprint_wrappers=1:nokey=1",
+            ])
+            .arg(&path)
+            .output()
+            .await
+            .map_err(|err| crate::err!(ProbeAudioFile(path.clone(), err.to_string())))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+            return Err(crate::err!(ProbeAudioFile(path, stderr)));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let duration = stdout
+            .trim()
+            .parse::<f64>()
+            .map_err(|err| crate::err!(ProbeAudioFile(path.clone(), err.to_string())))?;
+
+        Ok(Self {
+            path,
+            duration: time::Duration::from_secs_f64(duration),
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn title(&self) -> &str {
+        self.path
+            .file_stem()
+            .and_then(OsStr::to_str)
+            .unwrap_or("<unknown file name>")
+    }
+
+    pub(crate) fn duration(&self) -> time::Duration {
+        self.duration
+    }
+}
+
+/// Returns all audio files from the given directory (not recursively).
+/// The files that could not be probed are skipped.
+pub(crate) async fn read_audio_dir(dir: &Path) -> crate::Result<Vec<LocalAudioFile>> {
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .map_err(|err| crate::err!(ReadAudioDir(dir.to_owned(), err)))?;
+
+    let mut files = Vec::new();
+
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|err| crate::err!(ReadAudioDir(dir.to_owned(), err)))?
+    {
+        let path = entry.path();
+        let is_audio = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(|ext| AUDIO_FILE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+            .unwrap_or(false);
+
+        if !is_audio {
+            continue;
+        }
+
+        match LocalAudioFile::probe(path).await {
+            Ok(it) => files.push(it),
+            Err(err) => warn!(?err, "Skipping the audio file that could not be probed"),
+        }
+    }
+
+    Ok(files)
+}
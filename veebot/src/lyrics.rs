@@ -0,0 +1,140 @@
+//! Symbols related to searching the lyrics of the tracks
+
+use crate::util::{self, ReqwestBuilderExt};
+use serenity::{async_trait, prelude::Mutex};
+use std::{collections::HashMap, sync::Arc};
+use url::Url;
+
+/// Declarations of the LRCLIB API JSON types.
+/// https://lrclib.net/docs
+mod rpc {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct SearchItem {
+        pub(crate) track_name: String,
+        pub(crate) artist_name: String,
+        /// `null` for instrumental tracks
+        pub(crate) plain_lyrics: Option<String>,
+    }
+}
+
+/// Max number of results kept in the lyrics cache, it is cleared once it overflows
+const MAX_CACHED_LYRICS: usize = 256;
+
+pub(crate) struct Lyrics {
+    pub(crate) artist: String,
+    pub(crate) title: String,
+    pub(crate) text: String,
+}
+
+/// Abstract source of the lyrics, so that it could be easily replaced with another one.
+#[async_trait]
+pub(crate) trait LyricsProvider: Send + Sync {
+    /// Human-readable name of the provider (shown to the users)
+    fn name(&self) -> &str;
+
+    /// Returns the lyrics of the track that best matches the given query.
+    async fn find_lyrics(&self, query: &str) -> crate::Result<Option<Lyrics>>;
+}
+
+/// Provider that uses the free https://lrclib.net API
+pub(crate) struct LrclibLyricsProvider {
+    http_client: Arc<reqwest::Client>,
+    api_url: Url,
+}
+
+impl LrclibLyricsProvider {
+    pub(crate) fn new(api_url: Url, http_client: Arc<reqwest::Client>) -> Self {
+        Self {
+            http_client,
+            api_url,
+        }
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for LrclibLyricsProvider {
+    fn name(&self) -> &str {
+        "LRCLIB"
+    }
+
+    async fn find_lyrics(&self, query: &str) -> crate::Result<Option<Lyrics>> {
+        let items: Vec<rpc::SearchItem> = self
+            .http_client
+            .get(util::url_with_segments(&self.api_url, &["api", "search"]))
+            .query(&[("q", query)])
+            .read_json()
+            .await?;
+
+        Ok(items.into_iter().find_map(|it| {
+            Some(Lyrics {
+                text: it.plain_lyrics.filter(|it| !it.trim().is_empty())?,
+                artist: it.artist_name,
+                title: it.track_name,
+            })
+        }))
+    }
+}
+
+pub(crate) struct LyricsService {
+    provider: Box<dyn LyricsProvider>,
+    /// Results (including the absent ones) by the key of the track they were found for
+    cache: Mutex<HashMap<String, Option<Arc<Lyrics>>>>,
+}
+
+impl LyricsService {
+    pub(crate) fn new(provider: Box<dyn LyricsProvider>) -> Self {
+        Self {
+            provider,
+            cache: Default::default(),
+        }
+    }
+
+    pub(crate) fn provider_name(&self) -> &str {
+        self.provider.name()
+    }
+
+    /// Returns the lyrics found by the given query. The result is cached by the given
+    /// key, which should identify the track the lyrics are searched for.
+    pub(crate) async fn find_lyrics(
+        &self,
+        cache_key: String,
+        query: &str,
+    ) -> crate::Result<Option<Arc<Lyrics>>> {
+        if let Some(cached) = self.cache.lock().await.get(&cache_key) {
+            return Ok(cached.clone());
+        }
+
+        let lyrics = self.provider.find_lyrics(query).await?.map(Arc::new);
+
+        let mut cache = self.cache.lock().await;
+        if cache.len() >= MAX_CACHED_LYRICS {
+            cache.clear();
+        }
+        cache.insert(cache_key, lyrics.clone());
+
+        Ok(lyrics)
+    }
+}
+
+/// Converts the title of the video to the query that is more likely to find the lyrics
+/// by removing the usual noise like `(Official Video)` or `[HD]`.
+pub(crate) fn lyrics_query_from_title(title: &str, channel: Option<&str>) -> String {
+    let noise = util::regex!(r#"(?i)\s*[(\[][^)\]]*[)\]]|\s*\bofficial\b.*$|\s*\blyrics?\b.*$"#);
+    let title = noise.replace_all(title, "");
+    let title = title.trim();
+
+    // Titles of the songs on YouTube usually have the `Artist - Song` form,
+    // otherwise the artist is usually the channel (e.g. `Artist - Topic` or `ArtistVEVO`)
+    match channel {
+        Some(channel) if !title.contains(" - ") => {
+            let artist = channel
+                .trim_end_matches(" - Topic")
+                .trim_end_matches("VEVO");
+            format!("{} {}", artist, title)
+        }
+        _ => title.to_owned(),
+    }
+}
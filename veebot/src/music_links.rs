@@ -0,0 +1,325 @@
+//! Symbols related to resolving Spotify and Apple Music links to the
+//! tracks metadata, so that we could find them on YouTube
+
+use crate::{
+    util::{self, ReqwestBuilderExt},
+    yt::YtVideo,
+};
+use serenity::prelude::Mutex;
+use std::{collections::HashSet, sync::Arc, time};
+use url::Url;
+
+/// Declarations of the Spotify Web API and iTunes Search API JSON types.
+mod rpc {
+    pub(crate) mod spotify {
+        use serde::Deserialize;
+
+        #[derive(Deserialize)]
+        pub(crate) struct Token {
+            pub(crate) access_token: String,
+            pub(crate) expires_in: u64,
+        }
+
+        #[derive(Deserialize)]
+        pub(crate) struct Track {
+            pub(crate) name: String,
+            pub(crate) artists: Vec<Artist>,
+            pub(crate) duration_ms: u64,
+        }
+
+        #[derive(Deserialize)]
+        pub(crate) struct Artist {
+            pub(crate) name: String,
+        }
+
+        #[derive(Deserialize)]
+        pub(crate) struct Page<T> {
+            pub(crate) items: Vec<T>,
+        }
+
+        #[derive(Deserialize)]
+        pub(crate) struct PlaylistItem {
+            /// May be `null` if the track was removed from Spotify
+            pub(crate) track: Option<Track>,
+        }
+    }
+
+    pub(crate) mod itunes {
+        use serde::Deserialize;
+
+        #[derive(Deserialize)]
+        pub(crate) struct LookupResponse {
+            pub(crate) results: Vec<LookupItem>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        pub(crate) struct LookupItem {
+            /// `track` for songs, `collection` for albums
+            pub(crate) wrapper_type: String,
+            pub(crate) artist_name: String,
+            pub(crate) track_name: Option<String>,
+            pub(crate) track_time_millis: Option<u64>,
+        }
+    }
+}
+
+/// Max number of tracks we resolve from a single album or playlist,
+/// each of them costs us a YouTube search request. A search spends 100 units
+/// of the 10000 daily API quota, so the big playlists would quickly drain it.
+const MAX_TRACKS_PER_LINK: usize = 10;
+
+/// Metadata of the track from a music streaming service
+pub(crate) struct MusicTrack {
+    pub(crate) artists: Vec<String>,
+    pub(crate) title: String,
+    pub(crate) duration: Option<time::Duration>,
+}
+
+impl MusicTrack {
+    /// Query string for searching this track on YouTube
+    pub(crate) fn search_query(&self) -> String {
+        format!("{} - {}", self.artists.join(", "), self.title)
+    }
+
+    /// Returns a number in `[0; 1]` range that describes how likely
+    /// the given YouTube video is the recording of this track.
+    pub(crate) fn match_confidence(&self, video: &YtVideo) -> f64 {
+        let expected = words(&self.search_query());
+        let actual = words(&format!("{} {}", video.channel_title(), video.title()));
+
+        let words_score =
+            expected.intersection(&actual).count() as f64 / expected.len().max(1) as f64;
+
+        let duration_score = match (self.duration, video.duration()) {
+            (Some(expected), Some(actual)) => {
+                // Music videos often have some intro or outro, so we tolerate the difference
+                let diff = (expected.as_secs_f64() - actual.as_secs_f64()).abs();
+                (1.0 - diff / 60.0).max(0.0)
+            }
+            _ => 0.5,
+        };
+
+        0.7 * words_score + 0.3 * duration_score
+    }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.to_lowercase()
+        .split(|it: char| !it.is_alphanumeric())
+        .filter(|it| !it.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum MusicLink {
+    SpotifyTrack(String),
+    SpotifyAlbum(String),
+    SpotifyPlaylist(String),
+    /// Apple Music song or album id, both are resolved via the same iTunes lookup
+    Apple(String),
+}
+
+pub(crate) struct SpotifyCredentials {
+    pub(crate) client_id: String,
+    pub(crate) client_secret: String,
+}
+
+struct SpotifyToken {
+    value: String,
+    expires_at: time::Instant,
+}
+
+pub(crate) struct MusicLinkService {
+    http_client: Arc<reqwest::Client>,
+    spotify_credentials: Option<SpotifyCredentials>,
+    spotify_token: Mutex<Option<SpotifyToken>>,
+    spotify_api_url: Url,
+    spotify_accounts_url: Url,
+    itunes_api_url: Url,
+}
+
+impl MusicLinkService {
+    pub(crate) fn new(
+        spotify_credentials: Option<SpotifyCredentials>,
+        spotify_api_url: Url,
+        spotify_accounts_url: Url,
+        itunes_api_url: Url,
+        http_client: Arc<reqwest::Client>,
+    ) -> Self {
+        Self {
+            http_client,
+            spotify_credentials,
+            spotify_token: Mutex::new(None),
+            spotify_api_url,
+            spotify_accounts_url,
+            itunes_api_url,
+        }
+    }
+
+    /// Returns `true` if the url points to one of the supported music streaming services.
+    pub(crate) fn is_music_link(url: &Url) -> bool {
+        matches!(
+            url.host_str(),
+            Some("open.spotify.com") | Some("music.apple.com")
+        )
+    }
+
+    /// Returns the metadata of the track, album or playlist tracks the given url points to.
+    pub(crate) async fn resolve(&self, url: &Url) -> crate::Result<Vec<MusicTrack>> {
+        let mut tracks = match Self::parse_link(url)? {
+            MusicLink::SpotifyTrack(id) => {
+                let track: rpc::spotify::Track = self.spotify_get(&["tracks", &id]).await?;
+                vec![track]
+            }
+            MusicLink::SpotifyAlbum(id) => {
+                let page: rpc::spotify::Page<rpc::spotify::Track> =
+                    self.spotify_get(&["albums", &id, "tracks"]).await?;
+                page.items
+            }
+            MusicLink::SpotifyPlaylist(id) => {
+                let page: rpc::spotify::Page<rpc::spotify::PlaylistItem> =
+                    self.spotify_get(&["playlists", &id, "tracks"]).await?;
+                page.items.into_iter().filter_map(|it| it.track).collect()
+            }
+            MusicLink::Apple(id) => return self.itunes_lookup(&id).await,
+        }
+        .into_iter()
+        .map(|track| MusicTrack {
+            artists: track.artists.into_iter().map(|it| it.name).collect(),
+            title: track.name,
+            duration: Some(time::Duration::from_millis(track.duration_ms)),
+        })
+        .collect::<Vec<_>>();
+
+        tracks.truncate(MAX_TRACKS_PER_LINK);
+        Ok(tracks)
+    }
+
+    pub(crate) fn parse_link(url: &Url) -> crate::Result<MusicLink> {
+        let unsupported = || crate::err!(UnsupportedMusicLink(url.clone()));
+
+        let segments: Vec<_> = url.path_segments().ok_or_else(unsupported)?.collect();
+
+        match url.host_str() {
+            // https://open.spotify.com/track/{id}
+            // (there may also be a locale prefix like `/intl-de/track/{id}`)
+            Some("open.spotify.com") => {
+                let kind_index = segments
+                    .iter()
+                    .position(|it| matches!(*it, "track" | "album" | "playlist"))
+                    .ok_or_else(unsupported)?;
+
+                let id = segments
+                    .get(kind_index + 1)
+                    .ok_or_else(unsupported)?
+                    .to_string();
+
+                Ok(match segments[kind_index] {
+                    "track" => MusicLink::SpotifyTrack(id),
+                    "album" => MusicLink::SpotifyAlbum(id),
+                    _ => MusicLink::SpotifyPlaylist(id),
+                })
+            }
+            // https://music.apple.com/{country}/album/{name}/{album_id}?i={song_id}
+            // https://music.apple.com/{country}/song/{name}/{song_id}
+            Some("music.apple.com") => {
+                let song_id = url
+                    .query_pairs()
+                    .find(|(key, _)| key == "i")
+                    .map(|(_, val)| val.into_owned());
+
+                if let Some(id) = song_id {
+                    return Ok(MusicLink::Apple(id));
+                }
+
+                match segments.get(1) {
+                    Some(&"album") | Some(&"song") => segments
+                        .last()
+                        .filter(|it| it.chars().all(|it| it.is_ascii_digit()))
+                        .map(|it| MusicLink::Apple(it.to_string()))
+                        .ok_or_else(unsupported),
+                    _ => Err(unsupported()),
+                }
+            }
+            _ => Err(unsupported()),
+        }
+    }
+
+    /// https://developer.spotify.com/documentation/web-api/reference/
+    async fn spotify_get<T: serde::de::DeserializeOwned>(
+        &self,
+        segments: &[&str],
+    ) -> crate::Result<T> {
+        let token = self.spotify_token().await?;
+        self.http_client
+            .get(util::url_with_segments(&self.spotify_api_url, segments))
+            .query(&[("limit", MAX_TRACKS_PER_LINK.to_string())])
+            .bearer_auth(token)
+            .read_json()
+            .await
+    }
+
+    /// Returns the access token obtained via the client credentials flow.
+    /// https://developer.spotify.com/documentation/general/guides/authorization/client-credentials/
+    async fn spotify_token(&self) -> crate::Result<String> {
+        let credentials = self
+            .spotify_credentials
+            .as_ref()
+            .ok_or_else(|| crate::err!(SpotifyNotConfigured))?;
+
+        let mut token = self.spotify_token.lock().await;
+
+        if let Some(token) = &*token {
+            if token.expires_at > time::Instant::now() {
+                return Ok(token.value.clone());
+            }
+        }
+
+        let res: rpc::spotify::Token = self
+            .http_client
+            .post(util::url_with_segments(
+                &self.spotify_accounts_url,
+                &["api", "token"],
+            ))
+            .basic_auth(&credentials.client_id, Some(&credentials.client_secret))
+            .form(&[("grant_type", "client_credentials")])
+            .read_json()
+            .await?;
+
+        // Refresh the token a bit earlier than it actually expires
+        let expires_in = time::Duration::from_secs(res.expires_in.saturating_sub(60));
+
+        *token = Some(SpotifyToken {
+            value: res.access_token.clone(),
+            expires_at: time::Instant::now() + expires_in,
+        });
+
+        Ok(res.access_token)
+    }
+
+    /// https://developer.apple.com/library/archive/documentation/AudioVideo/Conceptual/iTuneSearchAPI/LookupExamples.html
+    async fn itunes_lookup(&self, id: &str) -> crate::Result<Vec<MusicTrack>> {
+        let res: rpc::itunes::LookupResponse = self
+            .http_client
+            .get(util::url_with_segments(&self.itunes_api_url, &["lookup"]))
+            .query(&[("id", id), ("entity", "song")])
+            .read_json()
+            .await?;
+
+        Ok(res
+            .results
+            .into_iter()
+            .filter(|it| it.wrapper_type == "track")
+            .filter_map(|it| {
+                Some(MusicTrack {
+                    artists: vec![it.artist_name],
+                    title: it.track_name?,
+                    duration: it.track_time_millis.map(time::Duration::from_millis),
+                })
+            })
+            .take(MAX_TRACKS_PER_LINK)
+            .collect())
+    }
+}
@@ -0,0 +1,79 @@
+//! Messages with several embed pages that are switched via reactions
+
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    model::{
+        channel::ReactionType,
+        id::{ChannelId, UserId},
+    },
+};
+use std::time;
+
+const PREV_PAGE: &str = "⬅️";
+const NEXT_PAGE: &str = "➡️";
+
+/// Pages are not switchable after this much time has passed since the last switch
+const PAGINATION_TIMEOUT: time::Duration = time::Duration::from_secs(3 * 60);
+
+/// Sends the first page to the channel and lets the given user switch between the pages
+/// by clicking on the reactions until the pagination times out.
+/// Each page is expected to mention its number by itself (e.g. in the footer).
+/// Nothing is sent if there are no pages.
+pub(crate) async fn send_paginated_embed(
+    ctx: &Context,
+    channel_id: ChannelId,
+    user_id: UserId,
+    pages: Vec<CreateEmbed>,
+) -> crate::Result<()> {
+    if pages.is_empty() {
+        return Ok(());
+    }
+
+    let mut index = 0;
+
+    let mut msg = channel_id
+        .send_message(ctx, |it| {
+            it.embed(|it| {
+                *it = pages[index].clone();
+                it
+            })
+        })
+        .await?;
+
+    if pages.len() <= 1 {
+        return Ok(());
+    }
+
+    for emoji in &[PREV_PAGE, NEXT_PAGE] {
+        msg.react(ctx, ReactionType::Unicode((*emoji).to_owned()))
+            .await?;
+    }
+
+    // Both added and removed reactions switch the page, so that users don't have
+    // to remove their reaction before clicking on it again (and the bot needs no
+    // permission to remove other users' reactions)
+    while let Some(action) = msg
+        .await_reaction(ctx)
+        .author_id(user_id)
+        .removed(true)
+        .timeout(PAGINATION_TIMEOUT)
+        .await
+    {
+        index = match &action.as_inner_ref().emoji {
+            ReactionType::Unicode(it) if it == PREV_PAGE => (index + pages.len() - 1) % pages.len(),
+            ReactionType::Unicode(it) if it == NEXT_PAGE => (index + 1) % pages.len(),
+            _ => continue,
+        };
+
+        msg.edit(ctx, |it| {
+            it.embed(|it| {
+                *it = pages[index].clone();
+                it
+            })
+        })
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,223 @@
+//! Scheduled radio mode implementation.
+//!
+//! The radio joins the configured voice channel at the scheduled time of the day
+//! and plays the configured playlist on shuffle via the regular audio queue
+//! until the end time comes.
+
+use crate::{
+    audio_queue::{AudioQueueCmd, AudioService, AudioTrackMeta},
+    local_audio,
+    store::JsonStore,
+    yt::YtService,
+};
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, GuildId},
+    prelude::Mutex,
+};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{atomic, Arc},
+    time,
+};
+use tracing::{info, warn};
+use url::Url;
+
+/// How often the scheduler checks whether it is time to start or stop the radio.
+const SCHEDULER_TICK: time::Duration = time::Duration::from_secs(30);
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct RadioSchedule {
+    pub(crate) voice_channel: ChannelId,
+    /// Channel where the radio status messages are sent to
+    pub(crate) text_channel: ChannelId,
+    /// Time of the day (UTC) when the radio goes on air
+    pub(crate) start: NaiveTime,
+    /// Time of the day (UTC) when the radio goes off air.
+    /// It may be less than `start`, which means the radio plays through the midnight.
+    pub(crate) end: NaiveTime,
+    pub(crate) source: RadioSource,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum RadioSource {
+    /// List of YouTube video urls
+    Playlist(Vec<Url>),
+    /// Directory with audio files on the machine the bot runs on
+    Dir(PathBuf),
+}
+
+impl RadioSchedule {
+    fn is_on_air(&self, now: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= now && now < self.end
+        } else {
+            now >= self.start || now < self.end
+        }
+    }
+}
+
+pub(crate) struct RadioService {
+    schedules: JsonStore<HashMap<GuildId, RadioSchedule>>,
+    /// Guilds where the radio is on air. The flag is set by the audio queue if it
+    /// turns the radio off early because none of the tracks could be played.
+    on_air: Mutex<HashMap<GuildId, Arc<atomic::AtomicBool>>>,
+    audio: Arc<AudioService>,
+    yt: Arc<YtService>,
+    http: Arc<Http>,
+}
+
+impl RadioService {
+    pub(crate) fn new(
+        schedules: JsonStore<HashMap<GuildId, RadioSchedule>>,
+        audio: Arc<AudioService>,
+        yt: Arc<YtService>,
+        http: Arc<Http>,
+    ) -> Self {
+        Self {
+            schedules,
+            on_air: Default::default(),
+            audio,
+            yt,
+            http,
+        }
+    }
+
+    pub(crate) async fn schedule(&self, guild_id: GuildId) -> Option<RadioSchedule> {
+        self.schedules.read(|it| it.get(&guild_id).cloned()).await
+    }
+
+    /// Replaces the schedule of the radio for the given guild.
+    /// If the radio is on air it is stopped, the new schedule is picked up on the next tick.
+    pub(crate) async fn set_schedule(
+        &self,
+        guild_id: GuildId,
+        schedule: RadioSchedule,
+    ) -> crate::Result<()> {
+        let text_channel = schedule.text_channel;
+        self.schedules
+            .update(|it| it.insert(guild_id, schedule))
+            .await?;
+        self.stop(guild_id, text_channel).await;
+        Ok(())
+    }
+
+    pub(crate) async fn remove_schedule(
+        &self,
+        guild_id: GuildId,
+    ) -> crate::Result<Option<RadioSchedule>> {
+        let removed = self.schedules.update(|it| it.remove(&guild_id)).await?;
+        if let Some(schedule) = &removed {
+            self.stop(guild_id, schedule.text_channel).await;
+        }
+        Ok(removed)
+    }
+
+    /// Returns `true` if the radio was turned off before the end of its schedule
+    /// today because none of the tracks could be played. It is not restarted
+    /// until the next scheduled time.
+    pub(crate) async fn has_failed(&self, guild_id: GuildId) -> bool {
+        self.on_air
+            .lock()
+            .await
+            .get(&guild_id)
+            .map_or(false, |it| it.load(atomic::Ordering::SeqCst))
+    }
+
+    /// Runs the infinite loop that starts and stops the radio according to the schedules.
+    pub(crate) async fn run_scheduler(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(SCHEDULER_TICK);
+        loop {
+            interval.tick().await;
+            self.tick().await;
+        }
+    }
+
+    async fn tick(&self) {
+        let now = chrono::Utc::now().time();
+        let schedules: Vec<_> = self
+            .schedules
+            .read(|it| it.iter().map(|(k, v)| (*k, v.clone())).collect())
+            .await;
+
+        for (guild_id, schedule) in schedules {
+            let should_be_on_air = schedule.is_on_air(now);
+            let is_on_air = self.on_air.lock().await.contains_key(&guild_id);
+
+            if should_be_on_air && !is_on_air {
+                if let Err(err) = self.start(guild_id, &schedule).await {
+                    let _ = schedule
+                        .text_channel
+                        .send_message(&self.http, |it| err.create_msg(it))
+                        .await;
+                }
+            } else if !should_be_on_air && is_on_air {
+                self.stop(guild_id, schedule.text_channel).await;
+            }
+        }
+    }
+
+    async fn start(&self, guild_id: GuildId, schedule: &RadioSchedule) -> crate::Result<()> {
+        // Mark the radio as on air right away, so that we don't retry
+        // loading the playlist every tick if it fails
+        let failed = Arc::new(atomic::AtomicBool::new(false));
+        self.on_air.lock().await.insert(guild_id, failed.clone());
+
+        info!(%guild_id, "Starting the scheduled radio");
+
+        let tracks = self.load_tracks(&schedule.source).await?;
+        if tracks.is_empty() {
+            return Err(crate::err!(RadioPlaylistEmpty));
+        }
+
+        self.audio
+            .get_or_create_queue(guild_id)
+            .await
+            .unbounded_send(AudioQueueCmd::StartRadio {
+                text_channel: schedule.text_channel,
+                voice_channel: schedule.voice_channel,
+                tracks,
+                failed,
+            })
+            .unwrap();
+
+        Ok(())
+    }
+
+    async fn stop(&self, guild_id: GuildId, text_channel: ChannelId) {
+        if self.on_air.lock().await.remove(&guild_id).is_none() {
+            return;
+        }
+
+        info!(%guild_id, "Stopping the scheduled radio");
+
+        self.audio
+            .get_or_create_queue(guild_id)
+            .await
+            .unbounded_send(AudioQueueCmd::StopRadio { text_channel })
+            .unwrap();
+    }
+
+    async fn load_tracks(&self, source: &RadioSource) -> crate::Result<Vec<AudioTrackMeta>> {
+        match source {
+            RadioSource::Dir(dir) => Ok(local_audio::read_audio_dir(dir)
+                .await?
+                .into_iter()
+                .map(AudioTrackMeta::Local)
+                .collect()),
+            RadioSource::Playlist(urls) => {
+                let mut tracks = Vec::with_capacity(urls.len());
+                for url in urls {
+                    match self.yt.find_video_by_url(url).await {
+                        Ok(it) => tracks.push(AudioTrackMeta::Yt(it)),
+                        Err(err) => warn!(?err, %url, "Skipping the radio track"),
+                    }
+                }
+                Ok(tracks)
+            }
+        }
+    }
+}
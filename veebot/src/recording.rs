@@ -0,0 +1,322 @@
+//! Voice channel recording implementation
+
+use serenity::{async_trait, model::id::UserId, voice::AudioReceiver};
+use std::{
+    collections::HashMap,
+    path::Path,
+    process::Stdio,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time,
+};
+use tokio::io::AsyncWriteExt;
+
+/// Discord sends voice with 48kHz sample rate.
+const SAMPLE_RATE: u32 = 48_000;
+
+/// Bitrate of the Opus encoded recordings. It is plenty for the voice, and
+/// [`MAX_RECORDING_DURATION`] of it takes ~3.6 MB, so it fits into the upload limit.
+const OPUS_BITRATE: &str = "32k";
+
+/// We don't want a forgotten recording to eat all the RAM, so the
+/// audio received after this duration is just dropped.
+pub(crate) const MAX_RECORDING_DURATION: time::Duration = time::Duration::from_secs(15 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RecordingMode {
+    /// All speakers are mixed into a single track
+    Mixed,
+    /// Each speaker gets their own track
+    PerSpeaker,
+}
+
+impl FromStr for RecordingMode {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mixed" => Ok(RecordingMode::Mixed),
+            "split" => Ok(RecordingMode::PerSpeaker),
+            _ => Err(crate::err!(UnknownRecordingMode {
+                input: s.to_owned()
+            })),
+        }
+    }
+}
+
+/// Encoded audio file ready to be uploaded or saved to the disk.
+pub(crate) struct RecordedFile {
+    pub(crate) name: String,
+    pub(crate) bytes: Vec<u8>,
+}
+
+/// Mono PCM samples of a single track.
+#[derive(Default)]
+struct Track {
+    samples: Vec<i16>,
+}
+
+impl Track {
+    fn mix_in(&mut self, offset: usize, samples: impl ExactSizeIterator<Item = i16>) {
+        let end = offset + samples.len();
+        if self.samples.len() < end {
+            self.samples.resize(end, 0);
+        }
+        for (dest, sample) in self.samples[offset..end].iter_mut().zip(samples) {
+            *dest = dest.saturating_add(sample);
+        }
+    }
+}
+
+/// Describes how to place the packets of a single speaker on the timeline.
+struct SpeakerTimeline {
+    /// Offset of the first received packet since the start of the recording (in samples)
+    base_offset: usize,
+    /// RTP timestamp of the first received packet (it is measured in samples too)
+    base_timestamp: u32,
+}
+
+/// State shared between the [`VoiceRecorder`] and the audio queue that owns the recording.
+pub(crate) struct RecordingBuffer {
+    mode: RecordingMode,
+    started_at: time::Instant,
+    ssrc_to_user: HashMap<u32, UserId>,
+    timelines: HashMap<u32, SpeakerTimeline>,
+    /// Tracks by the ssrc of the speaker (`None` in the mixed mode). The speakers
+    /// may be identified only after their first packets arrive, so the tracks
+    /// are assigned to the users only when they are encoded.
+    tracks: HashMap<Option<u32>, Track>,
+    truncated: bool,
+}
+
+impl RecordingBuffer {
+    pub(crate) fn new(mode: RecordingMode) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            mode,
+            started_at: time::Instant::now(),
+            ssrc_to_user: HashMap::new(),
+            timelines: HashMap::new(),
+            tracks: HashMap::new(),
+            truncated: false,
+        }))
+    }
+
+    pub(crate) fn elapsed(&self) -> time::Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Returns `true` if some audio was dropped because the recording
+    /// exceeded [`MAX_RECORDING_DURATION`].
+    pub(crate) fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Remembers which user speaks via the given ssrc.
+    pub(crate) fn set_speaker(&mut self, ssrc: u32, user_id: UserId) {
+        self.ssrc_to_user.insert(ssrc, user_id);
+    }
+
+    pub(crate) fn push_packet(&mut self, ssrc: u32, timestamp: u32, stereo: bool, data: &[i16]) {
+        let started_at = self.started_at;
+        let timeline = self
+            .timelines
+            .entry(ssrc)
+            .or_insert_with(|| SpeakerTimeline {
+                base_offset: samples_in(started_at.elapsed()),
+                base_timestamp: timestamp,
+            });
+
+        let delta = timestamp.wrapping_sub(timeline.base_timestamp);
+
+        // Packets that arrived out of order before the first one are just dropped
+        if delta > u32::MAX / 2 {
+            return;
+        }
+
+        let offset = timeline.base_offset + delta as usize;
+
+        if offset > samples_in(MAX_RECORDING_DURATION) {
+            self.truncated = true;
+            return;
+        }
+
+        let key = match self.mode {
+            RecordingMode::Mixed => None,
+            RecordingMode::PerSpeaker => Some(ssrc),
+        };
+
+        let track = self.tracks.entry(key).or_default();
+        if stereo {
+            // Downmix to mono, we don't need stereo for the voice anyway
+            track.mix_in(
+                offset,
+                data.chunks_exact(2)
+                    .map(|it| ((i32::from(it[0]) + i32::from(it[1])) / 2) as i16),
+            );
+        } else {
+            track.mix_in(offset, data.iter().copied());
+        }
+    }
+
+    /// Encodes the recorded tracks into WAV files.
+    /// `user_name` is used to give the per-speaker tracks human-readable file names.
+    pub(crate) fn encode_wav_files(
+        &self,
+        file_stem: &str,
+        user_name: impl Fn(UserId) -> String,
+    ) -> Vec<RecordedFile> {
+        // The same user may have several ssrcs (e.g. if they reconnected)
+        let mut tracks: HashMap<Option<UserId>, Track> = HashMap::new();
+        for (ssrc, track) in &self.tracks {
+            let user = ssrc.map(|ssrc| {
+                self.ssrc_to_user
+                    .get(&ssrc)
+                    .copied()
+                    .unwrap_or(UserId(u64::from(ssrc)))
+            });
+            tracks
+                .entry(user)
+                .or_default()
+                .mix_in(0, track.samples.iter().copied());
+        }
+
+        tracks
+            .iter()
+            .map(|(user, track)| RecordedFile {
+                name: match user {
+                    None => format!("{}.wav", file_stem),
+                    Some(user) => format!(
+                        "{}-{}.wav",
+                        file_stem,
+                        // User names may contain characters that are not allowed in file names
+                        user_name(*user).replace(|it: char| !it.is_alphanumeric(), "_")
+                    ),
+                },
+                bytes: encode_wav(&track.samples),
+            })
+            .collect()
+    }
+}
+
+fn samples_in(duration: time::Duration) -> usize {
+    (duration.as_secs_f64() * f64::from(SAMPLE_RATE)) as usize
+}
+
+/// Encodes the WAV file into Ogg/Opus via `ffmpeg`. A minute of WAV takes ~5.6 MB,
+/// so even short recordings wouldn't fit into the Discord upload limit otherwise.
+pub(crate) async fn encode_opus(wav: RecordedFile) -> crate::Result<RecordedFile> {
+    let name = Path::new(&wav.name)
+        .with_extension("ogg")
+        .to_string_lossy()
+        .into_owned();
+
+    let err = |message: String| crate::err!(EncodeRecording(name.clone(), message));
+
+    let mut child = tokio::process::Command::new("ffmpeg")
+        .args(&["-hide_banner", "-loglevel", "error"])
+        .args(&["-f", "wav", "-i", "pipe:0"])
+        .args(&["-c:a", "libopus", "-b:a", OPUS_BITRATE])
+        .args(&["-f", "ogg", "pipe:1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|it| err(it.to_string()))?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let input = wav.bytes;
+
+    // The input is written concurrently with reading the output, otherwise
+    // ffmpeg may block on the full stdout pipe while we block on its stdin.
+    // Stdin is closed once written, which tells ffmpeg the input has ended.
+    let write_input = async move { stdin.write_all(&input).await };
+    let (written, output) = futures::join!(write_input, child.wait_with_output());
+
+    let output = output.map_err(|it| err(it.to_string()))?;
+    if !output.status.success() {
+        return Err(err(String::from_utf8_lossy(&output.stderr).into_owned()));
+    }
+    written.map_err(|it| err(it.to_string()))?;
+
+    Ok(RecordedFile {
+        name,
+        bytes: output.stdout,
+    })
+}
+
+/// Encodes mono 16-bit PCM samples into WAV file format.
+/// The format is simple enough to not pull a dedicated dependency for this.
+pub(crate) fn encode_wav(samples: &[i16]) -> Vec<u8> {
+    const CHANNELS: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+    const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+
+    let data_len = (samples.len() * 2) as u32;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    // PCM format
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&CHANNELS.to_le_bytes());
+    bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(SAMPLE_RATE * u32::from(BLOCK_ALIGN)).to_le_bytes());
+    bytes.extend_from_slice(&BLOCK_ALIGN.to_le_bytes());
+    bytes.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    bytes
+}
+
+/// Voice receiver that accumulates the incoming audio into the shared [`RecordingBuffer`].
+pub(crate) struct VoiceRecorder {
+    buffer: Arc<Mutex<RecordingBuffer>>,
+}
+
+impl VoiceRecorder {
+    pub(crate) fn new(buffer: Arc<Mutex<RecordingBuffer>>) -> Self {
+        Self { buffer }
+    }
+}
+
+#[async_trait]
+impl AudioReceiver for VoiceRecorder {
+    async fn speaking_update(&mut self, ssrc: u32, user_id: u64, _speaking: bool) {
+        self.buffer
+            .lock()
+            .unwrap()
+            .set_speaker(ssrc, UserId(user_id));
+    }
+
+    async fn voice_packet(
+        &mut self,
+        ssrc: u32,
+        _sequence: u16,
+        timestamp: u32,
+        stereo: bool,
+        data: &[i16],
+        _compressed_size: usize,
+    ) {
+        self.buffer
+            .lock()
+            .unwrap()
+            .push_packet(ssrc, timestamp, stereo, data);
+    }
+
+    async fn client_connect(&mut self, ssrc: u32, user_id: u64) {
+        self.buffer
+            .lock()
+            .unwrap()
+            .set_speaker(ssrc, UserId(user_id));
+    }
+}
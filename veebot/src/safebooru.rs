@@ -0,0 +1,235 @@
+//! Symbols related to communicating with the Safebooru API
+
+use crate::{
+    booru::{
+        self, Booru, BooruCredentials, BooruMedia, BooruQuery, BooruRating, BooruSort, BooruTag,
+    },
+    util::{self, ReqwestBuilderExt, ThemeTag},
+};
+use itertools::Itertools;
+use rand::seq::SliceRandom;
+use serenity::async_trait;
+use std::sync::Arc;
+use url::Url;
+
+/// Declarations of the safebooru JSON API types.
+/// It's the same API as the gelbooru one, but of an older version.
+pub(crate) mod rpc {
+    use serde::Deserialize;
+
+    pub(crate) mod search {
+        use super::*;
+
+        #[derive(Debug, Deserialize)]
+        pub(crate) struct Response(pub(crate) Vec<Image>);
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Image {
+        pub(crate) id: u128,
+        /// Directory of the image file on the image server
+        pub(crate) directory: String,
+        /// File name of the image on the image server
+        pub(crate) image: String,
+        /// Hash that the thumbnail file is named by
+        pub(crate) hash: String,
+        /// Space-separated list of tags
+        pub(crate) tags: String,
+        /// `safe` or `questionable`
+        pub(crate) rating: String,
+        /// `null` for the posts no one voted for
+        pub(crate) score: Option<i64>,
+    }
+
+    pub(crate) mod autocomplete {
+        use super::*;
+
+        #[derive(Debug, Deserialize)]
+        pub(crate) struct Response(pub(crate) Vec<Item>);
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Item {
+        /// Name of the tag followed by the number of the images, e.g. `solo (1234)`
+        pub(crate) label: String,
+        /// Name of the tag
+        pub(crate) value: String,
+    }
+}
+
+// Links that are shown to the users, the requests go to `SafebooruService::api_url`
+util::def_url_base!(safebooru, "https://safebooru.org");
+
+/// Safebooru doesn't support random sorting, so we pick random
+/// images among this number of the latest ones with the given tags
+const RANDOM_IMAGES_BATCH: usize = 100;
+
+impl rpc::Item {
+    fn post_count(&self) -> u64 {
+        self.label
+            .rsplit('(')
+            .next()
+            .and_then(|it| it.trim_end_matches(')').parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+impl rpc::Image {
+    pub(crate) fn webpage_url(&self) -> Url {
+        let mut url = safebooru(&["index.php"]);
+        url.query_pairs_mut().extend_pairs(&[
+            ("page", "post"),
+            ("s", "view"),
+            ("id", &self.id.to_string()),
+        ]);
+        url
+    }
+
+    fn into_media(self) -> BooruMedia {
+        let media_url = safebooru(&["images", &self.directory, &self.image]);
+        BooruMedia {
+            id: self.id.to_string(),
+            webpage_url: self.webpage_url(),
+            thumbnail_url: Some(safebooru(&[
+                "thumbnails",
+                &self.directory,
+                &format!("thumbnail_{}.jpg", self.hash),
+            ])),
+            mime_type: booru::mime_type_from_url(&media_url),
+            media_url,
+            tags: self
+                .tags
+                .split_whitespace()
+                .map(ToOwned::to_owned)
+                .collect(),
+            score: self.score.unwrap_or(0),
+            rating: BooruRating::parse(&self.rating),
+            created_at: None,
+        }
+    }
+}
+
+pub(crate) struct SafebooruService {
+    http_client: Arc<reqwest::Client>,
+    credentials: Option<BooruCredentials>,
+    /// Url of the API endpoint (`https://safebooru.org/index.php`)
+    api_url: Url,
+}
+
+impl SafebooruService {
+    pub(crate) fn new(
+        credentials: Option<BooruCredentials>,
+        api_url: Url,
+        http_client: Arc<reqwest::Client>,
+    ) -> Self {
+        Self {
+            http_client,
+            credentials,
+            api_url,
+        }
+    }
+}
+
+#[async_trait]
+impl Booru for SafebooruService {
+    fn name(&self) -> &'static str {
+        "safebooru"
+    }
+
+    fn site(&self) -> &'static str {
+        "safebooru.org"
+    }
+
+    async fn fetch_media(&self, query: &BooruQuery) -> crate::Result<Vec<BooruMedia>> {
+        let safe_tag: ThemeTag = "rating:safe".parse().unwrap();
+        let rating_tag = if query.nsfw { None } else { Some(&safe_tag) };
+
+        let score_tag: ThemeTag = "sort:score".parse().unwrap();
+        let sort_tag = if query.sort == BooruSort::Score {
+            Some(&score_tag)
+        } else {
+            None
+        };
+
+        let tags = query
+            .underscored_search_tags()
+            .iter()
+            .chain(rating_tag)
+            .chain(sort_tag)
+            .unique()
+            .join(" ");
+
+        let limit = if query.sort == BooruSort::Random {
+            RANDOM_IMAGES_BATCH.max(query.limit)
+        } else {
+            query.limit
+        };
+        let limit = limit.to_string();
+
+        let mut params = vec![
+            ("page", "dapi"),
+            ("s", "post"),
+            ("q", "index"),
+            ("limit", &limit),
+            ("json", "1"),
+            ("tags", &tags),
+        ];
+
+        if let Some(credentials) = &self.credentials {
+            params.push(("api_key", &credentials.api_key));
+            params.push(("user_id", &credentials.login));
+        }
+
+        let res: crate::Result<rpc::search::Response> = self
+            .http_client
+            .get(self.api_url.clone())
+            .query(&params)
+            .read_json()
+            .await;
+
+        let mut images = match res {
+            Ok(it) => it.0,
+            // Same as gelbooru, when no image was found, the response has empty body...
+            Err(crate::Error {
+                kind: crate::ErrorKind::UnexpectedHttpResponseJsonShape(_),
+                ..
+            }) => return Ok(Vec::new()),
+            Err(it) => return Err(it),
+        };
+
+        if query.sort == BooruSort::Random {
+            images.shuffle(&mut rand::thread_rng());
+        }
+
+        Ok(images
+            .into_iter()
+            .take(query.limit)
+            .map(rpc::Image::into_media)
+            .collect())
+    }
+
+    /// The tags API of safebooru returns only XML, so we use the autocomplete of the site
+    async fn find_similar_tags(&self, tag: &str) -> crate::Result<Vec<BooruTag>> {
+        let url = self
+            .api_url
+            .join("autocomplete.php")
+            .expect("BUG: the autocomplete url must be valid");
+
+        let res: rpc::autocomplete::Response = self
+            .http_client
+            .get(url)
+            .query(&[("q", tag)])
+            .read_json()
+            .await?;
+
+        Ok(res
+            .0
+            .into_iter()
+            .map(|it| BooruTag {
+                post_count: it.post_count(),
+                name: it.value,
+                alias: None,
+            })
+            .collect())
+    }
+}
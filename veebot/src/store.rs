@@ -0,0 +1,93 @@
+//! Persistent storage of the bot state that needs to survive restarts.
+//!
+//! We don't need anything fancy (like a real database) for the amount
+//! of data the bot has, so each piece of state is just a JSON file on the disk.
+
+use serde::{de::DeserializeOwned, Serialize};
+use serenity::prelude::Mutex;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+pub(crate) struct JsonStore<T> {
+    /// `None` if the state is kept only in memory
+    path: Option<PathBuf>,
+    data: Mutex<T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default + Send> JsonStore<T> {
+    /// Loads the state from the file at the given path.
+    /// If there is no such file yet, the state is initialized with the default value.
+    pub(crate) async fn open(path: PathBuf) -> crate::Result<Self> {
+        let data = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|err| crate::err!(PersistentStoreJson(err)))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                info!(path = %path.display(), "Persistent store was not found, creating a new one");
+                T::default()
+            }
+            Err(err) => return Err(crate::err!(PersistentStoreIo(err))),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            data: Mutex::new(data),
+        })
+    }
+
+    /// Creates the store that is never written to the disk.
+    /// Useful for the state that is persistent only optionally.
+    pub(crate) fn in_memory() -> Self {
+        Self {
+            path: None,
+            data: Mutex::new(T::default()),
+        }
+    }
+
+    /// Returns the value computed from the current state.
+    pub(crate) async fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&*self.data.lock().await)
+    }
+
+    /// Mutates the state and immediately writes it to the disk.
+    pub(crate) async fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> crate::Result<R> {
+        let mut data = self.data.lock().await;
+        let result = f(&mut data);
+
+        if let Some(path) = &self.path {
+            let bytes = serde_json::to_vec_pretty(&*data)
+                .map_err(|err| crate::err!(PersistentStoreJson(err)))?;
+            write_file(path, bytes).await?;
+        }
+
+        Ok(result)
+    }
+
+    /// Mutates the state without writing it to the disk.
+    /// The changes are persisted only by the next [`JsonStore::update`] or [`JsonStore::save`].
+    pub(crate) async fn update_in_memory<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut *self.data.lock().await)
+    }
+
+    /// Writes the current state to the disk.
+    pub(crate) async fn save(&self) -> crate::Result<()> {
+        self.update(|_| ()).await
+    }
+}
+
+async fn write_file(path: &Path, bytes: Vec<u8>) -> crate::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|err| crate::err!(PersistentStoreIo(err)))?;
+    }
+
+    // Write to a temporary file first, so that we don't end up
+    // with a half-written file if the bot is killed in the middle
+    let tmp_path = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp_path, bytes)
+        .await
+        .map_err(|err| crate::err!(PersistentStoreIo(err)))?;
+    tokio::fs::rename(&tmp_path, path)
+        .await
+        .map_err(|err| crate::err!(PersistentStoreIo(err)))
+}
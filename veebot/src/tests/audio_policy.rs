@@ -0,0 +1,130 @@
+use super::{http_client, json_mock, mock_url};
+use crate::{
+    audio_policy::AudioPolicy, audio_queue::AudioTrackMeta, yt, yt::YtService, yt_cache::YtCache,
+    yt_quota::YtApiKeyPool, ErrorKind,
+};
+use std::time::Duration;
+
+const VIDEOS: &str = include_str!("fixtures/yt_videos.json");
+
+/// Returns the official music video (3:41) and the mix (1:10:05) from the fixtures
+async fn tracks(prefix: &str) -> (AudioTrackMeta, AudioTrackMeta) {
+    let yt = YtService::new(
+        YtApiKeyPool::new(vec!["key".to_owned()]),
+        YtCache::open(None).await.unwrap(),
+        None,
+        mock_url(prefix),
+        mock_url(prefix),
+        http_client(),
+    );
+
+    let mut videos = yt
+        .find_videos_by_ids(&["bbbbbbbbbbb", "aaaaaaaaaaa"])
+        .await
+        .unwrap()
+        .into_iter()
+        .map(AudioTrackMeta::Yt);
+
+    (videos.next().unwrap(), videos.next().unwrap())
+}
+
+#[tokio::test]
+async fn allows_everything_by_default() {
+    let _mock = json_mock("GET", "/policy-default/videos", VIDEOS).create();
+    let (official, mix) = tracks("policy-default").await;
+
+    let policy = AudioPolicy::default();
+    policy.check(&official).unwrap();
+    policy.check(&mix).unwrap();
+}
+
+#[tokio::test]
+async fn rejects_too_long_tracks() {
+    let _mock = json_mock("GET", "/policy-duration/videos", VIDEOS).create();
+    let (official, mix) = tracks("policy-duration").await;
+
+    let policy = AudioPolicy {
+        max_duration: Some(Duration::from_secs(60 * 60)),
+        ..AudioPolicy::default()
+    };
+
+    policy.check(&official).unwrap();
+    let err = policy.check(&mix).unwrap_err();
+    assert!(
+        matches!(err.kind, ErrorKind::TrackTooLong { .. }),
+        "{:?}",
+        err.kind
+    );
+}
+
+#[tokio::test]
+async fn rejects_blocked_channels_by_id_or_title() {
+    let _mock = json_mock("GET", "/policy-channels/videos", VIDEOS).create();
+    let (official, mix) = tracks("policy-channels").await;
+
+    let by_id = AudioPolicy {
+        blocked_channels: vec!["UCbbbbbbbbbbbbbbbbbbbbbb".to_owned()]
+            .into_iter()
+            .collect(),
+        ..AudioPolicy::default()
+    };
+    let err = by_id.check(&official).unwrap_err();
+    assert!(
+        matches!(
+            &err.kind,
+            ErrorKind::TrackChannelBlocked { channel } if channel == "UCbbbbbbbbbbbbbbbbbbbbbb"
+        ),
+        "{:?}",
+        err.kind
+    );
+    by_id.check(&mix).unwrap();
+
+    // Channel ids are case-sensitive, so a lowercased id must not match
+    let lowercase_id = AudioPolicy {
+        blocked_channels: vec!["ucbbbbbbbbbbbbbbbbbbbbbb".to_owned()]
+            .into_iter()
+            .collect(),
+        ..AudioPolicy::default()
+    };
+    lowercase_id.check(&official).unwrap();
+
+    // Titles are stored lowercase and matched case-insensitively
+    let by_title = AudioPolicy {
+        blocked_channels: vec!["mixes & more".to_owned()].into_iter().collect(),
+        ..AudioPolicy::default()
+    };
+    by_title.check(&official).unwrap();
+    let err = by_title.check(&mix).unwrap_err();
+    assert!(
+        matches!(err.kind, ErrorKind::TrackChannelBlocked { .. }),
+        "{:?}",
+        err.kind
+    );
+}
+
+#[tokio::test]
+async fn rejects_blocked_keywords_in_titles() {
+    let _mock = json_mock("GET", "/policy-keywords/videos", VIDEOS).create();
+    let (official, mix) = tracks("policy-keywords").await;
+
+    let policy = AudioPolicy {
+        blocked_keywords: vec!["official".to_owned()].into_iter().collect(),
+        ..AudioPolicy::default()
+    };
+
+    let err = policy.check(&official).unwrap_err();
+    assert!(
+        matches!(&err.kind, ErrorKind::TrackKeywordBlocked { keyword } if keyword == "official"),
+        "{:?}",
+        err.kind
+    );
+    policy.check(&mix).unwrap();
+}
+
+#[test]
+fn recognizes_channel_ids() {
+    assert!(yt::is_channel_id("UCq-Fj5jknLsUf-MWSy4_brA"));
+    assert!(!yt::is_channel_id("ucq-fj5jknlsuf-mwsy4_bra"));
+    assert!(!yt::is_channel_id("Some Label"));
+    assert!(!yt::is_channel_id("UCq-Fj5jknLsUf"));
+}
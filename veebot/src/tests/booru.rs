@@ -0,0 +1,293 @@
+use crate::{
+    booru::{
+        self, BlocklistOwner, Booru, BooruMedia, BooruQuery, BooruRegistry, BooruSort, BooruTag,
+    },
+    booru_subscriptions,
+    store::JsonStore,
+    ErrorKind,
+};
+use serenity::{
+    async_trait,
+    model::id::{ChannelId, GuildId, UserId},
+    prelude::Mutex,
+};
+use std::{collections::VecDeque, sync::Arc};
+
+fn query(tags: &[&str], nsfw: bool) -> crate::Result<BooruQuery> {
+    BooruQuery::new(tags.iter().map(|it| it.parse().unwrap()).collect(), nsfw)
+}
+
+#[test]
+fn refuses_nsfw_only_tags_in_sfw_channels() {
+    for tag in &["explicit", "Questionable", "rating:e", "rating:sensitive"] {
+        let err = query(&["pony", tag], false).unwrap_err();
+        assert!(
+            matches!(&err.kind, ErrorKind::NsfwTagInSfwChannel(it) if it == tag),
+            "{:?}",
+            err.kind,
+        );
+    }
+}
+
+#[test]
+fn allows_safe_and_negated_tags_in_sfw_channels() {
+    query(&["pony", "-explicit", "rating:general", "rating:s"], false).unwrap();
+}
+
+#[test]
+fn allows_nsfw_only_tags_in_nsfw_channels() {
+    let query = query(&["explicit", "rating:e"], true).unwrap();
+    assert_eq!(query.tags.len(), 2);
+    assert!(query.nsfw);
+}
+
+#[test]
+fn extracts_sort_order_from_tags() {
+    let top = query(&["pony", "sort:score"], false).unwrap();
+    assert_eq!(top.sort, BooruSort::Score);
+    assert_eq!(top.tags.len(), 1);
+    assert_eq!(top.tags[0].as_str(), "pony");
+    assert_eq!(top.limit, 1);
+
+    let newest = query(&["order:newest"], false).unwrap();
+    assert_eq!(newest.sort, BooruSort::Newest);
+    assert!(newest.tags.is_empty());
+
+    assert_eq!(query(&[], false).unwrap().sort, BooruSort::Random);
+
+    let err = query(&["sort:favorites"], false).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::InvalidArgumentValue { .. }));
+}
+
+/// Booru that returns the given batches of media one after another
+struct FakeBooru {
+    batches: Mutex<VecDeque<Vec<&'static str>>>,
+}
+
+impl FakeBooru {
+    fn new(batches: &[&[&'static str]]) -> Arc<Self> {
+        Arc::new(Self {
+            batches: Mutex::new(batches.iter().map(|it| it.to_vec()).collect()),
+        })
+    }
+}
+
+#[async_trait]
+impl Booru for FakeBooru {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn site(&self) -> &'static str {
+        "fake.org"
+    }
+
+    async fn fetch_media(&self, _query: &BooruQuery) -> crate::Result<Vec<BooruMedia>> {
+        let ids = self.batches.lock().await.pop_front().unwrap_or_default();
+        Ok(ids.into_iter().map(media).collect())
+    }
+
+    async fn find_similar_tags(&self, _tag: &str) -> crate::Result<Vec<BooruTag>> {
+        Ok(Vec::new())
+    }
+}
+
+fn media(id: &str) -> BooruMedia {
+    BooruMedia {
+        id: id.to_owned(),
+        webpage_url: format!("https://fake.org/{}", id).parse().unwrap(),
+        media_url: format!("https://fake.org/{}.png", id).parse().unwrap(),
+        thumbnail_url: None,
+        tags: Vec::new(),
+        score: 0,
+        rating: None,
+        created_at: None,
+        mime_type: "image/png".to_owned(),
+    }
+}
+
+fn ids(media: &[BooruMedia]) -> Vec<&str> {
+    media.iter().map(|it| it.id.as_str()).collect()
+}
+
+#[tokio::test]
+async fn rerolls_recently_shown_media() {
+    let booru = FakeBooru::new(&[&["1"], &["1"], &["2"], &["1"]]);
+    let registry = BooruRegistry::new(vec![booru.clone()], 10, JsonStore::in_memory());
+    let channel = ChannelId(1);
+
+    let first = registry
+        .fetch_unseen_media(&*booru, channel, query(&[], false).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(ids(&first), ["1"]);
+
+    let second = registry
+        .fetch_unseen_media(&*booru, channel, query(&[], false).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(ids(&second), ["2"]);
+
+    // The history is separate for every channel
+    let other_channel = registry
+        .fetch_unseen_media(&*booru, ChannelId(2), query(&[], false).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(ids(&other_channel), ["1"]);
+}
+
+#[tokio::test]
+async fn shows_repeats_if_there_is_nothing_else() {
+    let booru = FakeBooru::new(&[&["1"], &["1"], &["1"], &["1"], &["1"]]);
+    let registry = BooruRegistry::new(vec![booru.clone()], 10, JsonStore::in_memory());
+
+    for _ in 0..2 {
+        let media = registry
+            .fetch_unseen_media(&*booru, ChannelId(1), query(&[], false).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(ids(&media), ["1"]);
+    }
+}
+
+#[test]
+fn negates_excluded_tags() {
+    let mut query = query(&["pony", "-sad"], false).unwrap();
+    query.exclude_tags(vec!["gore".parse().unwrap()]).unwrap();
+
+    let tags = query.search_tags();
+    let tags: Vec<_> = tags.iter().map(|it| it.as_str()).collect();
+    assert_eq!(tags, ["pony", "-sad", "-gore"]);
+
+    let err = query
+        .exclude_tags(vec!["Pony".parse().unwrap()])
+        .unwrap_err();
+    assert!(
+        matches!(&err.kind, ErrorKind::BooruTagBlocked(it) if it == "Pony"),
+        "{:?}",
+        err.kind,
+    );
+}
+
+#[tokio::test]
+async fn merges_guild_and_user_blocklists() {
+    let registry = BooruRegistry::new(Vec::new(), 10, JsonStore::in_memory());
+    let (guild, user) = (GuildId(1), UserId(2));
+
+    registry
+        .update_blocklist(BlocklistOwner::Guild(guild), |it| {
+            it.insert("gore".to_owned());
+            it.insert("sad".to_owned());
+        })
+        .await
+        .unwrap();
+    registry
+        .update_blocklist(BlocklistOwner::User(user), |it| {
+            it.insert("sad".to_owned());
+            it.insert("spiders".to_owned());
+        })
+        .await
+        .unwrap();
+
+    let tags = registry.blocked_tags(Some(guild), user).await;
+    let tags: Vec<_> = tags.iter().map(|it| it.as_str()).collect();
+    assert_eq!(tags, ["gore", "sad", "spiders"]);
+
+    // The guild blocklist doesn't apply in DMs
+    let tags = registry.blocked_tags(None, user).await;
+    let tags: Vec<_> = tags.iter().map(|it| it.as_str()).collect();
+    assert_eq!(tags, ["sad", "spiders"]);
+}
+
+fn tag(name: &str, post_count: u64, alias: Option<&str>) -> BooruTag {
+    BooruTag {
+        name: name.to_owned(),
+        post_count,
+        alias: alias.map(ToOwned::to_owned),
+    }
+}
+
+#[test]
+fn suggests_tags_with_typos_fixed() {
+    let similar = vec![
+        tag("fluttershy", 200_000, None),
+        tag("flutter valley", 50, None),
+        tag("flurry heart", 20_000, None),
+        tag("fluttershy's cottage", 3_000, None),
+    ];
+
+    let suggestions = booru::rank_tag_suggestions("flutershy", similar);
+    assert_eq!(suggestions, ["fluttershy"]);
+}
+
+#[test]
+fn suggests_tags_the_alias_resolves_to() {
+    let similar = vec![
+        tag("twilight sparkle", 300_000, Some("ts")),
+        tag("trixie", 40_000, None),
+    ];
+
+    let suggestions = booru::rank_tag_suggestions("TS", similar);
+    assert_eq!(suggestions, ["twilight sparkle"]);
+}
+
+#[test]
+fn suggests_nothing_for_existing_tags() {
+    let similar = vec![
+        tag("solo", 1_000_000, None),
+        tag("solo focus", 10_000, None),
+    ];
+
+    assert!(booru::rank_tag_suggestions("solo", similar).is_empty());
+}
+
+#[test]
+fn suggests_popular_completions_first() {
+    let similar = vec![
+        tag("twilight velvet", 2_000, None),
+        tag("twilight sparkle", 300_000, None),
+        tag("twilight (astronomy)", 500, None),
+        tag("twist", 1_000, None),
+    ];
+
+    let suggestions = booru::rank_tag_suggestions("twilight", similar);
+    assert_eq!(
+        suggestions,
+        [
+            "twilight sparkle",
+            "twilight velvet",
+            "twilight (astronomy)"
+        ]
+    );
+}
+
+#[test]
+fn posts_only_media_newer_than_last_seen_in_upload_order() {
+    let fetched = vec![
+        media("15"),
+        media("9"),
+        media("12"),
+        media("10"),
+        media("deleted"),
+    ];
+
+    let new_media = booru_subscriptions::new_media(fetched, 10);
+    assert_eq!(ids(&new_media), ["12", "15"]);
+
+    assert!(booru_subscriptions::new_media(vec![media("3")], 10).is_empty());
+}
+
+#[test]
+fn joins_words_of_excluded_tags_with_underscores() {
+    let mut query = query(&["solo"], false).unwrap();
+    query
+        .exclude_tags(vec!["big breasts".parse().unwrap()])
+        .unwrap();
+
+    let tags: Vec<_> = query
+        .underscored_search_tags()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(tags, ["solo", "-big_breasts"]);
+}
@@ -0,0 +1,91 @@
+use crate::{
+    audio_queue,
+    chapters::{self, Chapter},
+};
+use std::{ffi::OsString, time::Duration};
+
+fn starts(chapters: &[Chapter]) -> Vec<u64> {
+    chapters.iter().map(|it| it.start.as_secs()).collect()
+}
+
+#[test]
+fn parses_timestamps_at_both_ends_of_lines() {
+    let description = "\
+Tracklist:
+0:00 Intro
+03:15 - Second track
+Third track (1:02:30)
+";
+    let chapters = chapters::parse_chapters(description, Some(Duration::from_secs(2 * 60 * 60)));
+
+    assert_eq!(starts(&chapters), [0, 3 * 60 + 15, 60 * 60 + 2 * 60 + 30]);
+    let titles: Vec<_> = chapters.iter().map(|it| it.title.as_str()).collect();
+    assert_eq!(titles, ["Intro", "Second track", "Third track"]);
+}
+
+#[test]
+fn requires_the_first_chapter_at_zero() {
+    let description = "\
+My favourite part is at
+3:15 Second track
+4:20 Third track
+";
+    assert!(chapters::parse_chapters(description, None).is_empty());
+}
+
+#[test]
+fn skips_timestamps_out_of_order() {
+    let description = "\
+0:00 Intro
+5:00 Second track
+2:00 Not a chapter
+6:00 Third track
+";
+    let chapters = chapters::parse_chapters(description, None);
+    assert_eq!(starts(&chapters), [0, 5 * 60, 6 * 60]);
+}
+
+#[test]
+fn stops_at_timestamps_past_the_duration() {
+    let description = "\
+0:00 Intro
+1:00 Second track
+10:00 Past the end
+";
+    let chapters = chapters::parse_chapters(description, Some(Duration::from_secs(5 * 60)));
+    assert_eq!(starts(&chapters), [0, 60]);
+}
+
+#[test]
+fn requires_at_least_two_chapters() {
+    assert!(chapters::parse_chapters("0:00 The only one", None).is_empty());
+
+    // The second timestamp is past the end, so only one chapter is left
+    let description = "0:00 Intro\n3:00 Outro";
+    let duration = Some(Duration::from_secs(2 * 60));
+    assert!(chapters::parse_chapters(description, duration).is_empty());
+}
+
+#[test]
+fn finds_the_chapter_at_position() {
+    let chapters = chapters::parse_chapters("0:00 First\n1:00 Second", None);
+
+    let at = |secs| chapters::chapter_index_at(&chapters, Duration::from_secs(secs));
+    assert_eq!(at(0), Some(0));
+    assert_eq!(at(59), Some(0));
+    assert_eq!(at(60), Some(1));
+    assert_eq!(at(60 * 60), Some(1));
+}
+
+#[test]
+fn seeks_the_input_before_decoding() {
+    let args = audio_queue::ffmpeg_args("in.webm".as_ref(), true, Duration::from_millis(90_500));
+    let args: Vec<_> = args.iter().map(|it| it.to_str().unwrap()).collect();
+
+    assert_eq!(args[..5], ["-ss", "90.500", "-i", "in.webm", "-af"]);
+    assert!(args[5].starts_with("loudnorm="), "{}", args[5]);
+
+    // No seeking from the very beginning
+    let args = audio_queue::ffmpeg_args("in.webm".as_ref(), false, Duration::default());
+    assert_eq!(args[..2], [OsString::from("-i"), OsString::from("in.webm")]);
+}
@@ -0,0 +1,87 @@
+use super::{http_client, json_mock, mock_url};
+use crate::{
+    booru::{Booru, BooruCredentials, BooruQuery, BooruRating},
+    danbooru::DanbooruService,
+};
+use mockito::Matcher;
+
+const POSTS: &str = include_str!("fixtures/danbooru_posts.json");
+
+#[tokio::test]
+async fn skips_posts_without_file_url() {
+    let posts_mock = json_mock("GET", "/danbooru/posts.json", POSTS)
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("tags".to_owned(), "1girl solo".to_owned()),
+            Matcher::UrlEncoded("random".to_owned(), "true".to_owned()),
+            Matcher::UrlEncoded("login".to_owned(), "user".to_owned()),
+            Matcher::UrlEncoded("api_key".to_owned(), "api-key".to_owned()),
+        ]))
+        .create();
+
+    let danbooru = DanbooruService::new(
+        Some(BooruCredentials {
+            login: "user".to_owned(),
+            api_key: "api-key".to_owned(),
+        }),
+        mock_url("danbooru"),
+        http_client(),
+    );
+
+    let post = danbooru
+        .fetch_media(
+            &BooruQuery::new(
+                vec!["1girl".parse().unwrap(), "solo".parse().unwrap()],
+                true,
+            )
+            .unwrap(),
+        )
+        .await
+        .unwrap()
+        .remove(0);
+
+    assert_eq!(post.id, "4130117");
+    assert_eq!(post.score, 23);
+    assert_eq!(post.rating, Some(BooruRating::Questionable));
+    assert_eq!(post.mime_type, "image/jpeg");
+    assert!(post.tags.iter().any(|it| it == "blue_sky"));
+    assert_eq!(
+        post.webpage_url.as_str(),
+        "https://danbooru.donmai.us/posts/4130117"
+    );
+    assert_eq!(
+        post.created_at.unwrap().to_rfc3339(),
+        "2020-10-14T17:51:08.123+00:00"
+    );
+
+    posts_mock.assert();
+}
+
+#[tokio::test]
+async fn filters_out_blocked_tags_without_credentials() {
+    // Anonymous users may search by only 2 tags, so the blocked ones are not sent
+    let posts_mock = json_mock("GET", "/danbooru-anonymous/posts.json", POSTS)
+        .match_query(Matcher::UrlEncoded(
+            "tags".to_owned(),
+            "1girl solo".to_owned(),
+        ))
+        .create();
+
+    let danbooru = DanbooruService::new(None, mock_url("danbooru-anonymous"), http_client());
+
+    let mut query = BooruQuery::new(
+        vec!["1girl".parse().unwrap(), "solo".parse().unwrap()],
+        true,
+    )
+    .unwrap();
+    query.limit = 10;
+    query
+        .exclude_tags(vec!["blue sky".parse().unwrap()])
+        .unwrap();
+
+    let posts = danbooru.fetch_media(&query).await.unwrap();
+
+    // The only post with a file url has the blocked tag
+    assert!(posts.is_empty());
+
+    posts_mock.assert();
+}
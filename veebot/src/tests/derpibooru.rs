@@ -0,0 +1,150 @@
+use super::{http_client, json_mock, mock_url};
+use crate::{
+    booru::{self, Booru, BooruQuery, BooruRating},
+    derpibooru::DerpibooruService,
+};
+use mockito::Matcher;
+
+const SEARCH: &str = include_str!("fixtures/derpibooru_search.json");
+const TAGS: &str = include_str!("fixtures/derpibooru_tags.json");
+
+#[tokio::test]
+async fn fetches_random_media() {
+    let search_mock = json_mock("GET", "/derpibooru/search/images", SEARCH)
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("sf".to_owned(), "random".to_owned()),
+            Matcher::UrlEncoded("per_page".to_owned(), "1".to_owned()),
+            Matcher::UrlEncoded("filter_id".to_owned(), "56027".to_owned()),
+            Matcher::UrlEncoded("key".to_owned(), "api-key".to_owned()),
+            Matcher::UrlEncoded("q".to_owned(), "fluttershy".to_owned()),
+        ]))
+        .create();
+
+    let derpibooru = DerpibooruService::new(
+        "api-key".to_owned(),
+        "100073".to_owned(),
+        "56027".to_owned(),
+        Default::default(),
+        mock_url("derpibooru"),
+        http_client(),
+    );
+
+    let image = derpibooru
+        .fetch_media(&BooruQuery::new(vec!["fluttershy".parse().unwrap()], true).unwrap())
+        .await
+        .unwrap()
+        .remove(0);
+
+    assert_eq!(image.id, "2454189");
+    assert!(image.is_image());
+    assert_eq!(image.score, 412);
+    assert_eq!(image.rating, Some(BooruRating::Safe));
+    assert!(image.tags.iter().any(|it| it == "fluttershy"));
+    assert_eq!(
+        image.webpage_url.as_str(),
+        "https://derpibooru.org/images/2454189"
+    );
+    assert_eq!(
+        image.media_url.as_str(),
+        "https://derpicdn.net/img/view/2020/10/14/2454189.png"
+    );
+
+    search_mock.assert();
+}
+
+#[tokio::test]
+async fn fetches_top_media_in_sfw_channel() {
+    let search_mock = json_mock("GET", "/derpibooru-top/search/images", SEARCH)
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("sf".to_owned(), "score".to_owned()),
+            Matcher::UrlEncoded("sd".to_owned(), "desc".to_owned()),
+            Matcher::UrlEncoded("per_page".to_owned(), "3".to_owned()),
+            Matcher::UrlEncoded("filter_id".to_owned(), "100073".to_owned()),
+        ]))
+        .create();
+
+    let derpibooru = DerpibooruService::new(
+        "api-key".to_owned(),
+        "100073".to_owned(),
+        "56027".to_owned(),
+        Default::default(),
+        mock_url("derpibooru-top"),
+        http_client(),
+    );
+
+    let mut query = BooruQuery::new(vec!["sort:score".parse().unwrap()], false).unwrap();
+    query.limit = 3;
+
+    let images = derpibooru.fetch_media(&query).await.unwrap();
+
+    assert_eq!(images.len(), 1);
+
+    search_mock.assert();
+}
+
+#[tokio::test]
+async fn finds_similar_tags() {
+    let tags_mock = json_mock("GET", "/derpibooru-tags/search/tags", TAGS)
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("q".to_owned(), "name:flu*".to_owned()),
+            Matcher::UrlEncoded("key".to_owned(), "api-key".to_owned()),
+        ]))
+        .create();
+
+    let derpibooru = DerpibooruService::new(
+        "api-key".to_owned(),
+        "100073".to_owned(),
+        "56027".to_owned(),
+        Default::default(),
+        mock_url("derpibooru-tags"),
+        http_client(),
+    );
+
+    let tags = derpibooru.find_similar_tags("Fluttershi").await.unwrap();
+
+    assert_eq!(tags.len(), 3);
+    assert_eq!(tags[0].name, "fluttershy");
+    assert_eq!(tags[0].post_count, 312451);
+    assert_eq!(tags[1].name, "fluttershy");
+    assert_eq!(tags[1].alias.as_deref(), Some("flutters"));
+    assert_eq!(tags[2].name, "oc:fluffle puff");
+
+    assert_eq!(
+        booru::rank_tag_suggestions("Fluttershi", tags),
+        ["fluttershy"]
+    );
+
+    tags_mock.assert();
+}
+
+#[tokio::test]
+async fn reverse_searches_image() {
+    let reverse_mock = json_mock("POST", "/derpibooru-reverse/search/reverse", SEARCH)
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("url".to_owned(), "https://example.com/pony.png".to_owned()),
+            Matcher::UrlEncoded("key".to_owned(), "api-key".to_owned()),
+        ]))
+        // Same image is found with every distance threshold
+        .expect(3)
+        .create();
+
+    let derpibooru = DerpibooruService::new(
+        "api-key".to_owned(),
+        "100073".to_owned(),
+        "56027".to_owned(),
+        Default::default(),
+        mock_url("derpibooru-reverse"),
+        http_client(),
+    );
+
+    let matches = derpibooru
+        .reverse_search(&"https://example.com/pony.png".parse().unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].media.id, "2454189");
+    assert!((matches[0].min_similarity - 0.95).abs() < f64::EPSILON);
+
+    reverse_mock.assert();
+}
@@ -0,0 +1,51 @@
+use super::{http_client, json_mock, mock_url};
+use crate::{
+    booru::{Booru, BooruCredentials, BooruQuery, BooruRating},
+    e621::{E621Service, E621Site},
+};
+use mockito::Matcher;
+
+const POSTS: &str = include_str!("fixtures/e621_posts.json");
+
+#[tokio::test]
+async fn fetches_random_media() {
+    let posts_mock = json_mock("GET", "/e621/posts.json", POSTS)
+        // e926 doesn't need the rating tag even in a SFW channel
+        .match_query(Matcher::UrlEncoded(
+            "tags".to_owned(),
+            "pony order:random".to_owned(),
+        ))
+        // base64 of `user:api-key`
+        .match_header("authorization", "Basic dXNlcjphcGkta2V5")
+        .create();
+
+    let e621 = E621Service::new(
+        E621Site::E926,
+        Some(BooruCredentials {
+            login: "user".to_owned(),
+            api_key: "api-key".to_owned(),
+        }),
+        mock_url("e621"),
+        http_client(),
+    );
+
+    assert_eq!(e621.name(), "e926");
+
+    let post = e621
+        .fetch_media(&BooruQuery::new(vec!["pony".parse().unwrap()], false).unwrap())
+        .await
+        .unwrap()
+        .remove(0);
+
+    // The first post has no file url, so it must be skipped
+    assert_eq!(post.id, "2458411");
+    assert_eq!(post.score, 40);
+    assert_eq!(post.rating, Some(BooruRating::Safe));
+    assert_eq!(post.mime_type, "video/webm");
+    assert!(!post.is_image());
+    assert!(post.tags.iter().any(|it| it == "fluttershy_(mlp)"));
+    assert!(post.tags.iter().any(|it| it == "flying"));
+    assert_eq!(post.webpage_url.as_str(), "https://e926.net/posts/2458411");
+
+    posts_mock.assert();
+}
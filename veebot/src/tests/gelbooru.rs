@@ -0,0 +1,78 @@
+use super::{http_client, mock_url};
+use crate::{
+    booru::{Booru, BooruQuery, BooruRating},
+    gelbooru::GelbooruService,
+};
+use mockito::Matcher;
+
+const SEARCH: &str = include_str!("fixtures/gelbooru_search.json");
+
+fn gelbooru_service(prefix: &str) -> GelbooruService {
+    GelbooruService::new(
+        "api-key".to_owned(),
+        "user-id".to_owned(),
+        mock_url(prefix),
+        http_client(),
+    )
+}
+
+fn search_query() -> Matcher {
+    Matcher::AllOf(vec![
+        Matcher::UrlEncoded("page".to_owned(), "dapi".to_owned()),
+        Matcher::UrlEncoded("s".to_owned(), "post".to_owned()),
+        Matcher::UrlEncoded("q".to_owned(), "index".to_owned()),
+        Matcher::UrlEncoded("json".to_owned(), "1".to_owned()),
+        Matcher::UrlEncoded("api_key".to_owned(), "api-key".to_owned()),
+        Matcher::UrlEncoded("user_id".to_owned(), "user-id".to_owned()),
+    ])
+}
+
+#[tokio::test]
+async fn fetches_random_media() {
+    let search_mock = mockito::mock("GET", "/gelbooru")
+        .match_query(search_query())
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(SEARCH)
+        .create();
+
+    let image = gelbooru_service("gelbooru")
+        .fetch_media(&BooruQuery::new(vec!["pony".parse().unwrap()], true).unwrap())
+        .await
+        .unwrap()
+        .remove(0);
+
+    assert_eq!(image.id, "5634271");
+    assert_eq!(image.score, 7);
+    assert_eq!(image.rating, Some(BooruRating::Safe));
+    assert_eq!(image.mime_type, "image/jpeg");
+    assert!(image.tags.iter().any(|it| it == "pony"));
+    assert_eq!(
+        image.webpage_url.as_str(),
+        "https://gelbooru.com/index.php?page=post&s=view&id=5634271"
+    );
+    assert_eq!(
+        image.created_at.unwrap().to_rfc3339(),
+        "2020-10-14T18:51:08+00:00"
+    );
+
+    search_mock.assert();
+}
+
+#[tokio::test]
+async fn returns_none_when_nothing_is_found() {
+    // Gelbooru responds with an empty body instead of an empty JSON array
+    let search_mock = mockito::mock("GET", "/gelbooru-empty")
+        .match_query(search_query())
+        .with_status(200)
+        .create();
+
+    let images = gelbooru_service("gelbooru-empty")
+        .fetch_media(&BooruQuery::new(vec!["nonexistent_tag".parse().unwrap()], true).unwrap())
+        .await
+        .unwrap();
+
+    assert!(images.is_empty());
+
+    search_mock.assert();
+}
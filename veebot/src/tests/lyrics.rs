@@ -0,0 +1,36 @@
+use crate::lyrics::lyrics_query_from_title;
+
+#[test]
+fn removes_noise_from_titles() {
+    assert_eq!(
+        lyrics_query_from_title("Artist - Song (Official Video)", None),
+        "Artist - Song"
+    );
+    assert_eq!(
+        lyrics_query_from_title("Artist - Song (feat. Other) [Official Lyric Video]", None),
+        "Artist - Song"
+    );
+    assert_eq!(
+        lyrics_query_from_title("Artist - Song Lyrics", None),
+        "Artist - Song"
+    );
+}
+
+#[test]
+fn uses_channel_as_artist_if_title_has_none() {
+    assert_eq!(
+        lyrics_query_from_title("Song [HD]", Some("Artist - Topic")),
+        "Artist Song"
+    );
+    assert_eq!(
+        lyrics_query_from_title("Song Official Music Video", Some("ArtistVEVO")),
+        "Artist Song"
+    );
+    assert_eq!(lyrics_query_from_title("Song", None), "Song");
+
+    // The artist from the title is preferred over the channel
+    assert_eq!(
+        lyrics_query_from_title("Artist - Song", Some("Some Label")),
+        "Artist - Song"
+    );
+}
@@ -0,0 +1,40 @@
+//! Tests of the HTTP services against a local mock server.
+//!
+//! The mock server responds with the fixtures that were recorded from the real APIs
+//! (with the unrelated items removed). Every test mounts its mocks under its own
+//! path prefix, so that the tests don't interfere with each other when run in parallel.
+
+mod audio_policy;
+mod booru;
+mod chapters;
+mod danbooru;
+mod derpibooru;
+mod e621;
+mod gelbooru;
+mod lyrics;
+mod music_links;
+mod recording;
+mod safebooru;
+mod twibooru;
+mod yt;
+mod ytdl;
+
+use crate::util;
+use std::sync::Arc;
+use url::Url;
+
+/// Returns the url of the mock server with the given path prefix.
+fn mock_url(prefix: &str) -> Url {
+    util::url_with_segments(&mockito::server_url().parse().unwrap(), &[prefix])
+}
+
+fn http_client() -> Arc<reqwest::Client> {
+    Arc::new(util::create_http_client())
+}
+
+fn json_mock(method: &str, path: &str, body: &str) -> mockito::Mock {
+    mockito::mock(method, path)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(body)
+}
@@ -0,0 +1,194 @@
+use super::{http_client, json_mock, mock_url};
+use crate::{
+    music_links::{MusicLink, MusicLinkService, MusicTrack, SpotifyCredentials},
+    yt::YtService,
+    yt_cache::YtCache,
+    yt_quota::YtApiKeyPool,
+    ErrorKind,
+};
+use mockito::Matcher;
+use std::time::Duration;
+
+const SPOTIFY_TOKEN: &str = include_str!("fixtures/spotify_token.json");
+const SPOTIFY_PLAYLIST_TRACKS: &str = include_str!("fixtures/spotify_playlist_tracks.json");
+const ITUNES_LOOKUP: &str = include_str!("fixtures/itunes_lookup.json");
+const YT_VIDEOS: &str = include_str!("fixtures/yt_videos.json");
+
+fn music_link_service(prefix: &str) -> MusicLinkService {
+    MusicLinkService::new(
+        Some(SpotifyCredentials {
+            client_id: "client-id".to_owned(),
+            client_secret: "client-secret".to_owned(),
+        }),
+        mock_url(prefix),
+        mock_url(prefix),
+        mock_url(prefix),
+        http_client(),
+    )
+}
+
+fn parse_link(url: &str) -> crate::Result<MusicLink> {
+    MusicLinkService::parse_link(&url.parse().unwrap())
+}
+
+#[test]
+fn parses_spotify_links() {
+    assert_eq!(
+        parse_link("https://open.spotify.com/track/4iV5W9uYEdYUVa79Axb7Rh?si=abc").unwrap(),
+        MusicLink::SpotifyTrack("4iV5W9uYEdYUVa79Axb7Rh".to_owned())
+    );
+    assert_eq!(
+        parse_link("https://open.spotify.com/intl-de/album/1DFixLWuPkv3KT3TnV35m3").unwrap(),
+        MusicLink::SpotifyAlbum("1DFixLWuPkv3KT3TnV35m3".to_owned())
+    );
+    assert_eq!(
+        parse_link("https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M").unwrap(),
+        MusicLink::SpotifyPlaylist("37i9dQZF1DXcBWIGoYBM5M".to_owned())
+    );
+}
+
+#[test]
+fn parses_apple_music_links() {
+    assert_eq!(
+        parse_link("https://music.apple.com/us/album/compilation/1440857781?i=1440857782").unwrap(),
+        MusicLink::Apple("1440857782".to_owned())
+    );
+    assert_eq!(
+        parse_link("https://music.apple.com/us/album/compilation/1440857781").unwrap(),
+        MusicLink::Apple("1440857781".to_owned())
+    );
+    assert_eq!(
+        parse_link("https://music.apple.com/us/song/restricted-track/1440857782").unwrap(),
+        MusicLink::Apple("1440857782".to_owned())
+    );
+}
+
+#[test]
+fn rejects_unsupported_music_links() {
+    for url in &[
+        "https://open.spotify.com/artist/0OdUWJ0sBjDrqHygGUXeCF",
+        "https://open.spotify.com/track",
+        "https://music.apple.com/us/artist/some-label/123",
+        "https://music.apple.com/us/album/compilation",
+        "https://example.com/track/123",
+    ] {
+        let err = parse_link(url).unwrap_err();
+        assert!(
+            matches!(err.kind, ErrorKind::UnsupportedMusicLink(_)),
+            "{}: {:?}",
+            url,
+            err.kind,
+        );
+    }
+}
+
+#[tokio::test]
+async fn resolves_spotify_playlist_tracks() {
+    let token_mock = json_mock("POST", "/spotify/api/token", SPOTIFY_TOKEN)
+        .match_header("authorization", Matcher::Regex("^Basic ".to_owned()))
+        .expect(1)
+        .create();
+
+    let tracks_mock = json_mock(
+        "GET",
+        "/spotify/playlists/37i9dQZF1DXcBWIGoYBM5M/tracks",
+        SPOTIFY_PLAYLIST_TRACKS,
+    )
+    .match_query(Matcher::UrlEncoded("limit".to_owned(), "10".to_owned()))
+    .match_header("authorization", "Bearer spotify-token")
+    .expect(2)
+    .create();
+
+    let music_links = music_link_service("spotify");
+    let url = "https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M"
+        .parse()
+        .unwrap();
+
+    let tracks = music_links.resolve(&url).await.unwrap();
+
+    // The removed track is skipped
+    let queries: Vec<_> = tracks.iter().map(MusicTrack::search_query).collect();
+    assert_eq!(
+        queries,
+        [
+            "Some Label - Restricted track",
+            "First Artist, Second Artist - Night Drive"
+        ]
+    );
+    assert_eq!(tracks[0].duration, Some(Duration::from_secs(221)));
+
+    // The token must be reused until it expires
+    music_links.resolve(&url).await.unwrap();
+
+    token_mock.assert();
+    tracks_mock.assert();
+}
+
+#[tokio::test]
+async fn resolves_apple_music_songs_via_itunes() {
+    let lookup_mock = json_mock("GET", "/itunes/lookup", ITUNES_LOOKUP)
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("id".to_owned(), "1440857781".to_owned()),
+            Matcher::UrlEncoded("entity".to_owned(), "song".to_owned()),
+        ]))
+        .create();
+
+    let url = "https://music.apple.com/us/album/compilation/1440857781"
+        .parse()
+        .unwrap();
+
+    let tracks = music_link_service("itunes").resolve(&url).await.unwrap();
+
+    // The album itself is not a track
+    let queries: Vec<_> = tracks.iter().map(MusicTrack::search_query).collect();
+    assert_eq!(
+        queries,
+        ["Some Label - Restricted track", "Some Label - Bonus track"]
+    );
+    assert_eq!(tracks[0].duration, Some(Duration::from_secs(221)));
+    assert_eq!(tracks[1].duration, None);
+
+    lookup_mock.assert();
+}
+
+#[tokio::test]
+async fn prefers_videos_with_matching_title_and_duration() {
+    let videos_mock = json_mock("GET", "/music-links-yt/videos", YT_VIDEOS).create();
+
+    let yt = YtService::new(
+        YtApiKeyPool::new(vec!["key".to_owned()]),
+        YtCache::open(None).await.unwrap(),
+        None,
+        mock_url("music-links-yt"),
+        mock_url("music-links-yt"),
+        http_client(),
+    );
+
+    // The first video is the official one, the second one is an unrelated mix
+    let videos = yt
+        .find_videos_by_ids(&["bbbbbbbbbbb", "aaaaaaaaaaa"])
+        .await
+        .unwrap();
+
+    let track = MusicTrack {
+        artists: vec!["Some Label".to_owned()],
+        title: "Restricted track".to_owned(),
+        duration: Some(Duration::from_secs(221)),
+    };
+
+    let official = track.match_confidence(&videos[0]);
+    let mix = track.match_confidence(&videos[1]);
+
+    assert!(official > 0.99, "{}", official);
+    assert!(mix < 0.1, "{}", mix);
+
+    // Unknown duration neither helps nor hurts much
+    let without_duration = MusicTrack {
+        duration: None,
+        ..track
+    };
+    let confidence = without_duration.match_confidence(&videos[0]);
+    assert!(confidence > 0.8 && confidence < official, "{}", confidence);
+
+    videos_mock.assert();
+}
@@ -0,0 +1,125 @@
+use crate::recording::{self, RecordedFile, RecordingBuffer, RecordingMode};
+use serenity::model::id::UserId;
+
+/// Number of samples in a single 20ms voice packet
+const PACKET: usize = 960;
+
+fn samples(file: &RecordedFile) -> Vec<i16> {
+    file.bytes[44..]
+        .chunks_exact(2)
+        .map(|it| i16::from_le_bytes([it[0], it[1]]))
+        .collect()
+}
+
+fn user_name(user: UserId) -> String {
+    match user {
+        UserId(42) => "Pinkie Pie".to_owned(),
+        _ => "unknown".to_owned(),
+    }
+}
+
+#[test]
+fn encodes_wav_header_and_samples() {
+    let wav = recording::encode_wav(&[1, -2, 3]);
+
+    assert_eq!(wav.len(), 44 + 6);
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(&wav[4..8], &42u32.to_le_bytes());
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(&wav[16..20], &16u32.to_le_bytes());
+    // PCM, mono
+    assert_eq!(&wav[20..24], &[1, 0, 1, 0]);
+    assert_eq!(&wav[24..28], &48_000u32.to_le_bytes());
+    assert_eq!(&wav[28..32], &96_000u32.to_le_bytes());
+    // Block align and bits per sample
+    assert_eq!(&wav[32..36], &[2, 0, 16, 0]);
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(&wav[40..44], &6u32.to_le_bytes());
+    assert_eq!(&wav[44..], &[1, 0, 0xfe, 0xff, 3, 0]);
+}
+
+#[test]
+fn places_packets_by_their_timestamps() {
+    let buffer = RecordingBuffer::new(RecordingMode::Mixed);
+    let mut buffer = buffer.lock().unwrap();
+
+    buffer.push_packet(1, 1000, false, &[1; PACKET]);
+    // The packet in between was lost, so there must be silence in its place
+    buffer.push_packet(1, 1000 + 2 * PACKET as u32, false, &[2; PACKET]);
+    // Late packet from before the first one is dropped
+    buffer.push_packet(1, 500, false, &[3; PACKET]);
+
+    let files = buffer.encode_wav_files("rec", user_name);
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].name, "rec.wav");
+
+    let samples = samples(&files[0]);
+    let tail = &samples[samples.len() - 3 * PACKET..];
+    assert!(tail[..PACKET].iter().all(|it| *it == 1));
+    assert!(tail[PACKET..2 * PACKET].iter().all(|it| *it == 0));
+    assert!(tail[2 * PACKET..].iter().all(|it| *it == 2));
+    assert!(!samples.contains(&3));
+}
+
+#[test]
+fn downmixes_stereo_packets() {
+    let buffer = RecordingBuffer::new(RecordingMode::Mixed);
+    let mut buffer = buffer.lock().unwrap();
+
+    buffer.push_packet(1, 0, true, &[10, 20].repeat(PACKET));
+
+    let samples = samples(&buffer.encode_wav_files("rec", user_name)[0]);
+    let voice: Vec<_> = samples.iter().filter(|it| **it != 0).collect();
+    assert_eq!(voice.len(), PACKET);
+    assert!(voice.iter().all(|it| **it == 15));
+}
+
+#[test]
+fn mixes_overlapping_audio_without_overflow() {
+    let buffer = RecordingBuffer::new(RecordingMode::Mixed);
+    let mut buffer = buffer.lock().unwrap();
+
+    buffer.push_packet(1, 0, false, &[i16::MAX; PACKET]);
+    buffer.push_packet(1, 0, false, &[i16::MAX; PACKET]);
+
+    let samples = samples(&buffer.encode_wav_files("rec", user_name)[0]);
+    assert!(samples[samples.len() - PACKET..]
+        .iter()
+        .all(|it| *it == i16::MAX));
+}
+
+#[test]
+fn assigns_packets_to_speakers_identified_later() {
+    let buffer = RecordingBuffer::new(RecordingMode::PerSpeaker);
+    let mut buffer = buffer.lock().unwrap();
+
+    // Packets may arrive before the speaking update tells who the ssrc belongs to
+    buffer.push_packet(7, 0, false, &[1; PACKET]);
+    buffer.set_speaker(7, UserId(42));
+    buffer.push_packet(7, PACKET as u32, false, &[2; PACKET]);
+
+    // The same user reconnected with another ssrc
+    buffer.set_speaker(8, UserId(42));
+    buffer.push_packet(8, 0, false, &[3; PACKET]);
+
+    let files = buffer.encode_wav_files("rec", user_name);
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].name, "rec-Pinkie_Pie.wav");
+
+    // Both tracks of the user are mixed into a single file
+    let samples = samples(&files[0]);
+    assert!(samples.contains(&2));
+}
+
+#[test]
+fn truncates_too_long_recordings() {
+    let buffer = RecordingBuffer::new(RecordingMode::Mixed);
+    let mut buffer = buffer.lock().unwrap();
+
+    buffer.push_packet(1, 0, false, &[1; PACKET]);
+    assert!(!buffer.is_truncated());
+
+    let max_samples = recording::MAX_RECORDING_DURATION.as_secs() as u32 * 48_000;
+    buffer.push_packet(1, max_samples + 1, false, &[1; PACKET]);
+    assert!(buffer.is_truncated());
+}
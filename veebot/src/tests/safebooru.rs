@@ -0,0 +1,46 @@
+use super::{http_client, json_mock, mock_url};
+use crate::{
+    booru::{Booru, BooruQuery, BooruRating},
+    safebooru::SafebooruService,
+};
+use mockito::Matcher;
+
+const SEARCH: &str = include_str!("fixtures/safebooru_search.json");
+
+#[tokio::test]
+async fn fetches_random_media() {
+    let search_mock = json_mock("GET", "/safebooru", SEARCH)
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("page".to_owned(), "dapi".to_owned()),
+            Matcher::UrlEncoded("s".to_owned(), "post".to_owned()),
+            Matcher::UrlEncoded("q".to_owned(), "index".to_owned()),
+            Matcher::UrlEncoded("json".to_owned(), "1".to_owned()),
+            Matcher::UrlEncoded("tags".to_owned(), "solo rating:safe".to_owned()),
+        ]))
+        .create();
+
+    let image = SafebooruService::new(None, mock_url("safebooru"), http_client())
+        .fetch_media(&BooruQuery::new(vec!["solo".parse().unwrap()], false).unwrap())
+        .await
+        .unwrap()
+        .remove(0);
+
+    assert_eq!(image.id, "3565870");
+    assert_eq!(image.score, 0);
+    assert_eq!(image.rating, Some(BooruRating::Safe));
+    assert_eq!(image.mime_type, "image/png");
+    assert_eq!(
+        image.media_url.as_str(),
+        "https://safebooru.org/images/3410/abcdef0123456789abcdef0123456789.png"
+    );
+    assert_eq!(
+        image.thumbnail_url.unwrap().as_str(),
+        "https://safebooru.org/thumbnails/3410/thumbnail_abcdef0123456789abcdef0123456789.jpg"
+    );
+    assert_eq!(
+        image.webpage_url.as_str(),
+        "https://safebooru.org/index.php?page=post&s=view&id=3565870"
+    );
+
+    search_mock.assert();
+}
@@ -0,0 +1,36 @@
+use super::{http_client, json_mock, mock_url};
+use crate::{
+    booru::{Booru, BooruQuery, BooruRating},
+    twibooru::TwibooruService,
+};
+use mockito::Matcher;
+
+const SEARCH: &str = include_str!("fixtures/twibooru_search.json");
+
+#[tokio::test]
+async fn matches_everything_without_tags() {
+    let search_mock = json_mock("GET", "/twibooru/search/posts", SEARCH)
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("sf".to_owned(), "random".to_owned()),
+            Matcher::UrlEncoded("q".to_owned(), "*".to_owned()),
+        ]))
+        .create();
+
+    let post = TwibooruService::new(None, mock_url("twibooru"), http_client())
+        .fetch_media(&BooruQuery::new(Vec::new(), true).unwrap())
+        .await
+        .unwrap()
+        .remove(0);
+
+    assert_eq!(post.id, "2381457");
+    assert!(post.is_image());
+    assert_eq!(post.score, 87);
+    assert_eq!(post.rating, Some(BooruRating::Questionable));
+    assert_eq!(post.webpage_url.as_str(), "https://twibooru.org/2381457");
+    assert_eq!(
+        post.thumbnail_url.unwrap().as_str(),
+        "https://cdn.twibooru.org/img/2020/10/14/2381457/thumb.gif"
+    );
+
+    search_mock.assert();
+}
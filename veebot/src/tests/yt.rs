@@ -0,0 +1,289 @@
+use super::{http_client, json_mock, mock_url};
+use crate::{
+    chapters::Chapter,
+    yt::{YtService, YtUnplayableReason, YtVideo},
+    yt_cache::YtCache,
+    yt_quota::YtApiKeyPool,
+    ErrorKind,
+};
+use mockito::Matcher;
+use std::time::Duration;
+
+const VIDEOS: &str = include_str!("fixtures/yt_videos.json");
+const SEARCH: &str = include_str!("fixtures/yt_search.json");
+const QUOTA_EXCEEDED: &str = include_str!("fixtures/yt_quota_exceeded.json");
+const CHANNEL_FEED: &str = include_str!("fixtures/yt_channel_feed.xml");
+const CHANNEL_PAGE: &str = include_str!("fixtures/yt_channel_page.html");
+
+/// Ids of the videos in the fixtures, the first one is a mix with chapters,
+/// the second one is age-restricted
+const MIX_ID: &str = "aaaaaaaaaaa";
+const RESTRICTED_ID: &str = "bbbbbbbbbbb";
+
+async fn yt_service(prefix: &str, keys: &[&str], region_code: Option<&str>) -> YtService {
+    YtService::new(
+        YtApiKeyPool::new(keys.iter().map(|it| (*it).to_owned())),
+        YtCache::open(None).await.unwrap(),
+        region_code.map(ToOwned::to_owned),
+        mock_url(prefix),
+        mock_url(prefix),
+        http_client(),
+    )
+}
+
+fn videos_query(ids: &str) -> Matcher {
+    Matcher::AllOf(vec![
+        Matcher::UrlEncoded("id".to_owned(), ids.to_owned()),
+        Matcher::UrlEncoded(
+            "part".to_owned(),
+            "snippet,contentDetails,liveStreamingDetails,status".to_owned(),
+        ),
+    ])
+}
+
+#[tokio::test]
+async fn finds_videos_by_ids_and_caches_them() {
+    let videos_mock = json_mock("GET", "/yt-videos/videos", VIDEOS)
+        .match_query(videos_query("bbbbbbbbbbb,aaaaaaaaaaa"))
+        .expect(1)
+        .create();
+
+    let yt = yt_service("yt-videos", &["key"], None).await;
+
+    let videos = yt
+        .find_videos_by_ids(&[RESTRICTED_ID, MIX_ID])
+        .await
+        .unwrap();
+
+    let ids: Vec<_> = videos.iter().map(|it| it.id()).collect();
+    assert_eq!(ids, [RESTRICTED_ID, MIX_ID]);
+
+    let mix = &videos[1];
+    assert_eq!(mix.title(), "Chill mix 2020");
+    assert_eq!(mix.channel_title(), "Mixes & more");
+    assert_eq!(
+        mix.duration(),
+        Some(Duration::from_secs(60 * 60 + 10 * 60 + 5))
+    );
+    assert!(!mix.is_livestream());
+    assert_eq!(
+        mix.chapters(),
+        [
+            Chapter {
+                start: Duration::from_secs(0),
+                title: "Intro".to_owned(),
+            },
+            Chapter {
+                start: Duration::from_secs(4 * 60 + 12),
+                title: "Second track".to_owned(),
+            },
+            Chapter {
+                start: Duration::from_secs(60 * 60 + 2 * 60 + 30),
+                title: "Third track".to_owned(),
+            },
+        ]
+    );
+
+    // The second lookup must be served from the cache
+    let cached = yt.find_videos_by_ids(&[MIX_ID]).await.unwrap();
+    assert_eq!(cached[0].title(), "Chill mix 2020");
+
+    let stats = yt.cache().stats().await;
+    assert_eq!((stats.hits, stats.misses), (1, 2));
+
+    videos_mock.assert();
+}
+
+#[tokio::test]
+async fn skips_unplayable_search_results() {
+    let search_mock = json_mock("GET", "/yt-search/search", SEARCH)
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("q".to_owned(), "chill mix".to_owned()),
+            Matcher::UrlEncoded("type".to_owned(), "video".to_owned()),
+            Matcher::UrlEncoded("key".to_owned(), "key".to_owned()),
+        ]))
+        .create();
+    let videos_mock = json_mock("GET", "/yt-search/videos", VIDEOS)
+        .match_query(videos_query("bbbbbbbbbbb,aaaaaaaaaaa"))
+        .create();
+
+    let yt = yt_service("yt-search", &["key"], None).await;
+
+    let video = yt.find_video_by_query("chill mix").await.unwrap();
+    assert_eq!(video.id(), MIX_ID);
+
+    search_mock.assert();
+    videos_mock.assert();
+}
+
+#[tokio::test]
+async fn rejects_search_when_no_results_are_playable() {
+    let _search_mock = json_mock("GET", "/yt-region/search", SEARCH).create();
+    let _videos_mock = json_mock("GET", "/yt-region/videos", VIDEOS).create();
+
+    // The mix is blocked in Germany, and the other video is age-restricted
+    let yt = yt_service("yt-region", &["key"], Some("DE")).await;
+
+    let videos = yt.find_videos_by_query("chill mix", 5).await.unwrap();
+    assert!(matches!(
+        yt.unplayable_reason(&videos[1]),
+        Some(YtUnplayableReason::RegionBlocked(region)) if region == "DE"
+    ));
+
+    let err = yt.find_video_by_query("chill mix").await.err().unwrap();
+    assert!(matches!(
+        err.kind,
+        ErrorKind::YtVideoUnplayable {
+            reason: YtUnplayableReason::AgeRestricted,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn checks_the_region_against_allowed_and_blocked_lists() {
+    let allowed_videos = VIDEOS.replace(r#""blocked": ["DE"]"#, r#""allowed": ["US", "ca"]"#);
+    assert_ne!(allowed_videos, VIDEOS);
+
+    let _blocked_mock = json_mock("GET", "/yt-region-blocked/videos", VIDEOS).create();
+    let _allowed_mock = json_mock("GET", "/yt-region-allowed/videos", &allowed_videos).create();
+
+    let blocked = yt_service("yt-region-blocked", &["key"], None).await;
+    let blocked = blocked
+        .find_videos_by_ids(&[MIX_ID])
+        .await
+        .unwrap()
+        .remove(0);
+
+    let allowed = yt_service("yt-region-allowed", &["key"], None).await;
+    let allowed = allowed
+        .find_videos_by_ids(&[MIX_ID])
+        .await
+        .unwrap()
+        .remove(0);
+
+    let is_playable = |video: &YtVideo, region| video.unplayable_reason(region).is_none();
+
+    // The region check is skipped if the region of the bot is unknown
+    assert!(is_playable(&blocked, None));
+    assert!(is_playable(&allowed, None));
+
+    assert!(is_playable(&blocked, Some("US")));
+    assert!(!is_playable(&blocked, Some("DE")));
+    assert!(!is_playable(&blocked, Some("de")));
+
+    assert!(is_playable(&allowed, Some("US")));
+    assert!(is_playable(&allowed, Some("CA")));
+    assert!(!is_playable(&allowed, Some("DE")));
+}
+
+#[tokio::test]
+async fn switches_to_the_next_key_when_the_quota_is_exhausted() {
+    let exhausted_mock = mockito::mock("GET", "/yt-quota/videos")
+        .match_query(Matcher::UrlEncoded("key".to_owned(), "first".to_owned()))
+        .with_status(403)
+        .with_header("content-type", "application/json")
+        .with_body(QUOTA_EXCEEDED)
+        .expect(1)
+        .create();
+    let ok_mock = json_mock("GET", "/yt-quota/videos", VIDEOS)
+        .match_query(Matcher::UrlEncoded("key".to_owned(), "second".to_owned()))
+        .expect(2)
+        .create();
+
+    let yt = yt_service("yt-quota", &["first", "second"], None).await;
+
+    yt.find_videos_by_ids(&[MIX_ID]).await.unwrap();
+
+    // The exhausted key must not be tried again
+    yt.cache().clear().await.unwrap();
+    yt.find_videos_by_ids(&[MIX_ID]).await.unwrap();
+
+    let stats = yt.api_keys().stats().await;
+    assert!(stats[0].exhausted);
+    assert_eq!(stats[0].used_units, 0);
+    assert!(!stats[1].exhausted);
+    assert_eq!(stats[1].used_units, 2);
+
+    exhausted_mock.assert();
+    ok_mock.assert();
+}
+
+#[tokio::test]
+async fn treats_unexpected_durations_as_unknown() {
+    let videos = VIDEOS.replace(r#""duration": "PT1H10M5S""#, r#""duration": "P1W""#);
+    assert_ne!(videos, VIDEOS);
+    let _mock = json_mock("GET", "/yt-weeks/videos", &videos).create();
+
+    let yt = yt_service("yt-weeks", &["key"], None).await;
+    let mix = yt.find_videos_by_ids(&[MIX_ID]).await.unwrap().remove(0);
+
+    assert_eq!(mix.duration(), None);
+}
+
+#[tokio::test]
+async fn parses_channel_feed() {
+    let feed_mock = mockito::mock("GET", "/yt-feed/feeds/videos.xml")
+        .match_query(Matcher::UrlEncoded(
+            "channel_id".to_owned(),
+            "UCaaaaaaaaaaaaaaaaaaaaaa".to_owned(),
+        ))
+        .with_status(200)
+        .with_header("content-type", "text/xml; charset=UTF-8")
+        .with_body(CHANNEL_FEED)
+        .create();
+
+    let yt = yt_service("yt-feed", &["key"], None).await;
+
+    let feed = yt.channel_feed("UCaaaaaaaaaaaaaaaaaaaaaa").await.unwrap();
+
+    assert_eq!(feed.title, "Mixes & more");
+    assert_eq!(feed.video_ids, ["ccccccccccc", MIX_ID]);
+
+    feed_mock.assert();
+}
+
+#[tokio::test]
+async fn resolves_channel_ids_from_urls() {
+    async fn channel_id(yt: &YtService, url: &str) -> crate::Result<String> {
+        yt.channel_id_from_url(&url.parse().unwrap()).await
+    }
+
+    let page_mock = mockito::mock("GET", "/yt-channel/@mixesandmore")
+        .with_status(200)
+        .with_header("content-type", "text/html; charset=utf-8")
+        .with_body(CHANNEL_PAGE)
+        .expect(2)
+        .create();
+
+    let yt = yt_service("yt-channel", &["key"], None).await;
+
+    // The channel id is taken from the url itself without any requests
+    let url = "https://www.youtube.com/channel/UCbbbbbbbbbbbbbbbbbbbbbb/videos";
+    assert_eq!(
+        channel_id(&yt, url).await.unwrap(),
+        "UCbbbbbbbbbbbbbbbbbbbbbb"
+    );
+
+    // Handles are resolved via the canonical url on the channel page
+    for url in &[
+        "https://www.youtube.com/@mixesandmore",
+        "https://m.youtube.com/@mixesandmore?app=desktop",
+    ] {
+        assert_eq!(
+            channel_id(&yt, url).await.unwrap(),
+            "UCaaaaaaaaaaaaaaaaaaaaaa"
+        );
+    }
+
+    let err = channel_id(&yt, "https://example.com/@mixesandmore")
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err.kind, ErrorKind::YtInferChannelId(_)),
+        "{:?}",
+        err.kind
+    );
+
+    page_mock.assert();
+}
@@ -0,0 +1,36 @@
+use crate::ytdl::is_public_web_url;
+
+fn is_public(url: &str) -> bool {
+    is_public_web_url(&url.parse().unwrap())
+}
+
+#[test]
+fn accepts_public_web_urls() {
+    assert!(is_public("https://soundcloud.com/artist/track"));
+    assert!(is_public("http://93.184.216.34/track.mp3"));
+    assert!(is_public(
+        "http://[2606:2800:220:1:248:1893:25c8:1946]/track.mp3"
+    ));
+}
+
+#[test]
+fn rejects_local_and_non_web_urls() {
+    for url in &[
+        "file:///etc/passwd",
+        "ftp://example.com/track.mp3",
+        "http://localhost:8080/admin",
+        "http://LOCALHOST./admin",
+        "http://printer.local/",
+        "http://127.0.0.1/",
+        "http://10.0.0.1/",
+        "http://192.168.1.1/",
+        "http://169.254.169.254/latest/meta-data/",
+        "http://0.0.0.0/",
+        "http://[::1]/",
+        "http://[fd00::1]/",
+        "http://[fe80::1]/",
+        "http://[::ffff:127.0.0.1]/",
+    ] {
+        assert!(!is_public(url), "{}", url);
+    }
+}
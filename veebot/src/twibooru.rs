@@ -0,0 +1,180 @@
+//! Symbols related to communicating with the Twibooru API
+
+use crate::{
+    booru::{Booru, BooruMedia, BooruQuery, BooruRating, BooruSort, BooruTag},
+    derpibooru,
+    util::{self, ReqwestBuilderExt, ThemeTag},
+};
+use itertools::Itertools;
+use serenity::async_trait;
+use std::sync::Arc;
+use url::Url;
+
+/// Declarations of the twibooru JSON API types.
+/// Twibooru runs on the fork of the same engine as derpibooru (Philomena),
+/// so the API is almost the same, but the images are called posts there.
+/// Reference: https://twibooru.org/pages/api
+pub(crate) mod rpc {
+    use chrono::Utc;
+    use serde::Deserialize;
+    use url::Url;
+
+    pub(crate) mod search {
+        use super::*;
+
+        #[derive(Debug, Deserialize)]
+        pub(crate) struct Response {
+            pub(crate) posts: Vec<Post>,
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Post {
+        pub(crate) id: u128,
+        /// `image/gif`, `image/jpeg`, `image/png`, `image/svg+xml` or `video/webm`
+        pub(crate) mime_type: String,
+        pub(crate) representations: PostRepresentations,
+        pub(crate) tags: Vec<String>,
+        pub(crate) created_at: chrono::DateTime<Utc>,
+        /// Upvotes minus downvotes
+        pub(crate) score: i64,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct PostRepresentations {
+        pub(crate) full: Url,
+        pub(crate) thumb: Url,
+    }
+}
+
+// Links that are shown to the users, the requests go to `TwibooruService::api_url`
+util::def_url_base!(twibooru, "https://twibooru.org");
+
+impl rpc::Post {
+    pub(crate) fn webpage_url(&self) -> Url {
+        twibooru(&[self.id.to_string()])
+    }
+
+    /// Same as on derpibooru, the rating is one of the tags
+    fn rating(&self) -> Option<BooruRating> {
+        self.tags.iter().find_map(|tag| match tag.as_str() {
+            "safe" => Some(BooruRating::Safe),
+            "suggestive" | "questionable" => Some(BooruRating::Questionable),
+            "explicit" => Some(BooruRating::Explicit),
+            _ => None,
+        })
+    }
+
+    fn into_media(self) -> BooruMedia {
+        BooruMedia {
+            id: self.id.to_string(),
+            webpage_url: self.webpage_url(),
+            rating: self.rating(),
+            media_url: self.representations.full,
+            thumbnail_url: Some(self.representations.thumb),
+            tags: self.tags,
+            score: self.score,
+            created_at: Some(self.created_at),
+            mime_type: self.mime_type,
+        }
+    }
+}
+
+pub(crate) struct TwibooruService {
+    http_client: Arc<reqwest::Client>,
+    /// The key is optional, without it the default filter of the site is used
+    api_key: Option<String>,
+    /// Base url of the API (`https://twibooru.org/api/v3`)
+    api_url: Url,
+}
+
+impl TwibooruService {
+    pub(crate) fn new(
+        api_key: Option<String>,
+        api_url: Url,
+        http_client: Arc<reqwest::Client>,
+    ) -> Self {
+        Self {
+            http_client,
+            api_key,
+            api_url,
+        }
+    }
+}
+
+#[async_trait]
+impl Booru for TwibooruService {
+    fn name(&self) -> &'static str {
+        "twibooru"
+    }
+
+    fn site(&self) -> &'static str {
+        "twibooru.org"
+    }
+
+    async fn fetch_media(&self, query: &BooruQuery) -> crate::Result<Vec<BooruMedia>> {
+        let safe_tag: ThemeTag = "safe".parse().unwrap();
+        let rating_tag = if query.nsfw { None } else { Some(&safe_tag) };
+
+        // Same as on derpibooru, the recently shown posts may be excluded right in the query
+        let tags = query
+            .search_tags()
+            .iter()
+            .chain(rating_tag)
+            .unique()
+            .map(ToString::to_string)
+            .chain(query.excluded_ids.iter().map(|id| format!("-id:{}", id)))
+            .join(",");
+
+        let sort_field = match query.sort {
+            BooruSort::Random => "random",
+            BooruSort::Score => "score",
+            BooruSort::Newest => "created_at",
+        };
+        let per_page = query.limit.to_string();
+
+        let mut params = vec![
+            ("sf", sort_field),
+            ("sd", "desc"),
+            ("per_page", &per_page),
+            // Unlike derpibooru, the query is required, so we match everything if there are no tags
+            ("q", if tags.is_empty() { "*" } else { tags.as_str() }),
+        ];
+
+        if let Some(key) = &self.api_key {
+            params.push(("key", key));
+        }
+
+        let res: rpc::search::Response = self
+            .http_client
+            .get(util::url_with_segments(&self.api_url, &["search", "posts"]))
+            .query(&params)
+            .read_json()
+            .await?;
+
+        Ok(res.posts.into_iter().map(rpc::Post::into_media).collect())
+    }
+
+    async fn find_similar_tags(&self, tag: &str) -> crate::Result<Vec<BooruTag>> {
+        let query = derpibooru::similar_tags_query(tag);
+        let mut params = vec![("q", query.as_str()), ("per_page", "50")];
+
+        if let Some(key) = &self.api_key {
+            params.push(("key", key));
+        }
+
+        // The tags are the same as on derpibooru
+        let res: derpibooru::rpc::tags::Response = self
+            .http_client
+            .get(util::url_with_segments(&self.api_url, &["search", "tags"]))
+            .query(&params)
+            .read_json()
+            .await?;
+
+        Ok(res
+            .tags
+            .into_iter()
+            .map(derpibooru::rpc::Tag::into_booru_tag)
+            .collect())
+    }
+}
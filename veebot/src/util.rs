@@ -7,7 +7,12 @@ use hhmmss::Hhmmss;
 use serde::de::DeserializeOwned;
 use serenity::{
     async_trait,
-    model::{guild::Guild, id::GuildId},
+    client::Cache,
+    model::{
+        channel::{ChannelType, GuildChannel},
+        guild::Guild,
+        id::{ChannelId, GuildId},
+    },
 };
 use tracing::{debug, warn};
 
@@ -31,18 +36,63 @@ macro_rules! _def_url_base {
 
 pub(crate) use {_def_url_base as def_url_base, _regex as regex};
 
+/// Same as [`def_url_base`], but for the base urls that are only known at runtime
+/// (e.g. the ones that may be overridden in the config to point to a local stub server).
+pub(crate) fn url_with_segments<T: AsRef<str>>(
+    base: &url::Url,
+    segments: impl IntoIterator<Item = T>,
+) -> url::Url {
+    let mut url = base.clone();
+    url.path_segments_mut()
+        .expect("base urls are never cannot-be-a-base")
+        .pop_if_empty()
+        .extend(segments);
+    url
+}
+
 #[async_trait]
 pub(crate) trait CacheExt {
     async fn guild_or_err(&self, guild_id: GuildId) -> crate::Result<Guild>;
+
+    /// Returns the channel of the guild if it is of one of the given kinds.
+    /// Used to validate the channels from the command arguments, which may
+    /// point to any channel (even in another guild).
+    async fn guild_channel_of_kind(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        kinds: &[ChannelType],
+        expected: &'static str,
+    ) -> crate::Result<GuildChannel>;
 }
 
 #[async_trait]
-impl CacheExt for serenity::client::Cache {
+impl CacheExt for Cache {
     async fn guild_or_err(&self, guild_id: GuildId) -> crate::Result<Guild> {
         self.guild(guild_id)
             .await
             .ok_or_else(|| crate::err!(DiscordGuildCacheMiss(guild_id)))
     }
+
+    async fn guild_channel_of_kind(
+        &self,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        kinds: &[ChannelType],
+        expected: &'static str,
+    ) -> crate::Result<GuildChannel> {
+        self.guild_or_err(guild_id)
+            .await?
+            .channels
+            .remove(&channel_id)
+            .filter(|it| kinds.contains(&it.kind))
+            .ok_or_else(|| {
+                crate::err!(UnexpectedChannel {
+                    channel: channel_id,
+                    expected
+                })
+            })
+    }
 }
 
 #[async_trait]
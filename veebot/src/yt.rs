@@ -1,10 +1,20 @@
 //! Symbols related to communicating with the YouTube API
 
+use serde::{Deserialize, Serialize};
 use std::{fmt, sync::Arc, time};
 use url::Url;
 use util::{regex, ReqwestBuilderExt};
 
-use crate::util;
+use crate::{
+    chapters::{self, Chapter},
+    util,
+    yt_cache::YtCache,
+    yt_quota::{self, YtApiKeyPool},
+    ytdl::YtdlTrack,
+    ErrorKind,
+};
+use serde::de::DeserializeOwned;
+use tracing::warn;
 
 /// Declarations of the types used in the YouTube's API.
 /// We could've used some crate that defines them, but
@@ -12,7 +22,7 @@ use crate::util;
 /// the ones @Veetaha has found at the time of this writing are
 /// quite outdated and unmaintained).
 mod rpc {
-    use serde::Deserialize;
+    use serde::{Deserialize, Serialize};
     use url::Url;
 
     pub(crate) mod search {
@@ -38,8 +48,8 @@ mod rpc {
     }
 
     pub(crate) mod videos {
-        use super::{ContentDetails, LiveStreamingDetails, VideoSnippet};
-        use serde::Deserialize;
+        use super::{ContentDetails, LiveStreamingDetails, VideoSnippet, VideoStatus};
+        use serde::{Deserialize, Serialize};
 
         #[derive(Deserialize)]
         #[serde(rename_all = "camelCase")]
@@ -47,58 +57,114 @@ mod rpc {
             pub(crate) items: Vec<Item>,
         }
 
-        #[derive(Deserialize)]
+        #[derive(Clone, Serialize, Deserialize)]
         #[serde(rename_all = "camelCase")]
         pub(crate) struct Item {
             pub(crate) id: String,
             pub(crate) snippet: VideoSnippet,
             pub(crate) content_details: ContentDetails,
             pub(crate) live_streaming_details: Option<LiveStreamingDetails>,
+            /// Absent in the entries of the YouTube API cache saved by the older versions of the bot
+            #[serde(default)]
+            pub(crate) status: Option<VideoStatus>,
         }
     }
 
-    #[derive(Deserialize)]
+    #[derive(Clone, Serialize, Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub(crate) struct VideoSnippet {
         pub(crate) channel_id: String,
         pub(crate) channel_title: String,
         pub(crate) title: String,
         pub(crate) thumbnails: VideoThumbnails,
+        pub(crate) live_broadcast_content: LiveBroadcastContent,
+        /// Absent in the entries of the YouTube API cache saved by the older versions of the bot
+        #[serde(default)]
+        pub(crate) description: String,
         // "publishedAt": datetime,
-        // "description": string,
     }
 
-    #[derive(Deserialize)]
+    #[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) enum LiveBroadcastContent {
+        /// The video is an active livestream
+        Live,
+        /// The livestream is scheduled, but not started yet
+        Upcoming,
+        /// The video is not a livestream (or the livestream has already ended)
+        None,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub(crate) struct VideoThumbnails {
         pub(crate) default: VideoThumbnail,
     }
 
-    #[derive(Deserialize)]
+    #[derive(Clone, Serialize, Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub(crate) struct VideoThumbnail {
         pub(crate) url: Url,
     }
 
-    #[derive(Deserialize)]
+    #[derive(Clone, Serialize, Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub(crate) struct ContentDetails {
         pub(crate) duration: String,
+        #[serde(default)]
+        pub(crate) region_restriction: Option<RegionRestriction>,
+        #[serde(default)]
+        pub(crate) content_rating: Option<ContentRating>,
     }
 
-    #[derive(Deserialize)]
+    #[derive(Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct RegionRestriction {
+        /// If present, the video is viewable only in these regions (ISO 3166-1 alpha-2 codes)
+        pub(crate) allowed: Option<Vec<String>>,
+        /// The video is not viewable in these regions
+        #[serde(default)]
+        pub(crate) blocked: Vec<String>,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct ContentRating {
+        /// The only possible value is `ytAgeRestricted`
+        pub(crate) yt_rating: Option<String>,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub(crate) struct VideoStatus {
+        /// `processed`, `uploaded`, `failed`, `rejected` or `deleted`
+        pub(crate) upload_status: String,
+        /// `public`, `unlisted` or `private`
+        pub(crate) privacy_status: String,
+        pub(crate) embeddable: bool,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub(crate) struct LiveStreamingDetails {}
 }
 
-util::def_url_base!(yt_api, "https://www.googleapis.com/youtube/v3");
+// Links that are shown to the users, the requests go to the base urls of `YtService`
 util::def_url_base!(yt, "https://www.youtube.com");
 
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct YtVideo(rpc::videos::Item);
 
 impl YtVideo {
+    /// Returns `true` only for the livestreams that are live right now.
+    /// The ended livestreams are regular videos with known duration.
     pub(crate) fn is_livestream(&self) -> bool {
-        self.0.live_streaming_details.is_some()
+        self.0.snippet.live_broadcast_content == rpc::LiveBroadcastContent::Live
+    }
+
+    /// Returns `true` for the livestreams that are scheduled, but not started yet.
+    pub(crate) fn is_upcoming(&self) -> bool {
+        self.0.snippet.live_broadcast_content == rpc::LiveBroadcastContent::Upcoming
     }
 
     pub(crate) fn url(&self) -> Url {
@@ -108,38 +174,29 @@ impl YtVideo {
     }
 
     pub(crate) fn format_duration(&self) -> impl fmt::Display {
-        let duration = self.duration();
-        if self.is_livestream() && duration == time::Duration::new(0, 0) {
-            "livestream".to_owned()
-        } else {
-            crate::util::format_duration(&duration)
+        match self.duration() {
+            Some(duration) => crate::util::format_duration(&duration),
+            None if self.is_livestream() => "🔴 LIVE".to_owned(),
+            None => "unknown".to_owned(),
         }
     }
 
-    pub(crate) fn duration(&self) -> time::Duration {
-        // FIXME: unfortunately chrono doesn't have parsing of duration from iso8601
-        // create an upstream issue about that?
-        let dur = iso8601::duration(&self.0.content_details.duration).unwrap();
-
-        time::Duration::from_secs_f64(match dur {
-            iso8601::Duration::YMDHMS {
-                year,
-                month,
-                day,
-                hour,
-                minute,
-                second,
-                millisecond: _,
-            } => {
-                f64::from(year) * 60. * 60. * 24. * 30. * 12.
-                    + f64::from(month) * 60. * 60. * 24. * 30.
-                    + f64::from(day) * 60. * 60. * 24.
-                    + f64::from(hour) * 60. * 60.
-                    + f64::from(minute) * 60.
-                    + f64::from(second)
+    /// Returns `None` for the livestreams, because their duration is unknown,
+    /// or if the API returned the duration we can't parse.
+    pub(crate) fn duration(&self) -> Option<time::Duration> {
+        // Upcoming livestreams report a bogus `P0D` duration
+        if self.is_livestream() || self.is_upcoming() {
+            return None;
+        }
+
+        match parse_duration(&self.0.content_details.duration) {
+            Ok(it) => Some(it),
+            Err(err) => {
+                // Unknown duration is handled the same way as the livestream's one
+                warn!(?err, video_id = %self.id(), "Failed to parse the video duration");
+                None
             }
-            iso8601::Duration::Weeks(_) => todo!(),
-        })
+        }
     }
 
     pub(crate) fn thumbnail_url(&self) -> &Url {
@@ -154,38 +211,449 @@ impl YtVideo {
         &self.0.snippet.title
     }
 
+    pub(crate) fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    pub(crate) fn channel_id(&self) -> &str {
+        &self.0.snippet.channel_id
+    }
+
     pub(crate) fn channel_url(&self) -> Url {
-        yt(&["channel", &self.0.snippet.channel_id])
+        channel_url(&self.0.snippet.channel_id)
+    }
+
+    /// Returns the reason why the bot won't be able to stream the video,
+    /// or `None` if it is playable (as far as the API tells).
+    /// The region check is skipped if the region of the bot is unknown.
+    pub(crate) fn unplayable_reason(
+        &self,
+        region_code: Option<&str>,
+    ) -> Option<YtUnplayableReason> {
+        let details = &self.0.content_details;
+
+        if self.is_upcoming() {
+            return Some(YtUnplayableReason::Upcoming);
+        }
+
+        if let Some(status) = &self.0.status {
+            if status.privacy_status == "private" {
+                return Some(YtUnplayableReason::Private);
+            }
+            if matches!(
+                status.upload_status.as_str(),
+                "failed" | "rejected" | "deleted"
+            ) {
+                return Some(YtUnplayableReason::Removed);
+            }
+            if !status.embeddable {
+                return Some(YtUnplayableReason::NotEmbeddable);
+            }
+        }
+
+        let yt_rating = details
+            .content_rating
+            .as_ref()
+            .and_then(|it| it.yt_rating.as_deref());
+        if yt_rating == Some("ytAgeRestricted") {
+            return Some(YtUnplayableReason::AgeRestricted);
+        }
+
+        if let (Some(restriction), Some(region)) = (&details.region_restriction, region_code) {
+            let has_region =
+                |regions: &[String]| regions.iter().any(|it| it.eq_ignore_ascii_case(region));
+            let allowed = restriction.allowed.as_deref().map_or(true, has_region);
+            if !allowed || has_region(&restriction.blocked) {
+                return Some(YtUnplayableReason::RegionBlocked(region.to_owned()));
+            }
+        }
+
+        None
+    }
+
+    /// Returns the chapters listed in the description of the video (empty if there are none).
+    pub(crate) fn chapters(&self) -> Vec<Chapter> {
+        chapters::parse_chapters(&self.0.snippet.description, self.duration())
+    }
+
+    /// Converts the metadata of the YouTube video received from `youtube-dl`
+    /// to the same shape as it is returned by the YouTube Data API.
+    fn from_ytdl_track(track: &YtdlTrack) -> Self {
+        let id = track.site_id().to_owned();
+        let thumbnail = match track.thumbnail_url() {
+            Some(it) => it.clone(),
+            None => format!("https://i.ytimg.com/vi/{}/default.jpg", id)
+                .parse()
+                .unwrap(),
+        };
+        let secs = track.duration().map_or(0, |it| it.as_secs());
+
+        Self(rpc::videos::Item {
+            id,
+            snippet: rpc::VideoSnippet {
+                channel_id: track.channel_id().unwrap_or_default().to_owned(),
+                channel_title: track.uploader().unwrap_or_default().to_owned(),
+                title: track.title().to_owned(),
+                thumbnails: rpc::VideoThumbnails {
+                    default: rpc::VideoThumbnail { url: thumbnail },
+                },
+                live_broadcast_content: if track.is_livestream() {
+                    rpc::LiveBroadcastContent::Live
+                } else {
+                    rpc::LiveBroadcastContent::None
+                },
+                description: track.description().unwrap_or_default().to_owned(),
+            },
+            content_details: rpc::ContentDetails {
+                duration: format!("PT{}S", secs),
+                region_restriction: None,
+                content_rating: None,
+            },
+            live_streaming_details: None,
+            // youtube-dl has already extracted the video, so it's considered playable
+            status: None,
+        })
+    }
+}
+
+/// Reason why the bot can't stream the YouTube video
+#[derive(Debug)]
+pub(crate) enum YtUnplayableReason {
+    Private,
+    Removed,
+    NotEmbeddable,
+    AgeRestricted,
+    /// The livestream is scheduled, but not started yet
+    Upcoming,
+    /// Contains the region the bot streams from
+    RegionBlocked(String),
+}
+
+impl fmt::Display for YtUnplayableReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            YtUnplayableReason::Private => f.write_str("the video is private"),
+            YtUnplayableReason::Removed => f.write_str("the video was removed or failed to upload"),
+            YtUnplayableReason::NotEmbeddable => {
+                f.write_str("the uploader doesn't allow playing the video outside of YouTube")
+            }
+            YtUnplayableReason::AgeRestricted => {
+                f.write_str("the video is age-restricted, so it can be watched only when signed in")
+            }
+            YtUnplayableReason::Upcoming => f.write_str("the livestream hasn't started yet"),
+            YtUnplayableReason::RegionBlocked(region) => {
+                write!(
+                    f,
+                    "the video is not available in the bot's region (`{}`)",
+                    region
+                )
+            }
+        }
+    }
+}
+
+/// Parses the ISO 8601 duration of the video as the API returns it (e.g. `PT3M41S`).
+fn parse_duration(duration: &str) -> crate::Result<time::Duration> {
+    // FIXME: unfortunately chrono doesn't have parsing of duration from iso8601
+    // create an upstream issue about that?
+    let dur = iso8601::duration(duration)
+        .map_err(|_| crate::err!(YtVideoDuration(duration.to_owned())))?;
+
+    Ok(time::Duration::from_secs_f64(match dur {
+        iso8601::Duration::YMDHMS {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            millisecond: _,
+        } => {
+            f64::from(year) * 60. * 60. * 24. * 30. * 12.
+                + f64::from(month) * 60. * 60. * 24. * 30.
+                + f64::from(day) * 60. * 60. * 24.
+                + f64::from(hour) * 60. * 60.
+                + f64::from(minute) * 60.
+                + f64::from(second)
+        }
+        // The API never returns the durations in weeks
+        iso8601::Duration::Weeks(_) => {
+            return Err(crate::err!(YtVideoDuration(duration.to_owned())))
+        }
+    }))
+}
+
+pub(crate) fn channel_url(channel_id: &str) -> Url {
+    yt(&["channel", channel_id])
+}
+
+/// Returns `true` if the string looks like a YouTube channel id (e.g. `UCq-Fj5jknLsUf-MWSy4_brA`)
+pub(crate) fn is_channel_id(input: &str) -> bool {
+    regex!(r#"^UC[a-zA-Z0-9_-]{22}$"#).is_match(input)
+}
+
+pub(crate) struct YtChannelFeed {
+    /// Title of the channel
+    pub(crate) title: String,
+    /// Ids of the latest uploaded videos, the newest first
+    pub(crate) video_ids: Vec<String>,
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Kind of the error the API responds with when the key can't be used right now
+enum QuotaError {
+    /// The daily quota of the key was spent
+    Exhausted,
+    /// Too many requests in a short period of time, the key may be used a bit later
+    RateLimited,
+}
+
+impl QuotaError {
+    /// https://developers.google.com/youtube/v3/docs/errors
+    fn from_error(err: &crate::Error) -> Option<Self> {
+        let (status, body) = match &err.kind {
+            ErrorKind::BadHttpResponseStatusCode { status, body } => (*status, body),
+            _ => return None,
+        };
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Some(QuotaError::RateLimited);
+        }
+        if status != reqwest::StatusCode::FORBIDDEN {
+            return None;
+        }
+        if body.contains("quotaExceeded") || body.contains("dailyLimitExceeded") {
+            Some(QuotaError::Exhausted)
+        } else if body.contains("rateLimitExceeded") || body.contains("userRateLimitExceeded") {
+            Some(QuotaError::RateLimited)
+        } else {
+            None
+        }
     }
 }
 
 pub(crate) struct YtService {
     http_client: Arc<reqwest::Client>,
-    yt_data_api_key: String,
+    api_keys: YtApiKeyPool,
+    cache: YtCache,
+    /// Region the bot streams the audio from, used to reject the region-locked videos
+    region_code: Option<String>,
+    /// Base url of the YouTube Data API (`https://www.googleapis.com/youtube/v3`)
+    api_url: Url,
+    /// Base url of the YouTube website (`https://www.youtube.com`), used for the RSS feeds
+    site_url: Url,
 }
 
+/// Number of search results we look through to find a playable video
+const PLAYABLE_SEARCH_RESULTS: u8 = 5;
+
 impl YtService {
-    pub(crate) fn new(yt_data_api_key: String, http_client: Arc<reqwest::Client>) -> Self {
+    pub(crate) fn new(
+        api_keys: YtApiKeyPool,
+        cache: YtCache,
+        region_code: Option<String>,
+        api_url: Url,
+        site_url: Url,
+        http_client: Arc<reqwest::Client>,
+    ) -> Self {
         Self {
-            yt_data_api_key,
+            api_keys,
+            cache,
+            region_code,
+            api_url,
+            site_url,
             http_client,
         }
     }
 
-    /// https://developers.google.com/youtube/v3/docs/videos/list
+    pub(crate) fn unplayable_reason(&self, video: &YtVideo) -> Option<YtUnplayableReason> {
+        video.unplayable_reason(self.region_code.as_deref())
+    }
+
+    /// Returns an error if the bot won't be able to stream the video.
+    pub(crate) fn check_playable(&self, video: &YtVideo) -> crate::Result<()> {
+        match self.unplayable_reason(video) {
+            Some(reason) => Err(crate::err!(YtVideoUnplayable {
+                title: video.title().to_owned(),
+                reason,
+            })),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn cache(&self) -> &YtCache {
+        &self.cache
+    }
+
+    /// Runs the infinite loop that saves the YouTube API cache to the disk.
+    pub(crate) async fn run_cache_saver(self: Arc<Self>) {
+        self.cache.run_saver().await
+    }
+
+    pub(crate) fn api_keys(&self) -> &YtApiKeyPool {
+        &self.api_keys
+    }
+
+    /// Sends the request to the YouTube Data API trying all the keys that have quota left.
+    /// Returns [`ErrorKind::YtQuotaExhausted`] if none of them could be used.
+    async fn api_get<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        cost: u64,
+        query: &[(&str, &str)],
+    ) -> crate::Result<T> {
+        for (index, key) in self.api_keys.available_keys().await {
+            let result = self
+                .http_client
+                .get(util::url_with_segments(&self.api_url, &[endpoint]))
+                .query(query)
+                .query(&[("key", &key)])
+                .read_json()
+                .await;
+
+            match result.as_ref().err().and_then(QuotaError::from_error) {
+                Some(QuotaError::Exhausted) => {
+                    warn!(key_index = index, "YouTube Data API key has exhausted its quota");
+                    self.api_keys.mark_exhausted(index).await;
+                }
+                Some(QuotaError::RateLimited) => {
+                    warn!(key_index = index, "YouTube Data API key is rate limited");
+                }
+                None => {
+                    self.api_keys.record_usage(index, cost).await;
+                    return result;
+                }
+            }
+        }
+        Err(crate::err!(YtQuotaExhausted))
+    }
+
     async fn find_video_by_id(&self, id: &str) -> crate::Result<Option<YtVideo>> {
-        let res: rpc::videos::Response = self
-            .http_client
-            .get(yt_api(&["videos"]))
-            .query(&[
-                ("part", "snippet,contentDetails,liveStreamingDetails"),
-                ("id", id),
-                ("key", &self.yt_data_api_key),
-            ])
-            .read_json()
-            .await?;
+        Ok(self.find_videos_by_ids(&[id]).await?.into_iter().next())
+    }
+
+    /// Returns the videos that were found by the given ids (at most 50 ids per call)
+    /// in the same order as the ids. Only the videos that are not cached are requested.
+    /// https://developers.google.com/youtube/v3/docs/videos/list
+    pub(crate) async fn find_videos_by_ids(&self, ids: &[&str]) -> crate::Result<Vec<YtVideo>> {
+        let mut found = Vec::with_capacity(ids.len());
+        let mut missing = Vec::new();
+        for id in ids {
+            match self.cache.video(id).await {
+                Some(it) => found.push(it),
+                None => missing.push(*id),
+            }
+        }
+
+        if !missing.is_empty() {
+            let res: crate::Result<rpc::videos::Response> = self
+                .api_get(
+                    "videos",
+                    yt_quota::VIDEOS_COST,
+                    &[
+                        ("part", "snippet,contentDetails,liveStreamingDetails,status"),
+                        ("id", &missing.join(",")),
+                    ],
+                )
+                .await;
+
+            let fetched = match res {
+                Ok(res) => res.items.into_iter().map(YtVideo).collect(),
+                Err(err) if matches!(err.kind, ErrorKind::YtQuotaExhausted) => {
+                    Self::find_videos_by_ids_via_ytdl(&missing).await
+                }
+                Err(err) => return Err(err),
+            };
+
+            self.cache.insert_videos(&fetched).await;
+            found.extend(fetched);
+        }
 
-        Ok(res.items.into_iter().next().map(YtVideo))
+        found.sort_by_key(|video| ids.iter().position(|id| *id == video.id()));
+        Ok(found)
+    }
+
+    /// Fallback for [`YtService::find_videos_by_ids`] when the API quota is exhausted.
+    /// The videos that could not be found are skipped the same way the API does.
+    async fn find_videos_by_ids_via_ytdl(ids: &[&str]) -> Vec<YtVideo> {
+        let mut videos = Vec::with_capacity(ids.len());
+        for id in ids {
+            let mut url = yt(&["watch"]);
+            url.query_pairs_mut().append_pair("v", id);
+            match YtdlTrack::extract(&url).await {
+                Ok(it) => videos.push(YtVideo::from_ytdl_track(&it)),
+                Err(err) => warn!(?err, id, "Failed to find the video via youtube-dl"),
+            }
+        }
+        videos
+    }
+
+    /// Returns the ids of the videos found by the given query (the most relevant first).
+    /// https://developers.google.com/youtube/v3/docs/search/list
+    async fn search_video_ids(&self, query: &str, max_results: u8) -> crate::Result<Vec<String>> {
+        if let Some(ids) = self.cache.query(query, max_results).await {
+            return Ok(ids);
+        }
+
+        let res: crate::Result<rpc::search::Response> = self
+            .api_get(
+                "search",
+                yt_quota::SEARCH_COST,
+                &[
+                    ("maxResults", max_results.to_string().as_str()),
+                    ("type", "video"),
+                    ("q", query),
+                ],
+            )
+            .await;
+
+        let ids: Vec<_> = match res {
+            Ok(res) => res.items.into_iter().map(|it| it.id.video_id).collect(),
+            Err(err) if matches!(err.kind, ErrorKind::YtQuotaExhausted) => {
+                // youtube-dl gives us the full metadata of the videos right away,
+                // so we put it into the cache to avoid looking them up once again
+                let videos: Vec<_> = YtdlTrack::search_youtube(query, max_results)
+                    .await?
+                    .iter()
+                    .map(YtVideo::from_ytdl_track)
+                    .collect();
+                self.cache.insert_videos(&videos).await;
+                videos.iter().map(|it| it.id().to_owned()).collect()
+            }
+            Err(err) => return Err(err),
+        };
+
+        self.cache.insert_query(query, max_results, ids.clone()).await;
+
+        Ok(ids)
+    }
+
+    /// Same as [`YtService::find_video_by_query`], but returns up to `max_results` videos
+    /// so that the caller may pick the most appropriate one.
+    pub(crate) async fn find_videos_by_query(
+        &self,
+        query: &str,
+        max_results: u8,
+    ) -> crate::Result<Vec<YtVideo>> {
+        let ids = self.search_video_ids(query, max_results).await?;
+        let ids: Vec<_> = ids.iter().map(String::as_str).collect();
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        self.find_videos_by_ids(&ids).await
+    }
+
+    /// Returns `true` if the url points to a YouTube video.
+    pub(crate) fn is_video_url(url: &Url) -> bool {
+        Self::video_id_from_url(url).is_ok()
     }
 
     pub(crate) async fn find_video_by_url(&self, url: &Url) -> crate::Result<YtVideo> {
@@ -195,34 +663,89 @@ impl YtService {
     }
 
     /// Tries to find yotube video by given `query` string.
-    /// Returns `None` or first found youtube video id.b
-    /// Search query to find video for.
+    /// Returns the first found video that the bot is able to play, or an error
+    /// explaining why the first one is not playable if none of them are.
     /// See: https://developers.google.com/youtube/v3/docs/search/list?apix_params=%7B%22part%22%3A%22snippet%22%2C%22relatedToVideoId%22%3A%22Ks-_Mh1QhMc%22%2C%22type%22%3A%22video%22%7D#usage
     pub(crate) async fn find_video_by_query(&self, query: &str) -> crate::Result<YtVideo> {
-        // First perform a search with the given human query string
-        let res: rpc::search::Response = self
-            .http_client
-            .get(yt_api(&["search"]))
-            .query(&[
-                ("maxResults", "1"),
-                ("type", "video"),
-                ("q", query),
-                ("key", &self.yt_data_api_key),
-            ])
-            .read_json()
+        // The search costs the same quota regardless of the number of results,
+        // and the videos themselves are requested in a single batch
+        let videos = self
+            .find_videos_by_query(query, PLAYABLE_SEARCH_RESULTS)
             .await?;
 
-        // Take the returned video metadata (if it was found)
-        let video = res
-            .items
-            .into_iter()
-            .next()
+        let first = videos
+            .first()
             .ok_or_else(|| crate::err!(YtVidNotFound(query.to_owned())))?;
 
-        // Do one more query (this one allows us to get extended info)
-        self.find_video_by_id(&video.id.video_id)
-            .await?
-            .ok_or_else(|| crate::err!(YtVidNotFound(query.to_owned())))
+        let playable = videos
+            .iter()
+            .find(|it| self.unplayable_reason(it).is_none());
+        match playable {
+            Some(video) => Ok(video.clone()),
+            None => self.check_playable(first).map(|()| first.clone()),
+        }
+    }
+
+    /// Returns the id of the channel the given url points to. Supports the urls
+    /// with the channel id itself, as well as custom urls and handles (`/c/name`, `/@name`).
+    pub(crate) async fn channel_id_from_url(&self, url: &Url) -> crate::Result<String> {
+        let is_yt_domain = matches!(
+            url.host_str(),
+            Some("youtube.com") | Some("www.youtube.com") | Some("m.youtube.com")
+        );
+        if !is_yt_domain {
+            return Err(crate::err!(YtInferChannelId(url.clone())));
+        }
+
+        let mut segments = url.path_segments().into_iter().flatten();
+        if let (Some("channel"), Some(id)) = (segments.next(), segments.next()) {
+            if is_channel_id(id) {
+                return Ok(id.to_owned());
+            }
+        }
+
+        // Custom urls and handles can't be resolved to ids via the API without
+        // spending the quota, but the channel page has its canonical url with the id.
+        // The path is kept as is (percent-encoded), only the site is replaced.
+        let mut page_url = self.site_url.clone();
+        page_url.set_path(&format!(
+            "{}{}",
+            self.site_url.path().trim_end_matches('/'),
+            url.path()
+        ));
+        let html = self.http_client.get(page_url).read_bytes().await?;
+        let canonical_url_regex = regex!(
+            r#"<link rel="canonical" href="https://www\.youtube\.com/channel/(UC[a-zA-Z0-9_-]{22})">"#
+        );
+
+        canonical_url_regex
+            .captures(&String::from_utf8_lossy(&html))
+            .map(|it| it[1].to_owned())
+            .ok_or_else(|| crate::err!(YtInferChannelId(url.clone())))
+    }
+
+    /// Returns the latest uploads of the channel from its public RSS feed.
+    /// It doesn't spend the API quota, so it is suitable for frequent polling.
+    pub(crate) async fn channel_feed(&self, channel_id: &str) -> crate::Result<YtChannelFeed> {
+        let mut url = util::url_with_segments(&self.site_url, &["feeds", "videos.xml"]);
+        url.query_pairs_mut().append_pair("channel_id", channel_id);
+
+        let xml = self.http_client.get(url).read_bytes().await?;
+        let xml = String::from_utf8_lossy(&xml);
+
+        // The feed is simple enough to not pull the whole XML parser for it.
+        // The first `<title>` is the title of the channel, the rest are the titles of the videos.
+        let title = regex!(r#"<title>([^<]*)</title>"#)
+            .captures(&xml)
+            .map(|it| unescape_xml(&it[1]))
+            .unwrap_or_default();
+
+        let video_ids = regex!(r#"<yt:videoId>([a-zA-Z0-9_-]{11})</yt:videoId>"#)
+            .captures_iter(&xml)
+            .map(|it| it[1].to_owned())
+            .collect();
+
+        Ok(YtChannelFeed { title, video_ids })
     }
 
     /// Ported code from JavaScript `ytdl-core` library:
@@ -0,0 +1,222 @@
+//! Cache of the YouTube Data API responses.
+//!
+//! The API has a pretty tight daily quota (a `search` request alone costs 100 units
+//! out of 10 000), and people tend to order the same tracks over and over again.
+
+use crate::{store::JsonStore, yt::YtVideo};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time,
+};
+use tracing::warn;
+
+/// Video metadata (title, thumbnail, etc.) rarely changes
+const VIDEO_TTL_HOURS: i64 = 12;
+
+/// Livestreams may end or start at any moment, which changes their metadata
+const LIVE_VIDEO_TTL_MINUTES: i64 = 5;
+
+/// Search results do change with time, but not that fast
+const QUERY_TTL_HOURS: i64 = 24;
+
+/// The cache is updated on almost every request, so instead of rewriting the whole
+/// file each time we save the changes periodically. Losing the last minute of the
+/// cache on restart only costs a few extra API requests.
+const SAVE_INTERVAL: time::Duration = time::Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry<T> {
+    value: T,
+    expires_at: DateTime<Utc>,
+}
+
+impl<T> CacheEntry<T> {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct YtCacheData {
+    /// Video id -> video metadata
+    videos: HashMap<String, CacheEntry<YtVideo>>,
+    /// Normalized search query -> ids of the found videos
+    queries: HashMap<String, CacheEntry<Vec<String>>>,
+}
+
+impl YtCacheData {
+    fn remove_expired(&mut self, now: DateTime<Utc>) {
+        self.videos.retain(|_, it| !it.is_expired(now));
+        self.queries.retain(|_, it| !it.is_expired(now));
+    }
+}
+
+pub(crate) struct YtCacheStats {
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) videos: usize,
+    pub(crate) queries: usize,
+    pub(crate) is_persistent: bool,
+}
+
+pub(crate) struct YtCache {
+    data: JsonStore<YtCacheData>,
+    is_persistent: bool,
+    /// Whether there are changes that were not saved to the disk yet
+    has_unsaved_changes: AtomicBool,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl YtCache {
+    /// Creates the cache that is saved to the given file or is kept only in memory if `None`.
+    pub(crate) async fn open(path: Option<PathBuf>) -> crate::Result<Self> {
+        let is_persistent = path.is_some();
+        let data = match path {
+            Some(path) => JsonStore::open(path).await?,
+            None => JsonStore::in_memory(),
+        };
+        Ok(Self {
+            data,
+            is_persistent,
+            has_unsaved_changes: AtomicBool::new(false),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    pub(crate) async fn video(&self, id: &str) -> Option<YtVideo> {
+        let now = Utc::now();
+        let video = self
+            .data
+            .read(|it| {
+                it.videos
+                    .get(id)
+                    .filter(|it| !it.is_expired(now))
+                    .map(|it| it.value.clone())
+            })
+            .await;
+        self.record_lookup(video.is_some());
+        video
+    }
+
+    pub(crate) async fn insert_videos(&self, videos: &[YtVideo]) {
+        let now = Utc::now();
+        self.update(|data| {
+            for video in videos {
+                let ttl = if video.is_livestream() || video.is_upcoming() {
+                    chrono::Duration::minutes(LIVE_VIDEO_TTL_MINUTES)
+                } else {
+                    chrono::Duration::hours(VIDEO_TTL_HOURS)
+                };
+                data.videos.insert(
+                    video.id().to_owned(),
+                    CacheEntry {
+                        value: video.clone(),
+                        expires_at: now + ttl,
+                    },
+                );
+            }
+        })
+        .await
+    }
+
+    /// Returns the ids of the videos found by the given search query previously.
+    pub(crate) async fn query(&self, query: &str, max_results: u8) -> Option<Vec<String>> {
+        let key = Self::query_key(query, max_results);
+        let now = Utc::now();
+        let ids = self
+            .data
+            .read(|it| {
+                it.queries
+                    .get(&key)
+                    .filter(|it| !it.is_expired(now))
+                    .map(|it| it.value.clone())
+            })
+            .await;
+        self.record_lookup(ids.is_some());
+        ids
+    }
+
+    pub(crate) async fn insert_query(&self, query: &str, max_results: u8, ids: Vec<String>) {
+        let key = Self::query_key(query, max_results);
+        let expires_at = Utc::now() + chrono::Duration::hours(QUERY_TTL_HOURS);
+        self.update(|data| {
+            data.queries.insert(
+                key,
+                CacheEntry {
+                    value: ids,
+                    expires_at,
+                },
+            );
+        })
+        .await
+    }
+
+    /// Runs the infinite loop that writes the changes of the persistent cache to the disk.
+    pub(crate) async fn run_saver(&self) {
+        if !self.is_persistent {
+            return;
+        }
+        let mut interval = tokio::time::interval(SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if !self.has_unsaved_changes.swap(false, Ordering::Relaxed) {
+                continue;
+            }
+            if let Err(err) = self.data.save().await {
+                warn!(?err, "Failed to save the YouTube API cache");
+                self.has_unsaved_changes.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Removes all the cached responses and resets the metrics.
+    pub(crate) async fn clear(&self) -> crate::Result<()> {
+        self.data.update(|it| *it = YtCacheData::default()).await?;
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub(crate) async fn stats(&self) -> YtCacheStats {
+        let (videos, queries) = self
+            .data
+            .read(|it| (it.videos.len(), it.queries.len()))
+            .await;
+        YtCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            videos,
+            queries,
+            is_persistent: self.is_persistent,
+        }
+    }
+
+    /// The changes are written to the disk later by [`YtCache::run_saver`]
+    async fn update(&self, f: impl FnOnce(&mut YtCacheData)) {
+        let now = Utc::now();
+        self.data
+            .update_in_memory(|data| {
+                // Cleanup on every write, so that the cache doesn't grow indefinitely
+                data.remove_expired(now);
+                f(data);
+            })
+            .await;
+        self.has_unsaved_changes.store(true, Ordering::Relaxed);
+    }
+
+    fn record_lookup(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn query_key(query: &str, max_results: u8) -> String {
+        let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+        format!("{}:{}", max_results, query.to_lowercase())
+    }
+}
@@ -0,0 +1,121 @@
+//! Accounting of the YouTube Data API quota usage across several API keys.
+//!
+//! Each key has a daily quota (10 000 units by default), so when one key gets
+//! exhausted we switch to the next one until the quota is reset.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serenity::prelude::Mutex;
+
+/// Quota costs of the API methods we use.
+/// https://developers.google.com/youtube/v3/determine_quota_cost
+pub(crate) const SEARCH_COST: u64 = 100;
+pub(crate) const VIDEOS_COST: u64 = 1;
+
+/// The quota is reset at midnight Pacific Time. We don't want to depend on the time zones
+/// database just for this, so we ignore daylight saving time, which means the key may
+/// be considered exhausted for an hour longer than it actually is in summer.
+const PACIFIC_TIME_OFFSET_HOURS: i64 = 8;
+
+fn quota_day(now: DateTime<Utc>) -> NaiveDate {
+    (now - chrono::Duration::hours(PACIFIC_TIME_OFFSET_HOURS))
+        .date()
+        .naive_utc()
+}
+
+struct KeyUsage {
+    key: String,
+    /// The day the usage stats below are recorded for
+    day: NaiveDate,
+    used_units: u64,
+    exhausted: bool,
+}
+
+impl KeyUsage {
+    /// Resets the stats if the quota was reset since the last use of the key.
+    fn refresh(&mut self, today: NaiveDate) {
+        if self.day != today {
+            self.day = today;
+            self.used_units = 0;
+            self.exhausted = false;
+        }
+    }
+}
+
+pub(crate) struct YtApiKeyStats {
+    /// Only the last few characters of the key, so that it's safe to show it in the chat
+    pub(crate) masked_key: String,
+    pub(crate) used_units: u64,
+    pub(crate) exhausted: bool,
+}
+
+pub(crate) struct YtApiKeyPool {
+    keys: Mutex<Vec<KeyUsage>>,
+}
+
+impl YtApiKeyPool {
+    pub(crate) fn new(keys: impl IntoIterator<Item = String>) -> Self {
+        let today = quota_day(Utc::now());
+        Self {
+            keys: Mutex::new(
+                keys.into_iter()
+                    .map(|key| KeyUsage {
+                        key,
+                        day: today,
+                        used_units: 0,
+                        exhausted: false,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Returns the indices and values of the keys that still have quota left today
+    /// in the order they should be tried.
+    pub(crate) async fn available_keys(&self) -> Vec<(usize, String)> {
+        let today = quota_day(Utc::now());
+        let mut keys = self.keys.lock().await;
+        keys.iter_mut()
+            .enumerate()
+            .filter_map(|(i, usage)| {
+                usage.refresh(today);
+                if usage.exhausted {
+                    None
+                } else {
+                    Some((i, usage.key.clone()))
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) async fn record_usage(&self, index: usize, units: u64) {
+        let today = quota_day(Utc::now());
+        let mut keys = self.keys.lock().await;
+        let usage = &mut keys[index];
+        usage.refresh(today);
+        usage.used_units += units;
+    }
+
+    pub(crate) async fn mark_exhausted(&self, index: usize) {
+        let today = quota_day(Utc::now());
+        let mut keys = self.keys.lock().await;
+        let usage = &mut keys[index];
+        usage.refresh(today);
+        usage.exhausted = true;
+    }
+
+    pub(crate) async fn stats(&self) -> Vec<YtApiKeyStats> {
+        let today = quota_day(Utc::now());
+        let mut keys = self.keys.lock().await;
+        keys.iter_mut()
+            .map(|usage| {
+                usage.refresh(today);
+                let suffix_start = usage.key.len().saturating_sub(4);
+                YtApiKeyStats {
+                    masked_key: format!("…{}", &usage.key[suffix_start..]),
+                    used_units: usage.used_units,
+                    exhausted: usage.exhausted,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,248 @@
+//! Notifications about the new uploads on the subscribed YouTube channels.
+//!
+//! Channels are polled via their public RSS feeds (which don't spend the API quota),
+//! and only the new videos are looked up via the YouTube Data API to show them nicely.
+
+use crate::{
+    store::JsonStore,
+    yt::{self, YtService, YtVideo},
+};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, GuildId},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time,
+};
+use tracing::{info, warn};
+
+/// How often the channels are checked for the new uploads
+pub(crate) const POLL_INTERVAL: time::Duration = time::Duration::from_secs(10 * 60);
+
+/// The feed contains only 15 latest videos, so there is no need to remember more than that
+/// (with some reserve for the videos that were removed from the feed)
+const MAX_SEEN_VIDEOS: usize = 50;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct YtSubscription {
+    pub(crate) channel_id: String,
+    pub(crate) channel_title: String,
+    /// Channel where the new uploads are announced
+    pub(crate) text_channel: ChannelId,
+    /// Ids of the videos that were already announced (or were there before the subscription),
+    /// the newest first
+    seen_video_ids: Vec<String>,
+}
+
+pub(crate) struct YtSubscriptionService {
+    subscriptions: JsonStore<HashMap<GuildId, Vec<YtSubscription>>>,
+    yt: Arc<YtService>,
+    http: Arc<Http>,
+}
+
+impl YtSubscriptionService {
+    pub(crate) fn new(
+        subscriptions: JsonStore<HashMap<GuildId, Vec<YtSubscription>>>,
+        yt: Arc<YtService>,
+        http: Arc<Http>,
+    ) -> Self {
+        Self {
+            subscriptions,
+            yt,
+            http,
+        }
+    }
+
+    pub(crate) async fn subscriptions(&self, guild_id: GuildId) -> Vec<YtSubscription> {
+        self.subscriptions
+            .read(|it| it.get(&guild_id).cloned().unwrap_or_default())
+            .await
+    }
+
+    /// Subscribes the guild to the channel. The videos that are already uploaded
+    /// are not announced, only the ones uploaded after the subscription.
+    pub(crate) async fn subscribe(
+        &self,
+        guild_id: GuildId,
+        channel_id: String,
+        text_channel: ChannelId,
+    ) -> crate::Result<YtSubscription> {
+        let feed = self.yt.channel_feed(&channel_id).await?;
+
+        let subscription = YtSubscription {
+            channel_id,
+            channel_title: feed.title,
+            text_channel,
+            seen_video_ids: feed.video_ids,
+        };
+
+        self.subscriptions
+            .update(|it| {
+                let subscriptions = it.entry(guild_id).or_default();
+                if subscriptions
+                    .iter()
+                    .any(|it| it.channel_id == subscription.channel_id)
+                {
+                    return Err(crate::err!(YtAlreadySubscribed(
+                        subscription.channel_title.clone()
+                    )));
+                }
+                subscriptions.push(subscription.clone());
+                Ok(())
+            })
+            .await??;
+
+        Ok(subscription)
+    }
+
+    pub(crate) async fn unsubscribe(
+        &self,
+        guild_id: GuildId,
+        channel_id: &str,
+    ) -> crate::Result<YtSubscription> {
+        self.subscriptions
+            .update(|it| {
+                let not_found = || crate::err!(YtSubscriptionNotFound(channel_id.to_owned()));
+                let subscriptions = it.get_mut(&guild_id).ok_or_else(not_found)?;
+                let index = subscriptions
+                    .iter()
+                    .position(|it| it.channel_id == channel_id)
+                    .ok_or_else(not_found)?;
+                Ok(subscriptions.remove(index))
+            })
+            .await?
+    }
+
+    /// Runs the infinite loop that checks the subscribed channels for the new uploads.
+    pub(crate) async fn run_poller(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.poll().await;
+        }
+    }
+
+    async fn poll(&self) {
+        let channel_ids: HashSet<String> = self
+            .subscriptions
+            .read(|it| {
+                it.values()
+                    .flatten()
+                    .map(|it| it.channel_id.clone())
+                    .collect()
+            })
+            .await;
+
+        for channel_id in channel_ids {
+            let feed = match self.yt.channel_feed(&channel_id).await {
+                Ok(it) => it,
+                Err(err) => {
+                    warn!(?err, %channel_id, "Failed to fetch the YouTube channel feed");
+                    continue;
+                }
+            };
+            if let Err(err) = self.announce_new_videos(&channel_id, feed.video_ids).await {
+                warn!(?err, %channel_id, "Failed to announce the new YouTube uploads");
+            }
+        }
+    }
+
+    async fn announce_new_videos(
+        &self,
+        channel_id: &str,
+        feed_video_ids: Vec<String>,
+    ) -> crate::Result<()> {
+        let announcements: Vec<(ChannelId, Vec<String>)> = self
+            .subscriptions
+            .read(|it| {
+                it.values()
+                    .flatten()
+                    .filter(|it| it.channel_id == channel_id)
+                    .filter_map(|subscription| {
+                        let new_ids: Vec<_> = feed_video_ids
+                            .iter()
+                            .filter(|id| !subscription.seen_video_ids.contains(id))
+                            .cloned()
+                            .collect();
+
+                        if new_ids.is_empty() {
+                            return None;
+                        }
+                        Some((subscription.text_channel, new_ids))
+                    })
+                    .collect()
+            })
+            .await;
+
+        for (text_channel, new_ids) in announcements {
+            info!(%channel_id, ?new_ids, "Announcing new YouTube uploads");
+
+            // If the lookup fails, the videos stay unseen and are retried on the next poll
+            let ids: Vec<_> = new_ids.iter().map(String::as_str).collect();
+            let videos = self.yt.find_videos_by_ids(&ids).await?;
+
+            // The API may not return some of the videos yet (e.g. the ones that are
+            // still processing), so they stay unseen and are retried on the next poll
+            let found_ids: Vec<_> = new_ids
+                .into_iter()
+                .filter(|id| videos.iter().any(|video| video.id() == id))
+                .collect();
+
+            if found_ids.is_empty() {
+                continue;
+            }
+
+            // Remember the videos as seen before sending, so that they are not
+            // announced twice even if sending the announcement fails
+            self.subscriptions
+                .update(|it| {
+                    let subscription = it
+                        .values_mut()
+                        .flatten()
+                        .find(|it| it.channel_id == channel_id && it.text_channel == text_channel);
+
+                    if let Some(subscription) = subscription {
+                        let seen = &mut subscription.seen_video_ids;
+                        seen.retain(|id| !found_ids.contains(id));
+                        seen.splice(0..0, found_ids.iter().cloned());
+                        seen.truncate(MAX_SEEN_VIDEOS);
+                    }
+                })
+                .await?;
+
+            // The feed lists the newest videos first, but we announce them in the upload order
+            for video in videos.iter().rev() {
+                if let Err(err) = self.announce_video(text_channel, video).await {
+                    warn!(?err, %text_channel, "Failed to announce the new YouTube upload");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn announce_video(&self, text_channel: ChannelId, video: &YtVideo) -> crate::Result<()> {
+        text_channel
+            .send_message(&self.http, |it| {
+                it.embed(|it| {
+                    it.title(video.title())
+                        .url(video.url())
+                        .author(|it| it.name(video.channel_title()).url(video.channel_url()))
+                        .description("New video was uploaded!")
+                        .image(video.thumbnail_url())
+                        .footer(|it| it.text(format_args!("Duration: {}", video.format_duration())))
+                })
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+impl YtSubscription {
+    pub(crate) fn channel_url(&self) -> url::Url {
+        yt::channel_url(&self.channel_id)
+    }
+}
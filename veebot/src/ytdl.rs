@@ -0,0 +1,232 @@
+//! Symbols related to playing tracks from the arbitrary sites supported by `youtube-dl`
+//! (SoundCloud, Bandcamp, Twitch and many others).
+//! YouTube videos are handled by [`crate::yt`], because the YouTube Data API is much faster.
+
+use crate::{
+    chapters::{self, Chapter},
+    util::format_duration,
+};
+use serde::Deserialize;
+use std::{fmt, net::Ipv4Addr, process::Output, time};
+use url::{Host, Url};
+
+/// `youtube-dl` is killed if it takes longer than this (e.g. the site hangs)
+const YTDL_TIMEOUT: time::Duration = time::Duration::from_secs(60);
+
+/// Subset of the `youtube-dl --dump-json` output we are interested in
+#[derive(Clone, Deserialize)]
+struct Info {
+    id: String,
+    /// Name of the extractor that was used (e.g. `Soundcloud`, `Bandcamp`)
+    extractor_key: String,
+    title: String,
+    description: Option<String>,
+    webpage_url: Url,
+    uploader: Option<String>,
+    uploader_url: Option<Url>,
+    /// YouTube-specific, the uploader is the channel in this case
+    channel_id: Option<String>,
+    thumbnail: Option<Url>,
+    /// Duration in seconds, may be absent for livestreams
+    duration: Option<f64>,
+    is_live: Option<bool>,
+}
+
+#[derive(Clone)]
+pub(crate) struct YtdlTrack(Info);
+
+impl YtdlTrack {
+    /// Extracts the metadata of the track from the given webpage via `youtube-dl`.
+    /// Only public http(s) urls are accepted, so that the users can't make the bot
+    /// read the local files or probe the services in its private network.
+    pub(crate) async fn extract(url: &Url) -> crate::Result<Self> {
+        if !is_public_web_url(url) {
+            return Err(crate::err!(YtdlExtract(
+                url.to_string(),
+                "Only public http(s) urls are supported".to_owned()
+            )));
+        }
+
+        dump_json(url.as_str())
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| crate::err!(YtdlExtract(url.to_string(), "No tracks found".to_owned())))
+    }
+
+    /// Searches YouTube via `youtube-dl` (doesn't spend the YouTube Data API quota,
+    /// but is much slower than the API).
+    pub(crate) async fn search_youtube(query: &str, max_results: u8) -> crate::Result<Vec<Self>> {
+        dump_json(&format!("ytsearch{}:{}", max_results, query)).await
+    }
+
+    /// Returns the id of the track on the site it comes from
+    pub(crate) fn site_id(&self) -> &str {
+        &self.0.id
+    }
+
+    pub(crate) fn channel_id(&self) -> Option<&str> {
+        self.0.channel_id.as_deref()
+    }
+
+    pub(crate) fn title(&self) -> &str {
+        &self.0.title
+    }
+
+    pub(crate) fn url(&self) -> &Url {
+        &self.0.webpage_url
+    }
+
+    pub(crate) fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    /// Returns the chapters listed in the description of the track (empty if there are none).
+    pub(crate) fn chapters(&self) -> Vec<Chapter> {
+        chapters::parse_chapters(self.description().unwrap_or_default(), self.duration())
+    }
+
+    pub(crate) fn thumbnail_url(&self) -> Option<&Url> {
+        self.0.thumbnail.as_ref()
+    }
+
+    /// Returns the name of the site this track comes from (e.g. `Soundcloud`)
+    pub(crate) fn site(&self) -> &str {
+        &self.0.extractor_key
+    }
+
+    /// Returns a string that uniquely identifies the track across all sites.
+    pub(crate) fn id(&self) -> String {
+        format!("{}:{}", self.0.extractor_key, self.0.id)
+    }
+
+    pub(crate) fn uploader(&self) -> Option<&str> {
+        self.0.uploader.as_deref()
+    }
+
+    pub(crate) fn uploader_url(&self) -> Option<&Url> {
+        self.0.uploader_url.as_ref()
+    }
+
+    pub(crate) fn is_livestream(&self) -> bool {
+        self.0.is_live == Some(true)
+    }
+
+    /// Returns `None` for the livestreams or if the site doesn't report the duration.
+    pub(crate) fn duration(&self) -> Option<time::Duration> {
+        if self.is_livestream() {
+            return None;
+        }
+        self.0.duration.map(time::Duration::from_secs_f64)
+    }
+
+    pub(crate) fn format_duration(&self) -> impl fmt::Display {
+        match self.duration() {
+            Some(duration) => format_duration(&duration),
+            None if self.is_livestream() => "🔴 LIVE".to_owned(),
+            None => "unknown".to_owned(),
+        }
+    }
+}
+
+/// Returns the metadata of the tracks from the given url or `ytsearch:` query.
+/// `youtube-dl` outputs one JSON object per line for each track.
+/// Runs `youtube-dl` with the given arguments, returns the error message if it fails to start
+/// or doesn't finish in time.
+async fn run_ytdl(args: &[&str], target: &str) -> Result<Output, String> {
+    let output = tokio::process::Command::new("youtube-dl")
+        .args(args)
+        .arg(target)
+        .kill_on_drop(true)
+        .output();
+
+    match tokio::time::timeout(YTDL_TIMEOUT, output).await {
+        Ok(output) => output.map_err(|err| err.to_string()),
+        Err(_) => Err(format!(
+            "youtube-dl didn't finish in {} seconds",
+            YTDL_TIMEOUT.as_secs()
+        )),
+    }
+}
+
+/// Returns `false` for the non-web schemes (e.g. `file://`) and the hosts that
+/// point to the machine the bot runs on or its private network.
+/// The domains that resolve to such addresses are not detected, but these are
+/// not as easy to come by as `localhost` or `192.168.0.1`.
+pub(crate) fn is_public_web_url(url: &Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+
+    match url.host() {
+        None => false,
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost") && !domain.ends_with(".local")
+        }
+        Some(Host::Ipv4(ip)) => is_public_ipv4(ip),
+        Some(Host::Ipv6(ip)) => {
+            if ip.is_loopback() || ip.is_unspecified() {
+                return false;
+            }
+            // Unique local (`fc00::/7`) and link-local (`fe80::/10`) addresses
+            let first = ip.segments()[0];
+            if (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80 {
+                return false;
+            }
+            // IPv4-mapped addresses (e.g. `::ffff:127.0.0.1`)
+            ip.to_ipv4().map_or(true, is_public_ipv4)
+        }
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast())
+}
+
+async fn dump_json(target: &str) -> crate::Result<Vec<YtdlTrack>> {
+    let err = |message: String| crate::err!(YtdlExtract(target.to_owned(), message));
+
+    let output = run_ytdl(&["--dump-json", "--no-playlist", "--skip-download"], target)
+        .await
+        .map_err(err)?;
+
+    if !output.status.success() {
+        return Err(err(String::from_utf8_lossy(&output.stderr).into_owned()));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|it| !it.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map(YtdlTrack)
+                .map_err(|it| err(it.to_string()))
+        })
+        .collect()
+}
+
+/// Returns the url of the raw audio stream of the track from the given webpage
+/// (works with YouTube too). Such urls are short-lived, so they should be used right away.
+pub(crate) async fn direct_audio_url(url: &Url) -> crate::Result<String> {
+    let output = run_ytdl(
+        &["--get-url", "--no-playlist", "--format", "bestaudio/best"],
+        url.as_str(),
+    )
+    .await
+    .map_err(|err| crate::err!(YtdlExtract(url.to_string(), err)))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    match stdout.lines().next() {
+        Some(it) if output.status.success() => Ok(it.to_owned()),
+        _ => {
+            let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+            Err(crate::err!(YtdlExtract(url.to_string(), stderr)))
+        }
+    }
+}